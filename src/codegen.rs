@@ -1,273 +1,1018 @@
-use crate::node::{BinaryType, Node};
-use crate::register::Register;
-use crate::typename::{sizeof, Typename};
+use std::collections::{BTreeSet, HashSet};
+
+use crate::frame::{Frame, TEARDOWN_MARK};
+use crate::ir::liveness::{inst_uses, temps};
+use crate::ir::{
+    BinaryOp, Block, BlockId, CompareOp, ConvertOp, Function, Global, Inst, Module, Operand, Temp,
+    Terminator, Type,
+};
+use crate::peephole::VOLATILE_MARK;
+use crate::regalloc::{self, Location};
+use crate::register::{is_reserved_name, Register};
+
+// ブロックの最後の整数の比較を、その結果を使う唯一の分岐とまとめられるか
+fn is_fused_compare(function: &Function, block: &Block) -> bool {
+    let dst = match (block.insts.last(), &block.terminator) {
+        (
+            Some(Inst::Compare(dst, _, typename, _, _)),
+            Terminator::Branch(Operand::Temp(cond), _, _, _),
+        ) if dst == cond && !typename.is_float() => *dst,
+        _ => return false,
+    };
+    let uses = function
+        .blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .insts
+                .iter()
+                .flat_map(inst_uses)
+                .chain(temps(block.terminator.operands()))
+        })
+        .filter(|temp| *temp == dst)
+        .count();
+    uses == 1
+}
 
 pub struct CodeGenerator {
     pub lines: Vec<String>,
     pub label_count: usize,
-    pub rsp_sub_size: usize,
-    pub label_func: usize,
+    // 関数の本体に入ってからpushしたバイト数
+    pub stack_depth: usize,
+    // call直前にrspが16byte境界にあるかを実行時に検査する
+    pub stack_check: bool,
+    pub va_frame: Option<VaFrame>,
+    // フレームポインタ (rbp) を使わずrspからの位置で変数を参照する
+    pub omit_frame_pointer: bool,
+    pub frame: Frame,
+    // 一時変数の置き場所 (レジスタか、ローカル変数の下のスタック)
+    pub locations: Vec<Option<Location>>,
+    // falseなら一時変数を全てスタックに置く (-O0)
+    pub allocate_registers: bool,
+    // 分岐でしか使わない比較の結果を値にせず、フラグで直接分岐する (-fno-peepholeで無効)
+    pub fuse_compare_branch: bool,
+    pub function_name: String,
+    // staticな関数と変数の名前
+    pub static_symbols: HashSet<String>,
+    // 別名 (.Lsym.name) で参照した外部から見える名前
+    pub aliases: BTreeSet<String>,
+}
+
+// 可変長引数関数のレジスタ退避領域などの情報
+pub struct VaFrame {
+    pub gp_offset: usize,       // 名前付き引数が使った整数レジスタのバイト数
+    pub fp_offset: usize,       // 48 + 名前付き引数が使ったSSEレジスタのバイト数
+    pub overflow_offset: usize, // base + 16 から名前付きのスタック渡し引数を除いた位置
+    pub reg_save_offset: usize, // レジスタ退避領域 (176byte) のオフセット
+}
+
+// 並行コピーの転送元
+enum Source {
+    Register(Register),
+    Operand(Operand),
 }
 
-fn stack_align(size: usize) -> usize {
-    if size % 16 == 0 {
-        size
+pub fn gen_ptr(address: String, size: usize) -> String {
+    format!(
+        "{} PTR {}",
+        match size {
+            1 => "BYTE",
+            2 => "WORD",
+            4 => "DWORD",
+            _ => "QWORD",
+        },
+        address
+    )
+}
+
+// 浮動小数点数のビット列 (4byteならfloat)
+fn float_bits(x: f64, typename: &Type) -> u64 {
+    if typename.size() == 4 {
+        (x as f32).to_bits() as u64
     } else {
-        16 * ((size / 16) + 1) - 8
+        x.to_bits()
     }
 }
 
-pub fn gen_lval_ptr(offset: usize, typename: &Typename) -> String {
-    match typename {
-        Typename::Integer(_, size) => format!(
-            "{} PTR [rbp-{:#0x}]",
-            match size {
-                1 => "BYTE",
-                2 => "WORD",
-                4 => "DWORD",
-                8 => "QWORD",
-                _ => "",
-            },
-            8 + offset
-        ),
-        _ => String::new(),
+// s: single, d: double
+fn float_suffix(typename: &Type) -> &'static str {
+    if let Type::F32 = typename {
+        "s"
+    } else {
+        "d"
     }
 }
 
 impl CodeGenerator {
-    // -> bool : require pop stack
-    pub fn gen(&mut self, node: &Node) -> bool {
-        match node {
-            Node::Function(name, _return_type, arg_types, block, local_var_size) => {
-                self.lines.push(format!("{}:", name));
-                self.lines.push(format!("    endbr64"));
-                self.lines.push(format!("    push rbp"));
-                self.lines.push(format!("    mov rbp, rsp"));
-                self.lines.push(format!("    push rdx"));
-                // rbpをpushするぶんを合わせる
-                self.rsp_sub_size = stack_align(*local_var_size);
-                self.lines
-                    .push(format!("    sub rsp, {:#0x}", self.rsp_sub_size));
-                // load arguments
-                let mut offset: usize = 0;
-                for (order, arg_type) in arg_types.iter().enumerate() {
-                    if order < 6 {
-                        let register_name = self
-                            .gen_function_arg_register(order)
-                            .get_name(sizeof(&arg_type));
-                        self.lines.push(format!(
-                            "    mov {0}, {1}",
-                            gen_lval_ptr(offset, &arg_type),
-                            register_name
-                        ));
+    pub fn gen(&mut self, module: &Module) {
+        self.static_symbols = module
+            .globals
+            .iter()
+            .filter(|global| global.is_static)
+            .map(|global| global.label.clone())
+            .chain(
+                module
+                    .functions
+                    .iter()
+                    .filter(|function| function.is_static)
+                    .map(|function| function.name.clone()),
+            )
+            .collect();
+        for global in module.globals.iter() {
+            self.gen_global(global);
+        }
+        for function in module.functions.iter() {
+            self.gen_function(function);
+        }
+        self.gen_string_literals(&module.strings);
+        self.gen_aliases();
+    }
+
+    // オペランドに書くシンボル
+    // アセンブラがレジスタなどと読む名前 (gs, r8, modなど) は .Lsym.name で参照する
+    // staticならその名前で定義し、そうでなければ最後に元の名前の別名として定義する
+    fn symbol(&mut self, name: &str) -> String {
+        if !is_reserved_name(name) {
+            return name.to_string();
+        }
+        if !self.static_symbols.contains(name) {
+            self.aliases.insert(name.to_string());
+        }
+        format!(".Lsym.{}", name)
+    }
+
+    // 定義するラベル (staticでなければ元の名前のまま)
+    fn definition_label(&mut self, name: &str, is_static: bool) -> String {
+        if is_static {
+            self.symbol(name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    // AT&T記法では%のない名前はレジスタと読まれないので、そこで別名を定義する
+    fn gen_aliases(&mut self) {
+        if self.aliases.is_empty() {
+            return;
+        }
+        self.lines.push(".att_syntax".to_string());
+        for name in std::mem::take(&mut self.aliases) {
+            self.lines.push(format!(".set .Lsym.{0}, {0}", name));
+        }
+        self.lines.push(".intel_syntax noprefix".to_string());
+    }
+
+    fn block_label(&self, block: BlockId) -> String {
+        format!(".L{}.{}", self.function_name, block.0)
+    }
+
+    fn gen_function(&mut self, function: &Function) {
+        // staticでなければ他の翻訳単位から見えるようにする
+        if !function.is_static {
+            self.lines.push(format!(".globl {}", function.name));
+        }
+        let label = self.definition_label(&function.name, function.is_static);
+        self.lines.push(format!("{}:", label));
+        self.lines.push("    endbr64".to_string());
+        self.function_name = function.name.clone();
+        self.stack_depth = 0;
+        let spill_offset = function.local_var_size.div_ceil(8) * 8;
+        let (locations, spill_size) = if self.allocate_registers {
+            regalloc::allocate(function, spill_offset)
+        } else {
+            regalloc::spill_all(function, spill_offset)
+        };
+        self.locations = locations;
+        // プロローグはフレームの大きさが決まってから本体の前に入れる
+        self.frame = Frame::new(
+            spill_offset + spill_size,
+            function.variadic,
+            self.omit_frame_pointer,
+        );
+        let body_start = self.lines.len();
+        let param_types: Vec<Type> = function
+            .params
+            .iter()
+            .map(|param| function.temp_types[param.0])
+            .collect();
+        let arg_registers = self.classify_args(param_types.iter());
+        // 引数レジスタは仮引数に移す前に退避する
+        self.va_frame = None;
+        if function.variadic {
+            self.gen_reg_save_area(&arg_registers);
+        }
+        // 引数を仮引数の一時変数に書き込む
+        let mut moves: Vec<(Location, Type, Source)> = Vec::new();
+        let mut stack_params: Vec<(Temp, usize)> = Vec::new();
+        for ((param, param_type), register) in function
+            .params
+            .iter()
+            .zip(param_types.iter())
+            .zip(arg_registers.iter())
+        {
+            match register {
+                Some(register) => {
+                    if let Some(location) = self.locations[param.0] {
+                        moves.push((location, *param_type, Source::Register(*register)));
                     }
-                    offset += sizeof(arg_type)
                 }
-                self.gen(&block);
-                // 終了処理
-                self.lines.push(format!(".Lendfunc{}:", self.label_func));
-                self.lines
-                    .push(format!("    add rsp, {:#0x}", self.rsp_sub_size));
-                self.lines.push(format!("    pop rdx"));
-                self.lines.push(format!("    pop rbp"));
-                self.lines.push(format!("    ret"));
-                self.label_func += 1;
-                return false;
-            }
-            Node::Block(statements) => {
-                for statement in statements.iter() {
-                    if self.gen(statement) {
-                        // required to pop
-                        self.lines.push(format!("    pop rax"));
-                    }
+                None => stack_params.push((*param, stack_params.len())),
+            }
+        }
+        self.gen_parallel_move(moves);
+        for (param, stack_order) in stack_params {
+            if self.locations[param.0].is_none() {
+                continue;
+            }
+            // スタック渡しの引数は [base+16] から8byteずつ並んでいる
+            self.lines.push(format!(
+                "    mov rax, QWORD PTR {}",
+                self.frame
+                    .address(16 + 8 * stack_order as isize, self.stack_depth)
+            ));
+            self.store_temp(param, &Type::I64, Register::RAX);
+        }
+        for (id, block) in function.blocks.iter().enumerate() {
+            self.lines
+                .push(format!("{}:", self.block_label(BlockId(id))));
+            let (insts, compare) = match block.insts.split_last() {
+                Some((compare, insts))
+                    if self.fuse_compare_branch && is_fused_compare(function, block) =>
+                {
+                    (insts, Some(compare))
                 }
-                return false;
+                _ => (block.insts.as_slice(), None),
+            };
+            for inst in insts.iter() {
+                self.gen_inst(inst);
             }
-            /* 制御構文(control statements) */
-            Node::If(if_arg) => {
-                let label = self.label_count;
-                self.label_count = self.label_count + 1;
-                self.gen(&if_arg.0);
-                self.lines.push(format!("    pop rax"));
-                self.lines.push(format!("    cmp rax, 0"));
-                self.lines.push(format!("    je .Lend{}", label));
-                // true case statement(s)
-                if self.gen(&if_arg.1) {
-                    // required to pop
-                    self.lines.push(format!("    pop rax"));
+            match (compare, &block.terminator) {
+                (
+                    Some(Inst::Compare(_, op, typename, lhs, rhs)),
+                    Terminator::Branch(_, _, then_block, else_block),
+                ) => {
+                    // 比較の結果を値にせず、フラグで直接分岐する
+                    let condition = self.gen_integer_compare(op, typename, lhs, rhs);
+                    self.lines.push(format!(
+                        "    j{} {}",
+                        condition,
+                        self.block_label(*then_block)
+                    ));
+                    self.lines
+                        .push(format!("    jmp {}", self.block_label(*else_block)));
                 }
-                self.lines.push(format!(".Lend{}:", label));
-                return false;
+                _ => self.gen_terminator(&block.terminator),
             }
-            Node::IfElse(if_arg) => {
-                let label = self.label_count;
-                self.label_count = self.label_count + 1;
-                self.gen(&if_arg.0);
-                self.lines.push(format!("    pop rax"));
-                self.lines.push(format!("    cmp rax, 0"));
-                self.lines.push(format!("    je .Lelse{}", label));
-                // true case statement(s)
-                if self.gen(&if_arg.1) {
-                    // required to pop
-                    self.lines.push(format!("    pop rax"));
+        }
+        // 終了処理
+        self.lines.push(format!(".L{}.return:", self.function_name));
+        self.frame.finish(&self.lines[body_start..]);
+        if self.omit_frame_pointer {
+            for line in self.lines[body_start..].iter_mut() {
+                *line = self.frame.patch(line);
+            }
+        }
+        let teardown = self.frame.teardown();
+        let body: Vec<String> = self
+            .lines
+            .split_off(body_start)
+            .into_iter()
+            .flat_map(|line| {
+                if line == TEARDOWN_MARK {
+                    teardown.clone()
+                } else {
+                    vec![line]
                 }
-                self.lines.push(format!("    jmp .Lend{}", label));
-                self.lines.push(format!(".Lelse{}:", label));
-                // otherwise statement(s)
-                if self.gen(&if_arg.2) {
-                    // required to pop
-                    self.lines.push(format!("    pop rax"));
+            })
+            .collect();
+        self.lines.extend(self.frame.prologue());
+        self.lines.extend(body);
+        let epilogue = self.frame.epilogue();
+        self.lines.extend(epilogue);
+    }
+
+    fn gen_inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy(dst, typename, src) => {
+                let register = self.result_register(*dst, typename);
+                self.load_operand(src, typename, register);
+                self.store_temp(*dst, typename, register);
+            }
+            Inst::Binary(dst, op, typename, lhs, rhs) => {
+                if typename.is_float() {
+                    self.load_operand(lhs, typename, Register::XMM(0));
+                    let rhs = self.operand_register(rhs, typename, Register::XMM(1));
+                    let name = match op {
+                        BinaryOp::FAdd => "add",
+                        BinaryOp::FSub => "sub",
+                        BinaryOp::FMul => "mul",
+                        BinaryOp::FDiv => "div",
+                        _ => panic!("integer operation on floating type"),
+                    };
+                    self.lines.push(format!(
+                        "    {}s{} xmm0, {}",
+                        name,
+                        float_suffix(typename),
+                        rhs.get_name(8)
+                    ));
+                    self.store_temp(*dst, typename, Register::XMM(0));
+                } else {
+                    self.gen_integer_binary(*dst, op, typename, lhs, rhs);
                 }
-                self.lines.push(format!(".Lend{}:", label));
-                return false;
             }
-            Node::While(while_arg) => {
-                let label = self.label_count;
-                self.label_count = self.label_count + 1;
-                self.lines.push(format!(".Lbegin{}:", label));
-                self.gen(&while_arg.0);
-                self.lines.push(format!("    pop rax"));
-                self.lines.push(format!("    cmp rax, 0"));
-                self.lines.push(format!("    je .Lend{}", label));
-                // loop statement(s)
-                if self.gen(&while_arg.1) {
-                    // if single statement
-                    self.lines.push(format!("    pop rax"));
+            Inst::Compare(dst, op, typename, lhs, rhs) => {
+                if typename.is_float() {
+                    self.load_operand(lhs, typename, Register::XMM(0));
+                    let rhs = self.operand_register(rhs, typename, Register::XMM(1));
+                    self.gen_floating_compare(op, typename, rhs);
+                } else {
+                    let condition = self.gen_integer_compare(op, typename, lhs, rhs);
+                    self.lines.push(format!("    set{} al", condition));
                 }
-                self.lines.push(format!("    jmp .Lbegin{}", label));
-                self.lines.push(format!(".Lend{}:", label));
-                return false;
+                let register = self.result_register(*dst, &Type::I32);
+                self.lines
+                    .push(format!("    movzx {}, al", register.get_name(4)));
+                self.store_temp(*dst, &Type::I32, register);
+            }
+            Inst::Convert(dst, op, from, to, src) => {
+                self.gen_convert(op, from, to, src);
+                let register = if to.is_float() {
+                    Register::XMM(0)
+                } else {
+                    Register::RAX
+                };
+                self.store_temp(*dst, to, register);
+            }
+            Inst::Load(dst, typename, address, volatile) => {
+                let ptr = self.memory_operand(address, typename.size());
+                let register = self.result_register(*dst, typename);
+                let mnemonic = if let Register::XMM(_) = register {
+                    format!("movs{}", float_suffix(typename))
+                } else {
+                    "mov".to_string()
+                };
+                let line = format!(
+                    "    {} {}, {}",
+                    mnemonic,
+                    register.get_name(typename.size()),
+                    ptr
+                );
+                self.push_memory_access(line, *volatile);
+                self.store_temp(*dst, typename, register);
+            }
+            Inst::Store(typename, address, value, volatile) => {
+                let value = self.operand_text(value, &bits_type(typename), Register::RDI);
+                let ptr = self.memory_operand(address, typename.size());
+                self.push_memory_access(format!("    mov {}, {}", ptr, value), *volatile);
+            }
+            Inst::Call(dst, callee, args, variadic) => {
+                self.gen_call(dst, callee, args, *variadic);
+            }
+            Inst::VaStart(ap) => {
+                let (gp_offset, fp_offset, overflow_offset, reg_save_offset) = match &self.va_frame
+                {
+                    Some(va_frame) => (
+                        va_frame.gp_offset,
+                        va_frame.fp_offset,
+                        va_frame.overflow_offset,
+                        va_frame.reg_save_offset,
+                    ),
+                    None => panic!("va_start used in a function with fixed arguments"),
+                };
+                self.load_operand(ap, &Type::Ptr, Register::RAX);
+                self.lines
+                    .push(format!("    mov DWORD PTR [rax], {:#0x}", gp_offset));
+                self.lines
+                    .push(format!("    mov DWORD PTR [rax+4], {:#0x}", fp_offset));
+                self.lines.push(format!(
+                    "    lea rdi, {}",
+                    self.frame
+                        .address(overflow_offset as isize, self.stack_depth)
+                ));
+                self.lines.push("    mov QWORD PTR [rax+8], rdi".to_string());
+                self.lines.push(format!(
+                    "    lea rdi, {}",
+                    self.frame
+                        .address(-(reg_save_offset as isize), self.stack_depth)
+                ));
+                self.lines.push("    mov QWORD PTR [rax+16], rdi".to_string());
             }
-            Node::For(for_arg) => {
+            Inst::VaArg(dst, typename, ap) => {
+                self.load_operand(ap, &Type::Ptr, Register::RDI);
                 let label = self.label_count;
-                self.label_count = self.label_count + 1;
-                self.gen(&for_arg.0); // initialization
-                self.lines.push(format!(".Lbegin{}:", label));
-                self.gen(&for_arg.1); // loop condition
-                self.lines.push(format!("    pop rax"));
-                self.lines.push(format!("    cmp rax, 0"));
-                self.lines.push(format!("    je .Lend{}", label));
-                // loop statement(s)
-                if self.gen(&for_arg.3) {
-                    // if single statement
-                    self.lines.push(format!("    pop rax"));
-                }
-                // var update
-                if self.gen(&for_arg.2) {
-                    // if single statement
-                    self.lines.push(format!("    pop rax"));
-                }
-                self.lines.push(format!("    jmp .Lbegin{}", label));
-                self.lines.push(format!(".Lend{}:", label));
-                return false;
-            }
-            /* 代入文 (assign statement) */
-            Node::Assign(assign_args) => {
-                self.gen(&assign_args.1);
-                self.lines.push(format!("    pop rdi"));
-                //self.lines.push(format!("    pop rax"));
-                let (offset, typename) = assign_args.0.expect_lvar();
+                self.label_count += 1;
+                // 整数はgp_offset (上限48), 浮動小数点数はfp_offset (上限176) から取り出す
+                let (offset_ptr, limit, step) = if typename.is_float() {
+                    ("DWORD PTR [rdi+4]", 176, 16)
+                } else {
+                    ("DWORD PTR [rdi]", 48, 8)
+                };
+                self.lines.push(format!("    mov eax, {}", offset_ptr));
+                self.lines.push(format!("    cmp eax, {}", limit));
+                self.lines.push(format!("    jae .Lvaover{}", label));
+                self.lines.push("    mov rdx, QWORD PTR [rdi+16]".to_string());
+                self.lines.push("    add rdx, rax".to_string());
+                self.lines.push(format!("    add eax, {}", step));
+                self.lines.push(format!("    mov {}, eax", offset_ptr));
+                self.lines.push(format!("    jmp .Lvaend{}", label));
+                // レジスタを使い切ったらスタック渡しの領域から取り出す
+                self.lines.push(format!(".Lvaover{}:", label));
+                self.lines.push("    mov rdx, QWORD PTR [rdi+8]".to_string());
+                self.lines.push("    lea rax, [rdx+8]".to_string());
+                self.lines.push("    mov QWORD PTR [rdi+8], rax".to_string());
+                self.lines.push(format!(".Lvaend{}:", label));
                 self.lines.push(format!(
                     "    mov {}, {}",
-                    gen_lval_ptr(offset, &typename),
-                    Register::RDI.get_name(sizeof(&typename))
+                    Register::RAX.get_name(typename.size()),
+                    gen_ptr("[rdx]".to_string(), typename.size())
                 ));
-                self.lines.push(format!("    push rdi"));
+                self.store_temp(*dst, &bits_type(typename), Register::RAX);
             }
-            /* return 文 (return statement) */
-            Node::Return(return_expr_optional) => {
-                if let Some(return_expr) = return_expr_optional {
-                    self.gen(&*return_expr);
-                    self.lines.push(format!("    pop rax"));
+            Inst::Phi(_, _, _) => panic!("phi must be removed before code generation"),
+            Inst::VaCopy(dest, src) => {
+                self.load_operand(dest, &Type::Ptr, Register::RDI);
+                self.load_operand(src, &Type::Ptr, Register::RDX);
+                for offset in [0, 8, 16].iter() {
+                    self.lines
+                        .push(format!("    mov rax, QWORD PTR [rdx+{}]", offset));
+                    self.lines
+                        .push(format!("    mov QWORD PTR [rdi+{}], rax", offset));
                 }
+            }
+        }
+    }
+
+    fn gen_terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Jump(target) => {
                 self.lines
-                    .push(format!("    jmp .Lendfunc{}", self.label_func));
-                return false;
-            }
-            /* 式(expression) */
-            Node::FunctionCall(name, arg_list) => {
-                for (order, arg) in arg_list.iter().enumerate().rev() {
-                    self.gen(arg);
-                    if order < 6 {
-                        self.lines.push(format!("    pop rax"));
-                        let register_name = self.gen_function_arg_register(order).get_name(8);
-                        self.lines.push(format!("    mov {}, rax", register_name));
-                    }
-                }
-                self.lines.push(format!("    call {}", name));
-                self.lines.push(format!("    push rax"));
+                    .push(format!("    jmp {}", self.block_label(*target)));
             }
-            Node::Unary(unary_arg, _unary_type) => {
-                self.gen(&unary_arg);
-                match _unary_type {
-                    _ => {}
+            Terminator::Branch(cond, typename, then_block, else_block) => {
+                let cond = self.operand_register(cond, typename, Register::RAX);
+                self.lines
+                    .push(format!("    test {0}, {0}", cond.get_name(typename.size())));
+                self.lines
+                    .push(format!("    jne {}", self.block_label(*then_block)));
+                self.lines
+                    .push(format!("    jmp {}", self.block_label(*else_block)));
+            }
+            Terminator::Return(value) => {
+                if let Some((value, typename)) = value {
+                    // 浮動小数点数はxmm0で返す
+                    let register = if typename.is_float() {
+                        Register::XMM(0)
+                    } else {
+                        Register::RAX
+                    };
+                    self.load_operand(value, typename, register);
                 }
+                self.lines
+                    .push(format!("    jmp .L{}.return", self.function_name));
             }
-            Node::Binary(binary_arg, binary_type) => {
-                self.gen(&binary_arg.0);
-                self.gen(&binary_arg.1);
-                self.lines.push(format!("    pop rdi"));
-                self.lines.push(format!("    pop rax"));
-                match binary_type {
-                    BinaryType::Add => {
-                        self.lines.push(format!("    add rax, rdi"));
-                    }
-                    BinaryType::Sub => {
-                        self.lines.push(format!("    sub rax, rdi"));
-                    }
-                    BinaryType::Mul => {
-                        self.lines.push(format!("    imul rax, rdi"));
-                    }
-                    BinaryType::Div => {
-                        self.lines.push(format!("    cqo"));
-                        self.lines.push(format!("    idiv rdi"));
-                    }
-                    BinaryType::Equal => {
-                        self.lines.push(format!("    cmp rax, rdi"));
-                        self.lines.push(format!("    sete al"));
-                        self.lines.push(format!("    movzb rax, al"));
+            Terminator::TailCall(callee, args, variadic) => {
+                // 引数は全てレジスタで渡す (スタック渡しの引数があれば末尾呼び出しにしない)
+                let arg_types: Vec<Type> = args.iter().map(|(_, typename)| *typename).collect();
+                let arg_registers = self.classify_args(arg_types.iter());
+                let target = self.gen_register_args(callee, args, &arg_registers, *variadic);
+                // フレームを片付けて、戻りアドレスはそのままに呼び出し先へ飛ぶ
+                self.lines.push(TEARDOWN_MARK.to_string());
+                self.lines.push(format!("    jmp {}", target));
+            }
+        }
+    }
+
+    fn gen_call(
+        &mut self,
+        dst: &Option<(Temp, Type)>,
+        callee: &Operand,
+        args: &[(Operand, Type)],
+        variadic: bool,
+    ) {
+        let arg_types: Vec<Type> = args.iter().map(|(_, typename)| *typename).collect();
+        let arg_registers = self.classify_args(arg_types.iter());
+        let stack_arg_count = arg_registers.iter().filter(|r| r.is_none()).count();
+
+        // call時点でrspが16byte境界に来るよう、スタック渡しの引数の下に詰め物をする
+        let padding = (self.stack_depth + 8 * stack_arg_count) % 16;
+        if padding != 0 {
+            self.lines.push(format!("    sub rsp, {:#0x}", padding));
+            self.stack_depth += padding;
+        }
+        // スタック渡しの引数を右から順に積む
+        for ((arg, typename), register) in args.iter().zip(arg_registers.iter()).rev() {
+            if register.is_none() {
+                self.load_operand(arg, &bits_type(typename), Register::RAX);
+                self.push("rax");
+            }
+        }
+        let call_target = self.gen_register_args(callee, args, &arg_registers, variadic);
+        if self.stack_check {
+            self.gen_stack_check();
+        }
+        self.lines.push(format!("    call {}", call_target));
+        // 呼び出し側で引数と詰め物を片付ける
+        let cleanup_size = 8 * stack_arg_count + padding;
+        if cleanup_size != 0 {
+            self.lines
+                .push(format!("    add rsp, {:#0x}", cleanup_size));
+            self.stack_depth -= cleanup_size;
+        }
+        if let Some((dst, typename)) = dst {
+            let register = if typename.is_float() {
+                Register::XMM(0)
+            } else {
+                Register::RAX
+            };
+            self.store_temp(*dst, typename, register);
+        }
+    }
+
+    // レジスタ渡しの引数と呼び出し先を用意して、呼び出し先 (callやjmpのオペランド) を返す
+    fn gen_register_args(
+        &mut self,
+        callee: &Operand,
+        args: &[(Operand, Type)],
+        arg_registers: &[Option<Register>],
+        variadic: bool,
+    ) -> String {
+        // SSEレジスタの引数は一時変数と重ならないので、そのまま入れてよい
+        let mut moves: Vec<(Location, Type, Source)> = Vec::new();
+        for ((arg, typename), register) in args.iter().zip(arg_registers.iter()) {
+            match register {
+                Some(Register::XMM(n)) => self.load_operand(arg, typename, Register::XMM(*n)),
+                Some(register) => moves.push((
+                    Location::Register(*register),
+                    *typename,
+                    Source::Operand(arg.clone()),
+                )),
+                None => {}
+            }
+        }
+        let target = match callee {
+            Operand::Function(name) => self.symbol(name),
+            _ => {
+                // alに使ったSSEレジスタの数を入れるので、呼び出し先はr11に置く
+                self.load_operand(callee, &Type::Ptr, Register::Rn(11));
+                "r11".to_string()
+            }
+        };
+        // 整数の引数は他の引数が入っているレジスタに書くことがあるので並行コピーにする
+        self.gen_parallel_move(moves);
+        if variadic {
+            let float_count = arg_registers
+                .iter()
+                .filter(|register| matches!(register, Some(Register::XMM(_))))
+                .count();
+            self.lines.push(format!("    mov eax, {}", float_count));
+        }
+        target
+    }
+
+    // volatileなアクセスはのぞき穴最適化で省かれないよう印を付ける
+    fn push_memory_access(&mut self, line: String, volatile: bool) {
+        if volatile {
+            self.lines.push(format!("{} {}", line, VOLATILE_MARK));
+        } else {
+            self.lines.push(line);
+        }
+    }
+
+    fn location(&self, temp: Temp) -> Location {
+        match self.locations[temp.0] {
+            Some(location) => location,
+            None => panic!("temporary %{} is not allocated", temp.0),
+        }
+    }
+
+    // 一時変数と同じ種類のレジスタに置かれていればそのレジスタ、そうでなければregister
+    fn temp_register(&self, temp: Temp, register: Register) -> Register {
+        match (self.location(temp), register) {
+            (Location::Register(Register::XMM(n)), Register::XMM(_)) => Register::XMM(n),
+            (Location::Register(Register::XMM(_)), register) => register,
+            (Location::Register(_), Register::XMM(n)) => Register::XMM(n),
+            (Location::Register(allocated), _) => allocated,
+            (Location::Stack(_), register) => register,
+        }
+    }
+
+    // 命令の結果を書き込むレジスタ
+    fn result_register(&self, temp: Temp, typename: &Type) -> Register {
+        let scratch = if typename.is_float() {
+            Register::XMM(0)
+        } else {
+            Register::RAX
+        };
+        self.temp_register(temp, scratch)
+    }
+
+    // オペランドがレジスタにあればそのレジスタを、なければregisterに読み込んで返す
+    fn operand_register(
+        &mut self,
+        operand: &Operand,
+        typename: &Type,
+        register: Register,
+    ) -> Register {
+        if let Operand::Temp(temp) = operand {
+            let allocated = self.temp_register(*temp, register);
+            if allocated != register {
+                return allocated;
+            }
+        }
+        self.load_operand(operand, typename, register);
+        register
+    }
+
+    // 整数命令の右オペランド (32bitに収まる即値ならそのまま、レジスタにあればその名前)
+    fn operand_text(&mut self, operand: &Operand, typename: &Type, register: Register) -> String {
+        let size = typename.size();
+        match operand {
+            Operand::Int(n) if size < 8 || *n as i32 as i64 == *n => match size {
+                1 => format!("{}", *n as i8),
+                2 => format!("{}", *n as i16),
+                4 => format!("{}", *n as i32),
+                _ => format!("{}", n),
+            },
+            _ => self
+                .operand_register(operand, typename, register)
+                .get_name(size),
+        }
+    }
+
+    // base-offset にある仮引数・ローカル変数のアドレス
+    fn local_address(&self, offset: usize) -> String {
+        self.frame.address(-(offset as isize), self.stack_depth)
+    }
+
+    fn store_temp(&mut self, temp: Temp, typename: &Type, register: Register) {
+        let location = self.location(temp);
+        self.store_location(&location, typename, register);
+    }
+
+    fn store_location(&mut self, location: &Location, typename: &Type, register: Register) {
+        let size = typename.size();
+        match location {
+            Location::Register(allocated) => self.move_register(*allocated, register, size),
+            Location::Stack(offset) => {
+                let ptr = gen_ptr(self.local_address(*offset), size);
+                match register {
+                    Register::XMM(n) => self.lines.push(format!(
+                        "    movs{} {}, xmm{}",
+                        if size == 4 { "s" } else { "d" },
+                        ptr,
+                        n
+                    )),
+                    register => {
+                        self.lines
+                            .push(format!("    mov {}, {}", ptr, register.get_name(size)))
                     }
-                    BinaryType::NotEqual => {
-                        self.lines.push(format!("    cmp rax, rdi"));
-                        self.lines.push(format!("    setne al"));
-                        self.lines.push(format!("    movzb rax, al"));
+                }
+            }
+        }
+    }
+
+    // レジスタ間のコピー (整数レジスタとSSEレジスタの間はビット列のまま移す)
+    fn move_register(&mut self, dst: Register, src: Register, size: usize) {
+        if dst == src {
+            return;
+        }
+        let (suffix, size) = if size == 4 { ("d", 4) } else { ("q", 8) };
+        match (dst, src) {
+            (Register::XMM(d), Register::XMM(s)) => {
+                self.lines.push(format!("    movaps xmm{}, xmm{}", d, s))
+            }
+            (Register::XMM(d), src) => self.lines.push(format!(
+                "    mov{} xmm{}, {}",
+                suffix,
+                d,
+                src.get_name(size)
+            )),
+            (dst, Register::XMM(s)) => self.lines.push(format!(
+                "    mov{} {}, xmm{}",
+                suffix,
+                dst.get_name(size),
+                s
+            )),
+            // 1, 2byteも32bitで移す
+            (dst, src) => {
+                let size = std::cmp::max(size, 4);
+                self.lines.push(format!(
+                    "    mov {}, {}",
+                    dst.get_name(size),
+                    src.get_name(size)
+                ))
+            }
+        }
+    }
+
+    // 全ての転送元を読んでから転送先に書くコピーを、順に実行できる形で出力する
+    fn gen_parallel_move(&mut self, moves: Vec<(Location, Type, Source)>) {
+        let mut pending: Vec<(Location, Type, Source)> = moves
+            .into_iter()
+            .map(|(dst, typename, src)| {
+                let src = match src {
+                    Source::Operand(Operand::Temp(temp)) => match self.location(temp) {
+                        Location::Register(register) => Source::Register(register),
+                        Location::Stack(_) => Source::Operand(Operand::Temp(temp)),
+                    },
+                    src => src,
+                };
+                (dst, typename, src)
+            })
+            .filter(|(dst, _, src)| !matches!((dst, src), (Location::Register(d), Source::Register(s)) if d == s))
+            .collect();
+        let reads = |pending: &[(Location, Type, Source)], register: Register| {
+            pending
+                .iter()
+                .any(|(_, _, src)| matches!(src, Source::Register(s) if *s == register))
+        };
+        while !pending.is_empty() {
+            // 他のコピーが読まないところへのコピーから行う
+            let ready = (0..pending.len()).find(|index| match pending[*index].0 {
+                Location::Register(register) => !reads(&pending, register),
+                Location::Stack(_) => true,
+            });
+            match ready {
+                Some(index) => {
+                    let (dst, typename, src) = pending.remove(index);
+                    match (&dst, src) {
+                        (_, Source::Register(register)) => {
+                            self.store_location(&dst, &typename, register)
+                        }
+                        (Location::Register(register), Source::Operand(operand)) => {
+                            self.load_operand(&operand, &typename, *register)
+                        }
+                        (Location::Stack(_), Source::Operand(operand)) => {
+                            self.load_operand(&operand, &typename, Register::RAX);
+                            self.store_location(&dst, &typename, Register::RAX);
+                        }
                     }
-                    BinaryType::Lt => {
-                        self.lines.push(format!("    cmp rax, rdi"));
-                        self.lines.push(format!("    setl al"));
-                        self.lines.push(format!("    movzb rax, al"));
+                }
+                None => {
+                    // 循環しているので、1つの転送先の値をraxに逃がす
+                    let register = match pending[0].0 {
+                        Location::Register(register) => register,
+                        Location::Stack(_) => panic!("cyclic copy into memory"),
+                    };
+                    self.move_register(Register::RAX, register, 8);
+                    for (_, _, src) in pending.iter_mut() {
+                        if matches!(src, Source::Register(s) if *s == register) {
+                            *src = Source::Register(Register::RAX);
+                        }
                     }
-                    BinaryType::LtEq => {
-                        self.lines.push(format!("    cmp rax, rdi"));
-                        self.lines.push(format!("    setle al"));
-                        self.lines.push(format!("    movzb rax, al"));
+                }
+            }
+        }
+    }
+
+    // オペランドの値をレジスタに入れる
+    // 整数レジスタには型の幅だけ書き込むので、それより上位のビットは不定になる
+    fn load_operand(&mut self, operand: &Operand, typename: &Type, register: Register) {
+        let size = typename.size();
+        if let Register::XMM(n) = register {
+            match operand {
+                Operand::Temp(temp) => match self.location(*temp) {
+                    Location::Register(allocated) => self.move_register(register, allocated, size),
+                    Location::Stack(offset) => {
+                        let ptr = gen_ptr(self.local_address(offset), size);
+                        self.lines.push(format!(
+                            "    movs{} xmm{}, {}",
+                            if size == 4 { "s" } else { "d" },
+                            n,
+                            ptr
+                        ));
                     }
+                },
+                _ => {
+                    // 即値はr11を経由する
+                    self.load_operand(operand, &bits_type(typename), Register::Rn(11));
+                    self.lines.push(format!(
+                        "    mov{} xmm{}, {}",
+                        if size == 4 { "d" } else { "q" },
+                        n,
+                        Register::Rn(11).get_name(size)
+                    ));
+                }
+            }
+            return;
+        }
+        let name = register.get_name(size);
+        match operand {
+            Operand::Temp(temp) => match self.location(*temp) {
+                Location::Register(allocated) => self.move_register(register, allocated, size),
+                Location::Stack(offset) => {
+                    let ptr = gen_ptr(self.local_address(offset), size);
+                    self.lines.push(format!("    mov {}, {}", name, ptr));
+                }
+            },
+            Operand::Int(n) => {
+                if size == 8 && *n as i32 as i64 != *n {
+                    // 32bitに収まらない即値はmovabsで入れる
+                    self.lines.push(format!("    movabs {}, {:#0x}", name, n));
+                } else {
+                    let n = match size {
+                        1 => *n as i8 as i64,
+                        2 => *n as i16 as i64,
+                        4 => *n as i32 as i64,
+                        _ => *n,
+                    };
+                    self.lines.push(format!("    mov {}, {}", name, n));
                 }
-                self.lines.push(format!("    push rax"));
             }
-            Node::LVar(offset, typename) => {
+            Operand::Float(x) => {
                 self.lines.push(format!(
-                    "    mov {}, {}",
-                    Register::RAX.get_name(sizeof(typename)),
-                    gen_lval_ptr(*offset, typename)
+                    "    movabs {}, {:#0x}",
+                    register.get_name(8),
+                    float_bits(*x, typename)
                 ));
-                self.lines.push(format!("    push rax"));
             }
-            Node::Num(n) => {
-                self.lines.push(format!("    push {:#0x}", n));
+            Operand::Local(offset) => {
+                self.lines.push(format!(
+                    "    lea {}, {}",
+                    register.get_name(8),
+                    self.local_address(*offset)
+                ));
+            }
+            Operand::Global(label) => {
+                let label = self.symbol(label);
+                self.lines
+                    .push(format!("    lea {}, [rip+{}]", register.get_name(8), label));
+            }
+            Operand::Function(name) => {
+                // 共有ライブラリの関数もあるのでGOT経由でアドレスを得る
+                let name = self.symbol(name);
+                self.lines.push(format!(
+                    "    mov {}, QWORD PTR [rip+{}@GOTPCREL]",
+                    register.get_name(8),
+                    name
+                ));
+            }
+        }
+    }
+
+    // addressが指すsize byteのメモリ (アドレスの計算にraxを使う)
+    fn memory_operand(&mut self, address: &Operand, size: usize) -> String {
+        match address {
+            Operand::Local(offset) => gen_ptr(self.local_address(*offset), size),
+            Operand::Global(label) => gen_ptr(format!("[rip+{}]", self.symbol(label)), size),
+            Operand::Temp(temp)
+                if !matches!(self.temp_register(*temp, Register::RAX), Register::RAX) =>
+            {
+                let register = self.temp_register(*temp, Register::RAX);
+                gen_ptr(format!("[{}]", register.get_name(8)), size)
+            }
+            _ => {
+                self.load_operand(address, &Type::Ptr, Register::RAX);
+                gen_ptr("[rax]".to_string(), size)
             }
-            Node::Boolean(flag) => {
-                if *flag {
-                    self.lines.push(format!("    push 1"));
+        }
+    }
+
+    // 名前付き引数の後ろから可変長引数を取り出せるよう、引数レジスタを全て退避する
+    fn gen_reg_save_area(&mut self, arg_registers: &[Option<Register>]) {
+        let reg_save_offset = self.frame.reg_save_offset;
+        for order in 0..6 {
+            let register = self.gen_function_arg_register(order);
+            self.lines.push(format!(
+                "    mov QWORD PTR {}, {}",
+                self.local_address(reg_save_offset - 8 * order),
+                register.get_name(8)
+            ));
+        }
+        for n in 0..8 {
+            self.lines.push(format!(
+                "    movups XMMWORD PTR {}, xmm{}",
+                self.local_address(reg_save_offset - 48 - 16 * n),
+                n
+            ));
+        }
+        let gp_count = arg_registers
+            .iter()
+            .filter(|register| matches!(register, Some(register) if !matches!(register, Register::XMM(_))))
+            .count();
+        let fp_count = arg_registers
+            .iter()
+            .filter(|register| matches!(register, Some(Register::XMM(_))))
+            .count();
+        let stack_count = arg_registers.iter().filter(|r| r.is_none()).count();
+        self.va_frame = Some(VaFrame {
+            gp_offset: 8 * gp_count,
+            fp_offset: 48 + 16 * fp_count,
+            overflow_offset: 16 + 8 * stack_count,
+            reg_save_offset,
+        });
+    }
+
+    // 静的変数の定義: 初期値があれば.data、なければ0埋めの.bssに置く
+    fn gen_global(&mut self, global: &Global) {
+        self.lines.push(
+            match (&global.initializer, global.readonly) {
+                (None, _) => ".bss",
+                (Some(_), false) => ".data",
+                (Some(_), true) => ".section .rodata",
+            }
+            .to_string(),
+        );
+        if !global.is_static {
+            self.lines.push(format!(".globl {}", global.label));
+        }
+        self.lines.push(format!("    .align {}", global.align));
+        let label = self.definition_label(&global.label, global.is_static);
+        self.lines.push(format!("{}:", label));
+        let directive = match global.size {
+            1 => ".byte",
+            2 => ".short",
+            4 => ".long",
+            _ => ".quad",
+        };
+        match &global.initializer {
+            None => self.lines.push(format!("    .zero {}", global.size)),
+            Some(Operand::Int(n)) => self.lines.push(format!("    {} {}", directive, n)),
+            Some(Operand::Float(x)) => {
+                // ビット列で出力する
+                let typename = if global.size == 4 {
+                    Type::F32
                 } else {
-                    self.lines.push(format!("    push 0"));
-                }
+                    Type::F64
+                };
+                self.lines.push(format!(
+                    "    {} {:#0x}",
+                    directive,
+                    float_bits(*x, &typename)
+                ));
             }
-            Node::Empty => {
-                return false;
+            Some(Operand::Global(label)) | Some(Operand::Function(label)) => {
+                let label = self.symbol(label);
+                self.lines.push(format!("    .quad {}", label))
             }
+            Some(_) => panic!("initializer element is not constant"),
         }
-        return true;
+        self.lines.push(".text".to_string());
+    }
+
+    // 文字列リテラルを読み取り専用データとして出力する
+    fn gen_string_literals(&mut self, strings: &[Vec<u8>]) {
+        if strings.is_empty() {
+            return;
+        }
+        self.lines.push(".section .rodata".to_string());
+        for (label, bytes) in strings.iter().enumerate() {
+            self.lines.push(format!(".LC{}:", label));
+            let escaped: String = bytes
+                .iter()
+                .map(|byte| match byte {
+                    b'"' => "\\\"".to_string(),
+                    b'\\' => "\\\\".to_string(),
+                    0x20..=0x7e => (*byte as char).to_string(),
+                    _ => format!("\\{:03o}", byte),
+                })
+                .collect();
+            self.lines.push(format!("    .string \"{}\"", escaped));
+        }
+        self.lines.push(".text".to_string());
+    }
+
+    fn push(&mut self, operand: &str) {
+        self.lines.push(format!("    push {}", operand));
+        self.stack_depth += 8;
+    }
+
+    // rspが16byte境界になければ不正命令で落とす
+    fn gen_stack_check(&mut self) {
+        let label = self.label_count;
+        self.label_count += 1;
+        self.lines.push("    test rsp, 0xf".to_string());
+        self.lines.push(format!("    jz .Lstackok{}", label));
+        self.lines.push("    ud2".to_string());
+        self.lines.push(format!(".Lstackok{}:", label));
+    }
+
+    // SysV ABIに従って引数を渡すレジスタを決める (Noneはスタック渡し)
+    pub fn classify_args<'a, I>(&mut self, arg_types: I) -> Vec<Option<Register>>
+    where
+        I: Iterator<Item = &'a Type>,
+    {
+        let mut int_count: usize = 0;
+        let mut float_count: usize = 0;
+        arg_types
+            .map(|arg_type| {
+                if arg_type.is_float() {
+                    float_count += 1;
+                    if float_count <= 8 {
+                        Some(Register::XMM(float_count - 1))
+                    } else {
+                        None
+                    }
+                } else {
+                    int_count += 1;
+                    if int_count <= 6 {
+                        Some(self.gen_function_arg_register(int_count - 1))
+                    } else {
+                        None
+                    }
+                }
+            })
+            .collect()
     }
 
     pub fn gen_function_arg_register(&mut self, order: usize) -> Register {
@@ -282,4 +1027,239 @@ impl CodeGenerator {
             _ => panic!("out of range"),
         }
     }
+
+    // 整数同士の演算結果をdstに入れる
+    fn gen_integer_binary(
+        &mut self,
+        dst: Temp,
+        op: &BinaryOp,
+        typename: &Type,
+        lhs: &Operand,
+        rhs: &Operand,
+    ) {
+        // 1, 2byteの演算は32bitで行う
+        let size = std::cmp::max(typename.size(), 4);
+        if let BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul = op {
+            // 左オペランドを結果のレジスタに入れて、右オペランドと直接演算する
+            let mut register = self.result_register(dst, typename);
+            let (mut lhs, mut rhs) = (lhs, rhs);
+            if let Operand::Temp(temp) = rhs {
+                if self.temp_register(*temp, Register::RAX) == register {
+                    // 交換できる演算なら左右を入れ替え、できなければraxで計算する
+                    if let BinaryOp::Sub = op {
+                        register = Register::RAX;
+                    } else {
+                        std::mem::swap(&mut lhs, &mut rhs);
+                    }
+                }
+            }
+            self.load_operand(lhs, typename, register);
+            let rhs = self.operand_text(rhs, &integer_type(size), Register::RDI);
+            let name = match op {
+                BinaryOp::Add => "add",
+                BinaryOp::Sub => "sub",
+                _ => "imul",
+            };
+            self.lines
+                .push(format!("    {} {}, {}", name, register.get_name(size), rhs));
+            self.store_temp(dst, typename, register);
+            return;
+        }
+        // 割り算はrdx:raxを右オペランドで割る
+        self.load_operand(lhs, typename, Register::RAX);
+        let mut rhs = self.operand_register(rhs, typename, Register::RDI);
+        let signed = match op {
+            BinaryOp::SDiv => true,
+            BinaryOp::UDiv => false,
+            _ => panic!("floating operation on integer type"),
+        };
+        if typename.size() < 4 {
+            let extend = if signed { "movsx" } else { "movzx" };
+            let from = typename.size();
+            self.lines.push(format!(
+                "    {} eax, {}",
+                extend,
+                Register::RAX.get_name(from)
+            ));
+            self.lines
+                .push(format!("    {} edi, {}", extend, rhs.get_name(from)));
+            rhs = Register::RDI;
+        }
+        if signed {
+            self.lines
+                .push(format!("    {}", if size == 8 { "cqo" } else { "cdq" }));
+            self.lines.push(format!("    idiv {}", rhs.get_name(size)));
+        } else {
+            self.lines.push("    xor edx, edx".to_string());
+            self.lines.push(format!("    div {}", rhs.get_name(size)));
+        }
+        self.store_temp(dst, typename, Register::RAX);
+    }
+
+    // 整数を比較してフラグを立て、成り立つときの条件 (setcc, jccの接尾辞) を返す
+    fn gen_integer_compare(
+        &mut self,
+        op: &CompareOp,
+        typename: &Type,
+        lhs: &Operand,
+        rhs: &Operand,
+    ) -> &'static str {
+        let size = typename.size();
+        let lhs = self.operand_register(lhs, typename, Register::RAX);
+        let rhs = self.operand_text(rhs, typename, Register::RDI);
+        self.lines
+            .push(format!("    cmp {}, {}", lhs.get_name(size), rhs));
+        match op {
+            CompareOp::Eq => "e",
+            CompareOp::Ne => "ne",
+            CompareOp::Slt => "l",
+            CompareOp::Sle => "le",
+            CompareOp::Ult => "b",
+            CompareOp::Ule => "be",
+            _ => panic!("floating comparison on integer type"),
+        }
+    }
+
+    // xmm0とrhsの浮動小数点数を比べた結果をalに入れる
+    fn gen_floating_compare(&mut self, op: &CompareOp, typename: &Type, rhs: Register) {
+        let suffix = float_suffix(typename);
+        let rhs = rhs.get_name(8);
+        match op {
+            CompareOp::FEq => {
+                // NaNとの比較はPFが立つので偽にする
+                self.lines
+                    .push(format!("    ucomis{} xmm0, {}", suffix, rhs));
+                self.lines.push("    sete al".to_string());
+                self.lines.push("    setnp dl".to_string());
+                self.lines.push("    and al, dl".to_string());
+            }
+            CompareOp::FNe => {
+                self.lines
+                    .push(format!("    ucomis{} xmm0, {}", suffix, rhs));
+                self.lines.push("    setne al".to_string());
+                self.lines.push("    setp dl".to_string());
+                self.lines.push("    or al, dl".to_string());
+            }
+            CompareOp::FLt => {
+                // a < b <=> b > a (NaNのときCF=1になるので逆向きに比較する)
+                self.lines
+                    .push(format!("    ucomis{} {}, xmm0", suffix, rhs));
+                self.lines.push("    seta al".to_string());
+            }
+            CompareOp::FLe => {
+                self.lines
+                    .push(format!("    ucomis{} {}, xmm0", suffix, rhs));
+                self.lines.push("    setae al".to_string());
+            }
+            _ => panic!("integer comparison on floating type"),
+        }
+    }
+
+    // srcを型fromから型toに変換し、結果をrax (整数) かxmm0 (浮動小数点数) に入れる
+    fn gen_convert(&mut self, op: &ConvertOp, from: &Type, to: &Type, src: &Operand) {
+        let suffix = float_suffix(to);
+        match op {
+            ConvertOp::Sext | ConvertOp::Zext | ConvertOp::Trunc => {
+                self.load_operand(src, from, Register::RAX);
+                if let ConvertOp::Sext | ConvertOp::Zext = op {
+                    self.gen_extend(op, from);
+                }
+            }
+            ConvertOp::SiToFp => {
+                self.load_operand(src, from, Register::RAX);
+                self.gen_extend(&ConvertOp::Sext, from);
+                self.lines.push(format!("    cvtsi2s{} xmm0, rax", suffix));
+            }
+            ConvertOp::UiToFp => {
+                self.load_operand(src, from, Register::RAX);
+                if from.size() < 8 {
+                    self.gen_extend(&ConvertOp::Zext, from);
+                    self.lines.push(format!("    cvtsi2s{} xmm0, rax", suffix));
+                } else {
+                    // 最上位ビットが立っていると符号付きとして変換されてしまうので
+                    // 半分にしてから変換して2倍する
+                    let label = self.label_count;
+                    self.label_count += 1;
+                    self.lines.push("    test rax, rax".to_string());
+                    self.lines.push(format!("    js .Lcvt{}", label));
+                    self.lines.push(format!("    cvtsi2s{} xmm0, rax", suffix));
+                    self.lines.push(format!("    jmp .Lcvtend{}", label));
+                    self.lines.push(format!(".Lcvt{}:", label));
+                    self.lines.push("    mov rdi, rax".to_string());
+                    self.lines.push("    shr rdi, 1".to_string());
+                    self.lines.push("    and eax, 1".to_string());
+                    self.lines.push("    or rdi, rax".to_string());
+                    self.lines.push(format!("    cvtsi2s{} xmm0, rdi", suffix));
+                    self.lines.push(format!("    adds{0} xmm0, xmm0", suffix));
+                    self.lines.push(format!(".Lcvtend{}:", label));
+                }
+            }
+            ConvertOp::FpToUi if to.size() == 8 => {
+                // 2^63以上は2^63を引いてから変換し、最上位ビットを立てる
+                let suffix = float_suffix(from);
+                let label = self.label_count;
+                self.label_count += 1;
+                self.load_operand(src, from, Register::XMM(0));
+                self.load_operand(
+                    &Operand::Float(9223372036854775808.0),
+                    from,
+                    Register::XMM(1),
+                );
+                self.lines.push(format!("    comis{} xmm0, xmm1", suffix));
+                self.lines.push(format!("    jae .Lcvt{}", label));
+                self.lines.push(format!("    cvtts{}2si rax, xmm0", suffix));
+                self.lines.push(format!("    jmp .Lcvtend{}", label));
+                self.lines.push(format!(".Lcvt{}:", label));
+                self.lines.push(format!("    subs{} xmm0, xmm1", suffix));
+                self.lines.push(format!("    cvtts{}2si rax, xmm0", suffix));
+                self.lines.push("    btc rax, 63".to_string());
+                self.lines.push(format!(".Lcvtend{}:", label));
+            }
+            ConvertOp::FpToSi | ConvertOp::FpToUi => {
+                self.load_operand(src, from, Register::XMM(0));
+                self.lines
+                    .push(format!("    cvtts{}2si rax, xmm0", float_suffix(from)));
+            }
+            ConvertOp::FpExt => {
+                self.load_operand(src, from, Register::XMM(0));
+                self.lines.push("    cvtss2sd xmm0, xmm0".to_string());
+            }
+            ConvertOp::FpTrunc => {
+                self.load_operand(src, from, Register::XMM(0));
+                self.lines.push("    cvtsd2ss xmm0, xmm0".to_string());
+            }
+        }
+    }
+
+    // raxの下位 (型fromの幅) を64bitに拡張する
+    fn gen_extend(&mut self, op: &ConvertOp, from: &Type) {
+        let signed = matches!(op, ConvertOp::Sext);
+        match (signed, from.size()) {
+            (true, 1) => self.lines.push("    movsx rax, al".to_string()),
+            (true, 2) => self.lines.push("    movsx rax, ax".to_string()),
+            (true, 4) => self.lines.push("    movsxd rax, eax".to_string()),
+            (false, 1) => self.lines.push("    movzx eax, al".to_string()),
+            (false, 2) => self.lines.push("    movzx eax, ax".to_string()),
+            (false, 4) => self.lines.push("    mov eax, eax".to_string()),
+            _ => {}
+        }
+    }
+}
+
+fn integer_type(size: usize) -> Type {
+    match size {
+        1 => Type::I8,
+        2 => Type::I16,
+        4 => Type::I32,
+        _ => Type::I64,
+    }
+}
+
+// メモリやスタック渡しで扱うときは浮動小数点数もビット列として整数レジスタに入れる
+fn bits_type(typename: &Type) -> Type {
+    match typename {
+        Type::F32 => Type::I32,
+        Type::F64 => Type::I64,
+        typename => *typename,
+    }
 }
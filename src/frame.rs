@@ -0,0 +1,191 @@
+use crate::register::Register;
+
+// 呼び出し先が保存しなければならないレジスタ (rbp以外)
+pub const CALLEE_SAVED: [Register; 5] = [
+    Register::RBX,
+    Register::Rn(12),
+    Register::Rn(13),
+    Register::Rn(14),
+    Register::Rn(15),
+];
+
+// フレームポインタを省略したときのアドレスの仮置き
+// 本体を生成し終えてフレームの大きさが決まってから置き換える
+const FRAME_ADDRESS_MARK: &str = "{frame:";
+
+// 末尾呼び出しの前にフレームを片付ける位置の印
+// 退避するcallee-savedレジスタが決まってから置き換える
+pub const TEARDOWN_MARK: &str = "{teardown}";
+
+fn align_to(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+// 符号付きの変位を "+0x8" / "-0x8" の形にする
+fn sprint_displacement(displacement: isize) -> String {
+    if displacement < 0 {
+        format!("-{:#0x}", -displacement)
+    } else {
+        format!("+{:#0x}", displacement)
+    }
+}
+
+// 関数のスタックフレーム
+//
+//   [base+16+8k]      スタック渡しの引数
+//   [base+8]          戻りアドレス
+//   [base]            呼び出し元のrbp (フレームポインタを省略したときは空き)
+//   [base-offset]     仮引数とローカル変数 (offsetは領域の下端)
+//   [base-reg_save]   レジスタ退避領域 (可変長引数関数のみ176byte)
+//   ...               callee-savedレジスタの退避先
+//   [base-size]       本体開始時のrsp (16byte境界)
+//
+// baseはフレームポインタを使うときはrbp、省略するときはrspから求める
+pub struct Frame {
+    pub omit_frame_pointer: bool,
+    pub reg_save_offset: usize,               // 0ならレジスタ退避領域なし
+    pub callee_saved: Vec<(Register, usize)>, // (register, offset)
+    pub size: usize,
+    // callee-savedレジスタの退避先の上端
+    saved_area_offset: usize,
+}
+
+impl Frame {
+    pub fn new(local_var_size: usize, variadic: bool, omit_frame_pointer: bool) -> Frame {
+        let local_area = align_to(local_var_size, 16);
+        let reg_save_offset = if variadic { local_area + 176 } else { 0 };
+        let saved_area_offset = std::cmp::max(local_area, reg_save_offset);
+        Frame {
+            omit_frame_pointer,
+            reg_save_offset,
+            callee_saved: Vec::new(),
+            size: saved_area_offset,
+            saved_area_offset,
+        }
+    }
+
+    // base + displacement のアドレス
+    // stack_depthは本体に入ってからpushしたバイト数
+    pub fn address(&self, displacement: isize, stack_depth: usize) -> String {
+        if self.omit_frame_pointer {
+            format!(
+                "{}{}}}",
+                FRAME_ADDRESS_MARK,
+                stack_depth as isize + displacement
+            )
+        } else {
+            format!("[rbp{}]", sprint_displacement(displacement))
+        }
+    }
+
+    // 本体で使ったcallee-savedレジスタの退避先を割り当てて大きさを決める
+    pub fn finish(&mut self, body: &[String]) {
+        let used = |register: &Register| {
+            let names: Vec<String> = [1, 2, 4, 8]
+                .iter()
+                .map(|size| register.get_name(*size))
+                .collect();
+            body.iter().any(|line| {
+                line.split(|c: char| !c.is_ascii_alphanumeric())
+                    .any(|word| names.iter().any(|name| name == word))
+            })
+        };
+        let mut offset = self.saved_area_offset;
+        self.callee_saved.clear();
+        for register in CALLEE_SAVED.iter().filter(|register| used(register)) {
+            offset += 8;
+            self.callee_saved.push((*register, offset));
+        }
+        self.size = align_to(offset, 16);
+    }
+
+    // 仮置きしたアドレスをrspからの位置に置き換える
+    pub fn patch(&self, line: &str) -> String {
+        let mut patched = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find(FRAME_ADDRESS_MARK) {
+            let end = start + rest[start..].find('}').unwrap();
+            let displacement: isize = rest[start + FRAME_ADDRESS_MARK.len()..end].parse().unwrap();
+            patched += &rest[..start];
+            patched += &format!(
+                "[rsp{}]",
+                sprint_displacement(self.size as isize + displacement)
+            );
+            rest = &rest[end + 1..];
+        }
+        patched + rest
+    }
+
+    pub fn prologue(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        if self.omit_frame_pointer {
+            // 呼び出し直後のrspは16byte境界から8ずれているので、rbpを積む代わりの8byteを足す
+            lines.push(format!("    sub rsp, {:#0x}", self.size + 8));
+        } else {
+            lines.push("    push rbp".to_string());
+            lines.push("    mov rbp, rsp".to_string());
+            if self.size != 0 {
+                lines.push(format!("    sub rsp, {:#0x}", self.size));
+            }
+        }
+        for (register, offset) in self.callee_saved.iter() {
+            lines.push(self.patch(&format!(
+                "    mov QWORD PTR {}, {}",
+                self.address(-(*offset as isize), 0),
+                register.get_name(8)
+            )));
+        }
+        lines
+    }
+
+    // callee-savedレジスタを戻してフレームを片付ける (rspは戻りアドレスを指す)
+    pub fn teardown(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for (register, offset) in self.callee_saved.iter() {
+            lines.push(self.patch(&format!(
+                "    mov {}, QWORD PTR {}",
+                register.get_name(8),
+                self.address(-(*offset as isize), 0)
+            )));
+        }
+        if self.omit_frame_pointer {
+            lines.push(format!("    add rsp, {:#0x}", self.size + 8));
+        } else {
+            lines.push("    mov rsp, rbp".to_string());
+            lines.push("    pop rbp".to_string());
+        }
+        lines
+    }
+
+    pub fn epilogue(&self) -> Vec<String> {
+        let mut lines = self.teardown();
+        lines.push("    ret".to_string());
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::frame::Frame;
+
+    #[test]
+    fn frame_test() {
+        // ローカル変数20byte、rbxとr12を使う関数
+        let mut frame = Frame::new(20, false, true);
+        let body = vec![
+            format!("    mov {}, rbx", frame.address(-8, 8)),
+            "    add r12d, eax".to_string(),
+        ];
+        frame.finish(&body);
+        assert_eq!(frame.size, 48);
+        assert_eq!(frame.patch(&body[0]), "    mov [rsp+0x30], rbx");
+        assert_eq!(
+            frame.prologue(),
+            vec![
+                "    sub rsp, 0x38",
+                "    mov QWORD PTR [rsp+0x8], rbx",
+                "    mov QWORD PTR [rsp+0x0], r12",
+            ]
+        );
+    }
+}
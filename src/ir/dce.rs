@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+
+use super::dominator::reverse_postorder;
+use super::liveness::{inst_uses, temps};
+use super::{BlockId, Function, Inst, Module, Operand, Temp};
+
+// 入口から到達できないブロックを取り除き、残りのブロックに番号を振り直す
+pub fn remove_unreachable_blocks(function: &mut Function) -> bool {
+    let mut reachable = vec![false; function.blocks.len()];
+    for block in reverse_postorder(function) {
+        reachable[block.0] = true;
+    }
+    if reachable.iter().all(|flag| *flag) {
+        return false;
+    }
+    // 元の番号 -> 新しい番号
+    let mut renumber: Vec<Option<BlockId>> = vec![None; function.blocks.len()];
+    let mut count: usize = 0;
+    for (id, flag) in reachable.iter().enumerate() {
+        if *flag {
+            renumber[id] = Some(BlockId(count));
+            count += 1;
+        }
+    }
+    let blocks = std::mem::take(&mut function.blocks);
+    for (id, mut block) in blocks.into_iter().enumerate() {
+        if !reachable[id] {
+            continue;
+        }
+        for inst in block.insts.iter_mut() {
+            if let Inst::Phi(_, _, incoming) = inst {
+                incoming.retain(|(predecessor, _)| reachable[predecessor.0]);
+                for (predecessor, _) in incoming.iter_mut() {
+                    *predecessor = renumber[predecessor.0].unwrap();
+                }
+            }
+        }
+        for successor in block.terminator.successors_mut() {
+            *successor = renumber[successor.0].unwrap();
+        }
+        function.blocks.push(block);
+    }
+    true
+}
+
+// 結果を使わなければ取り除いてよい命令
+fn is_pure(inst: &Inst) -> bool {
+    match inst {
+        Inst::Copy(_, _, _)
+        | Inst::Binary(_, _, _, _, _)
+        | Inst::Compare(_, _, _, _, _)
+        | Inst::Convert(_, _, _, _, _)
+        | Inst::Phi(_, _, _) => true,
+        Inst::Load(_, _, _, volatile) => !volatile,
+        _ => false,
+    }
+}
+
+// 使われない値を計算する命令を取り除く
+// 副作用のある命令と終端命令から使われる値をたどって印を付け、残りを消す
+pub fn remove_dead_values(function: &mut Function) -> bool {
+    let mut definitions: Vec<Option<&Inst>> = vec![None; function.temp_types.len()];
+    let mut worklist: Vec<Temp> = Vec::new();
+    for block in function.blocks.iter() {
+        for inst in block.insts.iter() {
+            if let Some(dst) = inst.dst() {
+                definitions[dst.0] = Some(inst);
+            }
+            if !is_pure(inst) {
+                worklist.extend(inst_uses(inst));
+            }
+        }
+        worklist.extend(temps(block.terminator.operands()));
+    }
+    let mut live: HashSet<Temp> = HashSet::new();
+    while let Some(temp) = worklist.pop() {
+        if !live.insert(temp) {
+            continue;
+        }
+        if let Some(inst) = definitions[temp.0] {
+            worklist.extend(inst_uses(inst));
+        }
+    }
+    let mut changed = false;
+    for block in function.blocks.iter_mut() {
+        let before = block.insts.len();
+        block.insts.retain(|inst| match inst.dst() {
+            Some(dst) if is_pure(inst) => live.contains(&dst),
+            _ => true,
+        });
+        changed |= block.insts.len() != before;
+    }
+    changed
+}
+
+// 同じブロックで読まれる前に上書きされるローカル変数への書き込みを取り除く
+pub fn remove_dead_stores(function: &mut Function) -> bool {
+    let mut changed = false;
+    for block in function.blocks.iter_mut() {
+        // 後で書き込まれる (offset, 大きさ)
+        let mut overwritten: Vec<(usize, usize)> = Vec::new();
+        let mut dead: Vec<usize> = Vec::new();
+        for (index, inst) in block.insts.iter().enumerate().rev() {
+            match inst {
+                Inst::Store(typename, Operand::Local(offset), _, false) => {
+                    // 後の書き込みが同じ位置から同じ大きさ以上を覆う
+                    if overwritten
+                        .iter()
+                        .any(|(other, size)| other == offset && *size >= typename.size())
+                    {
+                        dead.push(index);
+                    } else {
+                        overwritten.push((*offset, typename.size()));
+                    }
+                }
+                // 計算だけの命令はメモリを読まない
+                Inst::Copy(_, _, _)
+                | Inst::Binary(_, _, _, _, _)
+                | Inst::Compare(_, _, _, _, _)
+                | Inst::Convert(_, _, _, _, _)
+                | Inst::Phi(_, _, _) => {}
+                // 読むかもしれない
+                _ => overwritten.clear(),
+            }
+        }
+        changed |= !dead.is_empty();
+        for index in dead {
+            block.insts.remove(index);
+        }
+    }
+    changed
+}
+
+// 到達しないブロック、使われない値、上書きされる書き込みを取り除く
+pub fn dce(function: &mut Function) -> bool {
+    let mut changed = remove_unreachable_blocks(function);
+    changed |= remove_dead_stores(function);
+    changed |= remove_dead_values(function);
+    changed
+}
+
+// どこからも参照されないstatic関数を取り除く
+pub fn remove_unused_functions(module: &mut Module) -> bool {
+    // 外部から見える関数と静的変数の初期値から参照をたどる
+    let mut used: HashSet<String> = HashSet::new();
+    let mut worklist: Vec<String> = module
+        .functions
+        .iter()
+        .filter(|function| !function.is_static)
+        .map(|function| function.name.clone())
+        .chain(
+            module
+                .globals
+                .iter()
+                .filter_map(|global| match &global.initializer {
+                    Some(Operand::Function(name)) => Some(name.clone()),
+                    _ => None,
+                }),
+        )
+        .collect();
+    while let Some(name) = worklist.pop() {
+        if !used.insert(name.clone()) {
+            continue;
+        }
+        let function = match module
+            .functions
+            .iter()
+            .find(|function| function.name == name)
+        {
+            Some(function) => function,
+            None => continue,
+        };
+        for block in function.blocks.iter() {
+            let operands = block
+                .insts
+                .iter()
+                .flat_map(|inst| inst.operands())
+                .chain(block.terminator.operands());
+            for operand in operands {
+                if let Operand::Function(callee) = operand {
+                    worklist.push(callee.clone());
+                }
+            }
+        }
+    }
+    let before = module.functions.len();
+    module
+        .functions
+        .retain(|function| used.contains(&function.name));
+    module.functions.len() != before
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::dce::{dce, remove_unused_functions};
+    use crate::ir::fold::fold;
+    use crate::ir::lower::lower;
+    use crate::ir::sprint_module;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn dce_test() {
+        let program = parse(tokenize(
+            "static int g() { return 1; } static int h() { return g(); } int f(int a) { int x; x = a * 2; if (0) h(); return a; x = 3; }"
+                .to_string(),
+        ));
+        let mut module = lower(&program, false);
+        for function in module.functions.iter_mut() {
+            ssa::construct(function);
+            fold(function);
+            dce(function);
+        }
+        remove_unused_functions(&mut module);
+        assert_eq!(
+            sprint_module(&module),
+            "function @f(i32 %0) -> i32 {
+bb0:
+    jmp bb1
+bb1:
+    ret i32 %0
+}
+"
+        );
+    }
+
+    #[test]
+    fn function_address_in_global_test() {
+        let program = parse(tokenize(
+            "static int g() { return 1; } static int h() { return 2; } int (*table)() = g;"
+                .to_string(),
+        ));
+        let mut module = lower(&program, false);
+        remove_unused_functions(&mut module);
+        // 静的変数の初期値で参照されるgは残る
+        let names: Vec<&str> = module
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["g"]);
+    }
+}
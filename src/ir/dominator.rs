@@ -0,0 +1,141 @@
+use super::{BlockId, Function};
+
+// 入口から到達できるブロックの逆後順
+pub fn reverse_postorder(function: &Function) -> Vec<BlockId> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut postorder: Vec<BlockId> = Vec::new();
+    // (ブロック, 次に調べる後続ブロックの番号)
+    let mut stack: Vec<(BlockId, usize)> = vec![(BlockId(0), 0)];
+    visited[0] = true;
+    while let Some((block, index)) = stack.pop() {
+        let successors = function.block(block).terminator.successors();
+        if index < successors.len() {
+            stack.push((block, index + 1));
+            let successor = successors[index];
+            if !visited[successor.0] {
+                visited[successor.0] = true;
+                stack.push((successor, 0));
+            }
+        } else {
+            postorder.push(block);
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+// 支配木 (Cooper, Harvey, Kennedy の反復法で求める)
+pub struct DominatorTree {
+    pub idom: Vec<Option<BlockId>>, // 入口と到達できないブロックはNone
+    pub children: Vec<Vec<BlockId>>,
+    pub reverse_postorder: Vec<BlockId>,
+    order: Vec<Option<usize>>, // 逆後順での番号 (到達できなければNone)
+}
+
+impl DominatorTree {
+    pub fn new(function: &Function) -> DominatorTree {
+        let reverse_postorder = reverse_postorder(function);
+        let mut order = vec![None; function.blocks.len()];
+        for (index, block) in reverse_postorder.iter().enumerate() {
+            order[block.0] = Some(index);
+        }
+        let predecessors = function.predecessors();
+        // 入口の直接支配節は自分自身として計算し、最後にNoneに戻す
+        let mut idom: Vec<Option<BlockId>> = vec![None; function.blocks.len()];
+        idom[0] = Some(BlockId(0));
+        let intersect = |idom: &Vec<Option<BlockId>>, mut lhs: BlockId, mut rhs: BlockId| {
+            while lhs != rhs {
+                while order[lhs.0] > order[rhs.0] {
+                    lhs = idom[lhs.0].unwrap();
+                }
+                while order[rhs.0] > order[lhs.0] {
+                    rhs = idom[rhs.0].unwrap();
+                }
+            }
+            lhs
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in reverse_postorder.iter().skip(1) {
+                let mut new_idom: Option<BlockId> = None;
+                for predecessor in predecessors[block.0].iter() {
+                    if idom[predecessor.0].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *predecessor,
+                        Some(current) => intersect(&idom, *predecessor, current),
+                    });
+                }
+                if idom[block.0] != new_idom {
+                    idom[block.0] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        let mut children = vec![Vec::new(); function.blocks.len()];
+        for block in reverse_postorder.iter() {
+            if let Some(parent) = idom[block.0] {
+                children[parent.0].push(*block);
+            }
+        }
+        DominatorTree {
+            idom,
+            children,
+            reverse_postorder,
+            order,
+        }
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.order[block.0].is_some()
+    }
+
+    // lhsがrhsを支配するか
+    pub fn dominates(&self, lhs: BlockId, rhs: BlockId) -> bool {
+        if !self.is_reachable(lhs) || !self.is_reachable(rhs) {
+            return false;
+        }
+        let mut block = rhs;
+        loop {
+            if block == lhs {
+                return true;
+            }
+            match self.idom[block.0] {
+                Some(parent) => block = parent,
+                None => return false,
+            }
+        }
+    }
+
+    // 支配辺境
+    pub fn frontiers(&self, function: &Function) -> Vec<Vec<BlockId>> {
+        let predecessors = function.predecessors();
+        let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); function.blocks.len()];
+        for block in self.reverse_postorder.iter() {
+            let reachable: Vec<BlockId> = predecessors[block.0]
+                .iter()
+                .filter(|predecessor| self.is_reachable(**predecessor))
+                .cloned()
+                .collect();
+            if reachable.len() < 2 {
+                continue;
+            }
+            for predecessor in reachable {
+                let mut runner = Some(predecessor);
+                while let Some(current) = runner {
+                    if Some(current) == self.idom[block.0] {
+                        break;
+                    }
+                    if !frontiers[current.0].contains(block) {
+                        frontiers[current.0].push(*block);
+                    }
+                    runner = self.idom[current.0];
+                }
+            }
+        }
+        frontiers
+    }
+}
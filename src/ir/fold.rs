@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use super::dominator::reverse_postorder;
+use super::{BinaryOp, CompareOp, ConvertOp, Function, Inst, Operand, Temp, Terminator, Type};
+
+// 型の幅で切り詰めて符号拡張する (中間表現の整数定数はこの形にそろえる)
+pub fn sign_extend(n: i64, typename: &Type) -> i64 {
+    match typename.size() {
+        1 => n as i8 as i64,
+        2 => n as i16 as i64,
+        4 => n as i32 as i64,
+        _ => n,
+    }
+}
+
+// 型の幅で切り詰めて0拡張する
+pub fn zero_extend(n: i64, typename: &Type) -> u64 {
+    match typename.size() {
+        1 => n as u8 as u64,
+        2 => n as u16 as u64,
+        4 => n as u32 as u64,
+        _ => n as u64,
+    }
+}
+
+// 4byteの浮動小数点数はfloatの精度に丸める
+fn round_float(x: f64, typename: &Type) -> f64 {
+    if let Type::F32 = typename {
+        x as f32 as f64
+    } else {
+        x
+    }
+}
+
+// 定数同士の演算 (実行時に未定義動作やトラップになるものは畳み込まない)
+pub fn eval_binary(op: BinaryOp, typename: &Type, lhs: &Operand, rhs: &Operand) -> Option<Operand> {
+    match (lhs, rhs) {
+        (Operand::Int(l), Operand::Int(r)) => {
+            let n = match op {
+                BinaryOp::Add => l.wrapping_add(*r),
+                BinaryOp::Sub => l.wrapping_sub(*r),
+                BinaryOp::Mul => l.wrapping_mul(*r),
+                BinaryOp::SDiv => {
+                    let (l, r) = (sign_extend(*l, typename), sign_extend(*r, typename));
+                    // 0除算と最小値 / -1 はidivが例外になる
+                    let min = sign_extend(i64::MIN >> (64 - 8 * typename.size()), typename);
+                    if r == 0 || (r == -1 && l == min) {
+                        return None;
+                    }
+                    l / r
+                }
+                BinaryOp::UDiv => {
+                    let (l, r) = (zero_extend(*l, typename), zero_extend(*r, typename));
+                    if r == 0 {
+                        return None;
+                    }
+                    (l / r) as i64
+                }
+                _ => return None,
+            };
+            Some(Operand::Int(sign_extend(n, typename)))
+        }
+        (Operand::Float(l), Operand::Float(r)) => {
+            let (l, r) = (round_float(*l, typename), round_float(*r, typename));
+            let x = match (op, typename) {
+                // floatの演算はfloatで行う
+                (BinaryOp::FAdd, Type::F32) => (l as f32 + r as f32) as f64,
+                (BinaryOp::FSub, Type::F32) => (l as f32 - r as f32) as f64,
+                (BinaryOp::FMul, Type::F32) => (l as f32 * r as f32) as f64,
+                (BinaryOp::FDiv, Type::F32) => (l as f32 / r as f32) as f64,
+                (BinaryOp::FAdd, _) => l + r,
+                (BinaryOp::FSub, _) => l - r,
+                (BinaryOp::FMul, _) => l * r,
+                (BinaryOp::FDiv, _) => l / r,
+                _ => return None,
+            };
+            Some(Operand::Float(x))
+        }
+        _ => None,
+    }
+}
+
+pub fn eval_compare(
+    op: CompareOp,
+    typename: &Type,
+    lhs: &Operand,
+    rhs: &Operand,
+) -> Option<Operand> {
+    let flag = match (lhs, rhs) {
+        (Operand::Int(l), Operand::Int(r)) => {
+            let (sl, sr) = (sign_extend(*l, typename), sign_extend(*r, typename));
+            let (ul, ur) = (zero_extend(*l, typename), zero_extend(*r, typename));
+            match op {
+                CompareOp::Eq => sl == sr,
+                CompareOp::Ne => sl != sr,
+                CompareOp::Slt => sl < sr,
+                CompareOp::Sle => sl <= sr,
+                CompareOp::Ult => ul < ur,
+                CompareOp::Ule => ul <= ur,
+                _ => return None,
+            }
+        }
+        (Operand::Float(l), Operand::Float(r)) => {
+            let (l, r) = (round_float(*l, typename), round_float(*r, typename));
+            // NaNとの比較は != だけが真
+            match op {
+                CompareOp::FEq => l == r,
+                CompareOp::FNe => l != r,
+                CompareOp::FLt => l < r,
+                CompareOp::FLe => l <= r,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(Operand::Int(flag as i64))
+}
+
+pub fn eval_convert(op: ConvertOp, from: &Type, to: &Type, value: &Operand) -> Option<Operand> {
+    match (op, value) {
+        (ConvertOp::Sext, Operand::Int(n)) => {
+            Some(Operand::Int(sign_extend(sign_extend(*n, from), to)))
+        }
+        (ConvertOp::Zext, Operand::Int(n)) => {
+            Some(Operand::Int(sign_extend(zero_extend(*n, from) as i64, to)))
+        }
+        (ConvertOp::Trunc, Operand::Int(n)) => Some(Operand::Int(sign_extend(*n, to))),
+        (ConvertOp::SiToFp, Operand::Int(n)) => {
+            let n = sign_extend(*n, from);
+            Some(Operand::Float(if let Type::F32 = to {
+                n as f32 as f64
+            } else {
+                n as f64
+            }))
+        }
+        (ConvertOp::UiToFp, Operand::Int(n)) => {
+            let n = zero_extend(*n, from);
+            Some(Operand::Float(if let Type::F32 = to {
+                n as f32 as f64
+            } else {
+                n as f64
+            }))
+        }
+        (ConvertOp::FpToSi, Operand::Float(x)) => {
+            let x = round_float(*x, from).trunc();
+            // 範囲外の変換は未定義なので実行時に任せる
+            let limit = 2f64.powi(8 * to.size() as i32 - 1);
+            if x.is_nan() || x < -limit || x >= limit {
+                return None;
+            }
+            Some(Operand::Int(x as i64))
+        }
+        (ConvertOp::FpToUi, Operand::Float(x)) => {
+            let x = round_float(*x, from).trunc();
+            let limit = 2f64.powi(8 * to.size() as i32);
+            if x.is_nan() || x < 0.0 || x >= limit {
+                return None;
+            }
+            Some(Operand::Int(sign_extend(x as u64 as i64, to)))
+        }
+        (ConvertOp::FpExt, Operand::Float(x)) => Some(Operand::Float(round_float(*x, from))),
+        (ConvertOp::FpTrunc, Operand::Float(x)) => Some(Operand::Float(*x as f32 as f64)),
+        _ => None,
+    }
+}
+
+// 命令の結果が定数 (またはコピー元の値) に決まるならその値
+fn eval_inst(inst: &Inst) -> Option<Operand> {
+    match inst {
+        Inst::Copy(_, _, src) => Some(src.clone()),
+        Inst::Binary(_, op, typename, lhs, rhs) => eval_binary(*op, typename, lhs, rhs),
+        Inst::Compare(_, op, typename, lhs, rhs) => eval_compare(*op, typename, lhs, rhs),
+        Inst::Convert(_, op, from, to, src) => eval_convert(*op, from, to, src),
+        Inst::Phi(dst, _, incoming) => {
+            // 自分自身以外の値が全て同じならその値
+            let mut values = incoming
+                .iter()
+                .map(|(_, value)| value)
+                .filter(|value| **value != Operand::Temp(*dst));
+            let first = values.next()?;
+            if values.all(|value| value == first) {
+                Some(first.clone())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+// SSA形式の関数で定数の演算を畳み込み、定数で決まる分岐を無条件ジャンプにする
+// 変化があればtrueを返す
+pub fn fold(function: &mut Function) -> bool {
+    let mut values: HashMap<Temp, Operand> = HashMap::new();
+    let mut changed_any = false;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in function.blocks.iter_mut() {
+            let mut kept: Vec<Inst> = Vec::new();
+            for mut inst in std::mem::take(&mut block.insts) {
+                for operand in inst.operands_mut() {
+                    substitute(operand, &values);
+                }
+                match (inst.dst(), eval_inst(&inst)) {
+                    (Some(dst), Some(value)) => {
+                        values.insert(dst, value);
+                        changed = true;
+                    }
+                    _ => kept.push(inst),
+                }
+            }
+            block.insts = kept;
+            for operand in block.terminator.operands_mut() {
+                substitute(operand, &values);
+            }
+        }
+        for id in 0..function.blocks.len() {
+            if let Terminator::Branch(Operand::Int(n), typename, then_block, else_block) =
+                function.blocks[id].terminator.clone()
+            {
+                let (taken, dropped) = if sign_extend(n, &typename) != 0 {
+                    (then_block, else_block)
+                } else {
+                    (else_block, then_block)
+                };
+                function.blocks[id].terminator = Terminator::Jump(taken);
+                // 通らなくなった辺のphiの引数を1つ取り除く
+                for inst in function.block_mut(dropped).insts.iter_mut() {
+                    if let Inst::Phi(_, _, incoming) = inst {
+                        if let Some(index) = incoming.iter().position(|(block, _)| block.0 == id) {
+                            incoming.remove(index);
+                        }
+                    }
+                }
+                changed = true;
+            }
+        }
+        // 到達しなくなったブロックから来るphiの引数を取り除く
+        let mut reachable = vec![false; function.blocks.len()];
+        for block in reverse_postorder(function) {
+            reachable[block.0] = true;
+        }
+        for block in function.blocks.iter_mut() {
+            for inst in block.insts.iter_mut() {
+                if let Inst::Phi(_, _, incoming) = inst {
+                    let before = incoming.len();
+                    incoming.retain(|(predecessor, _)| reachable[predecessor.0]);
+                    changed |= incoming.len() != before;
+                }
+            }
+        }
+        changed_any |= changed;
+    }
+    changed_any
+}
+
+fn substitute(operand: &mut Operand, values: &HashMap<Temp, Operand>) {
+    while let Operand::Temp(temp) = operand {
+        match values.get(temp) {
+            Some(value) => *operand = value.clone(),
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::fold::fold;
+    use crate::ir::lower::lower;
+    use crate::ir::sprint_function;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn fold_test() {
+        let program = parse(tokenize(
+            "int f() { unsigned int u; int x; u = 0 - 1; x = 1 + 2 * 3; if (u / 2 < 0) x = 0; return x + u / 2147483647; }"
+                .to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        ssa::construct(&mut function);
+        fold(&mut function);
+        // 4294967295 / 2 は符号なしの割り算、0より小さくはならない
+        assert_eq!(
+            sprint_function(&function),
+            "function @f() -> i32 {
+bb0:
+    jmp bb2
+bb1:
+    jmp bb2
+bb2:
+    ret i32 9
+}
+"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use super::dominator::DominatorTree;
+use super::{sprint_operand, BinaryOp, BlockId, CompareOp, Function, Inst, Operand, Temp, Type};
+
+// 同じ値になる式に付けるキー (被演算子は番号付け済みの値で書く)
+fn expression_key(inst: &Inst) -> Option<String> {
+    match inst {
+        Inst::Binary(_, op, typename, lhs, rhs) => {
+            let (mut lhs, mut rhs) = (sprint_operand(lhs), sprint_operand(rhs));
+            // 可換な演算は被演算子の順序をそろえる
+            let commutative = matches!(
+                op,
+                BinaryOp::Add | BinaryOp::Mul | BinaryOp::FAdd | BinaryOp::FMul
+            );
+            if commutative && lhs > rhs {
+                std::mem::swap(&mut lhs, &mut rhs);
+            }
+            Some(format!("{:?} {:?} {}, {}", op, typename, lhs, rhs))
+        }
+        Inst::Compare(_, op, typename, lhs, rhs) => {
+            let (mut lhs, mut rhs) = (sprint_operand(lhs), sprint_operand(rhs));
+            let commutative = matches!(
+                op,
+                CompareOp::Eq | CompareOp::Ne | CompareOp::FEq | CompareOp::FNe
+            );
+            if commutative && lhs > rhs {
+                std::mem::swap(&mut lhs, &mut rhs);
+            }
+            Some(format!("{:?} {:?} {}, {}", op, typename, lhs, rhs))
+        }
+        Inst::Convert(_, op, from, to, value) => Some(format!(
+            "{:?} {:?} {} to {:?}",
+            op,
+            from,
+            sprint_operand(value),
+            to
+        )),
+        _ => None,
+    }
+}
+
+// 2つの読み書きが同じメモリに触れうるか
+// 別々のグローバル変数と、重ならないローカル変数の領域は重ならない
+pub fn may_alias(lhs: &Operand, lhs_size: usize, rhs: &Operand, rhs_size: usize) -> bool {
+    match (lhs, rhs) {
+        (Operand::Global(lhs), Operand::Global(rhs)) => lhs == rhs,
+        (Operand::Local(lhs), Operand::Local(rhs)) => {
+            // 領域はbase-offsetから大きさの分
+            *lhs < *rhs + lhs_size && *rhs < *lhs + rhs_size
+        }
+        (Operand::Local(_), Operand::Global(_)) | (Operand::Global(_), Operand::Local(_)) => false,
+        _ => true,
+    }
+}
+
+// ブロックの中で読み出した (または書き込んだ) メモリの値
+struct Memory {
+    // (型, アドレス, 値)
+    values: Vec<(Type, Operand, Operand)>,
+}
+
+impl Memory {
+    fn find(&self, typename: &Type, address: &Operand) -> Option<Operand> {
+        self.values
+            .iter()
+            .find(|(known_type, known_address, _)| {
+                known_type == typename && known_address == address
+            })
+            .map(|(_, _, value)| value.clone())
+    }
+
+    // addressへの書き込みで変わりうる値を忘れる
+    fn clobber(&mut self, typename: &Type, address: &Operand) {
+        self.values.retain(|(known_type, known_address, _)| {
+            !may_alias(known_address, known_type.size(), address, typename.size())
+        });
+    }
+}
+
+struct Numbering {
+    // 式のキー -> 支配するブロックで計算済みの値
+    expressions: HashMap<String, Operand>,
+    // 取り除いた命令の結果 -> 代わりに使う値
+    replace: HashMap<Temp, Operand>,
+}
+
+impl Numbering {
+    fn rewrite(&self, operand: &mut Operand) {
+        while let Operand::Temp(temp) = operand {
+            match self.replace.get(temp) {
+                Some(value) => *operand = value.clone(),
+                None => break,
+            }
+        }
+    }
+
+    // 支配木を前順にたどり、支配するブロックで計算した式を再利用する
+    fn number(&mut self, function: &mut Function, tree: &DominatorTree, block: BlockId) {
+        let mut inserted: Vec<String> = Vec::new();
+        let mut memory = Memory { values: Vec::new() };
+        let insts = std::mem::take(&mut function.block_mut(block).insts);
+        let mut kept: Vec<Inst> = Vec::new();
+        for mut inst in insts {
+            if !matches!(inst, Inst::Phi(_, _, _)) {
+                for operand in inst.operands_mut() {
+                    self.rewrite(operand);
+                }
+            }
+            if let (Some(dst), Some(key)) = (inst.dst(), expression_key(&inst)) {
+                match self.expressions.get(&key) {
+                    Some(value) => {
+                        self.replace.insert(dst, value.clone());
+                        continue;
+                    }
+                    None => {
+                        self.expressions.insert(key.clone(), Operand::Temp(dst));
+                        inserted.push(key);
+                    }
+                }
+            }
+            match &inst {
+                Inst::Load(dst, typename, address, false) => {
+                    if let Some(value) = memory.find(typename, address) {
+                        self.replace.insert(*dst, value);
+                        continue;
+                    }
+                    memory
+                        .values
+                        .push((*typename, address.clone(), Operand::Temp(*dst)));
+                }
+                Inst::Store(typename, address, value, volatile) => {
+                    memory.clobber(typename, address);
+                    // 書き込んだ値はそのまま読み出せる
+                    if !volatile {
+                        memory
+                            .values
+                            .push((*typename, address.clone(), value.clone()));
+                    }
+                }
+                // 呼び出し先や可変長引数の操作はどこに書き込むか分からない
+                Inst::Call(_, _, _, _)
+                | Inst::VaStart(_)
+                | Inst::VaArg(_, _, _)
+                | Inst::VaCopy(_, _) => memory.values.clear(),
+                _ => {}
+            }
+            kept.push(inst);
+        }
+        function.block_mut(block).insts = kept;
+        for child in tree.children[block.0].clone() {
+            self.number(function, tree, child);
+        }
+        for key in inserted {
+            self.expressions.remove(&key);
+        }
+    }
+}
+
+// 支配木に沿った値番号付けで共通部分式を取り除く
+// メモリの読み出しはブロックの中で、間に書き込みや呼び出しがなければ再利用する
+pub fn gvn(function: &mut Function) -> bool {
+    let tree = DominatorTree::new(function);
+    let mut numbering = Numbering {
+        expressions: HashMap::new(),
+        replace: HashMap::new(),
+    };
+    numbering.number(function, &tree, BlockId(0));
+    if numbering.replace.is_empty() {
+        return false;
+    }
+    // phiと終端命令は後から来るブロックの分もまとめて置き換える
+    for block in function.blocks.iter_mut() {
+        for inst in block.insts.iter_mut() {
+            for operand in inst.operands_mut() {
+                numbering.rewrite(operand);
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            numbering.rewrite(operand);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::gvn::gvn;
+    use crate::ir::lower::lower;
+    use crate::ir::sprint_function;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn gvn_test() {
+        let program = parse(tokenize(
+            "int g(); int f(int *a, int i) { int x; x = a[i] + a[i]; a[i] = x; x = x + a[i]; g(); return x + a[i]; }"
+                .to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        ssa::construct(&mut function);
+        gvn(&mut function);
+        // 書き込んだ値は読み直さず、呼び出しの後は読み直す
+        assert_eq!(
+            sprint_function(&function),
+            "function @f(ptr %0, i32 %1) -> i32 {
+bb0:
+    %4 = sext i32 %1 to i64
+    %5 = mul i64 %4, 4
+    %6 = add ptr %0, %5
+    %7 = load i32 %6
+    %14 = add i32 %7, %7
+    store i32 %6, %14
+    %28 = add i32 %14, %14
+    %29 = call i32 @g(...)
+    %36 = load i32 %6
+    %37 = add i32 %28, %36
+    ret i32 %37
+}
+"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+
+use super::fold::sign_extend;
+use super::loops::{insert_preheaders, preheader, Loop, LoopForest};
+use super::{BinaryOp, BlockId, ConvertOp, Function, Inst, Operand, Temp, Type};
+
+// 基本帰納変数 (ヘッダのphiで、1回の繰り返しごとに定数だけ増える)
+struct Induction {
+    phi: Temp,
+    typename: Type,
+    init: Operand,
+    step: i64,
+    // 増やした値を計算する命令の位置
+    update: (BlockId, Temp),
+}
+
+// 帰納変数に施す演算
+#[derive(Clone)]
+enum Step {
+    Sext(Type, Type),
+    // (演算, 型, ループ不変の値, 帰納変数の側が左か)
+    Binary(BinaryOp, Type, Operand, bool),
+}
+
+// 帰納変数から一次式で決まる値
+#[derive(Clone)]
+struct Derived {
+    induction: usize,
+    steps: Vec<Step>,
+    typename: Type,
+}
+
+impl Derived {
+    // 掛け算を含めば、足し算だけで更新すると安くなる
+    fn has_mul(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|step| matches!(step, Step::Binary(BinaryOp::Mul, _, _, _)))
+    }
+
+    // 帰納変数が1回の繰り返しでstepだけ増えるときに増える量
+    fn stride(&self, step: i64) -> i64 {
+        let stride = self.steps.iter().fold(step, |stride, op| match op {
+            Step::Sext(from, _) => sign_extend(stride, from),
+            Step::Binary(BinaryOp::Mul, _, Operand::Int(n), _) => stride.wrapping_mul(*n),
+            Step::Binary(BinaryOp::Sub, _, _, false) => stride.wrapping_neg(),
+            Step::Binary(_, _, _, _) => stride,
+        });
+        sign_extend(stride, &self.typename)
+    }
+}
+
+fn find_inductions(
+    function: &Function,
+    lp: &Loop,
+    preheader: BlockId,
+    definitions: &HashMap<Temp, (BlockId, Inst)>,
+) -> Vec<Induction> {
+    let latch = match lp.latches.as_slice() {
+        [latch] => *latch,
+        _ => return Vec::new(),
+    };
+    let mut inductions: Vec<Induction> = Vec::new();
+    for inst in function.block(lp.header).insts.iter() {
+        let (phi, typename, incoming) = match inst {
+            Inst::Phi(phi, typename, incoming) => (*phi, *typename, incoming),
+            _ => break,
+        };
+        let (init, next) = match incoming.as_slice() {
+            [(first, init), (second, Operand::Temp(next))]
+            | [(second, Operand::Temp(next)), (first, init)]
+                if *first == preheader && *second == latch =>
+            {
+                (init.clone(), *next)
+            }
+            _ => continue,
+        };
+        let step = match definitions.get(&next) {
+            Some((_, Inst::Binary(_, BinaryOp::Add, _, Operand::Temp(lhs), Operand::Int(n))))
+            | Some((_, Inst::Binary(_, BinaryOp::Add, _, Operand::Int(n), Operand::Temp(lhs))))
+                if *lhs == phi =>
+            {
+                *n
+            }
+            Some((_, Inst::Binary(_, BinaryOp::Sub, _, Operand::Temp(lhs), Operand::Int(n))))
+                if *lhs == phi =>
+            {
+                n.wrapping_neg()
+            }
+            _ => continue,
+        };
+        if typename.is_float() || step == 0 {
+            continue;
+        }
+        inductions.push(Induction {
+            phi,
+            typename,
+            init,
+            step,
+            update: (definitions[&next].0, next),
+        });
+    }
+    inductions
+}
+
+// ループの中の命令のうち、帰納変数の一次式になるものを調べる
+fn find_derived(
+    function: &Function,
+    lp: &Loop,
+    inductions: &[Induction],
+    definitions: &HashMap<Temp, (BlockId, Inst)>,
+) -> HashMap<Temp, Derived> {
+    let is_invariant = |operand: &Operand| match operand {
+        Operand::Temp(temp) => match definitions.get(temp) {
+            Some((block, _)) => !lp.contains(*block),
+            None => true,
+        },
+        Operand::Float(_) => false,
+        _ => true,
+    };
+    let mut derived: HashMap<Temp, Derived> = HashMap::new();
+    for (index, induction) in inductions.iter().enumerate() {
+        derived.insert(
+            induction.phi,
+            Derived {
+                induction: index,
+                steps: Vec::new(),
+                typename: induction.typename,
+            },
+        );
+    }
+    // 逆後順にたどれば、使う値は先に調べ終わっている
+    for block in lp.blocks.iter() {
+        for inst in function.block(*block).insts.iter() {
+            let (dst, base, step) = match inst {
+                Inst::Convert(dst, ConvertOp::Sext, from, to, Operand::Temp(src)) => {
+                    (*dst, *src, Step::Sext(*from, *to))
+                }
+                Inst::Binary(dst, BinaryOp::Mul, typename, Operand::Temp(src), Operand::Int(n))
+                | Inst::Binary(dst, BinaryOp::Mul, typename, Operand::Int(n), Operand::Temp(src)) => {
+                    (
+                        *dst,
+                        *src,
+                        Step::Binary(BinaryOp::Mul, *typename, Operand::Int(*n), true),
+                    )
+                }
+                Inst::Binary(dst, op @ (BinaryOp::Add | BinaryOp::Sub), typename, lhs, rhs) => {
+                    match (lhs, rhs) {
+                        (Operand::Temp(src), other)
+                            if derived.contains_key(src) && is_invariant(other) =>
+                        {
+                            (
+                                *dst,
+                                *src,
+                                Step::Binary(*op, *typename, other.clone(), true),
+                            )
+                        }
+                        (other, Operand::Temp(src))
+                            if derived.contains_key(src) && is_invariant(other) =>
+                        {
+                            (
+                                *dst,
+                                *src,
+                                Step::Binary(*op, *typename, other.clone(), false),
+                            )
+                        }
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let mut value = match derived.get(&base) {
+                Some(value) => value.clone(),
+                None => continue,
+            };
+            value.typename = match step {
+                Step::Sext(_, to) => to,
+                Step::Binary(_, typename, _, _) => typename,
+            };
+            value.steps.push(step);
+            derived.insert(dst, value);
+        }
+    }
+    derived
+}
+
+// 帰納変数の値からstepsを順に計算する命令を前置ブロックの末尾に足す
+fn materialize(
+    function: &mut Function,
+    preheader: BlockId,
+    value: &Derived,
+    init: &Operand,
+    from: Type,
+) -> Operand {
+    let mut current = init.clone();
+    let mut typename = from;
+    for step in value.steps.iter() {
+        let dst = match step {
+            Step::Sext(_, to) => {
+                let dst = function.new_temp(*to);
+                function.block_mut(preheader).insts.push(Inst::Convert(
+                    dst,
+                    ConvertOp::Sext,
+                    typename,
+                    *to,
+                    current,
+                ));
+                typename = *to;
+                dst
+            }
+            Step::Binary(op, op_type, other, left) => {
+                let dst = function.new_temp(*op_type);
+                let (lhs, rhs) = if *left {
+                    (current, other.clone())
+                } else {
+                    (other.clone(), current)
+                };
+                function
+                    .block_mut(preheader)
+                    .insts
+                    .push(Inst::Binary(dst, *op, *op_type, lhs, rhs));
+                typename = *op_type;
+                dst
+            }
+        };
+        current = Operand::Temp(dst);
+    }
+    current
+}
+
+fn reduce_loop(function: &mut Function, lp: &Loop) -> bool {
+    let preheader = match preheader(function, lp) {
+        Some(preheader) => preheader,
+        None => return false,
+    };
+    let mut definitions: HashMap<Temp, (BlockId, Inst)> = HashMap::new();
+    for (id, block) in function.blocks.iter().enumerate() {
+        for inst in block.insts.iter() {
+            if let Some(dst) = inst.dst() {
+                definitions.insert(dst, (BlockId(id), inst.clone()));
+            }
+        }
+    }
+    let inductions = find_inductions(function, lp, preheader, &definitions);
+    if inductions.is_empty() {
+        return false;
+    }
+    let derived = find_derived(function, lp, &inductions, &definitions);
+
+    // 一次式の計算の途中でなく、他の命令や終端命令で使われる値だけを置き換える
+    let mut candidates: Vec<Temp> = Vec::new();
+    for block in function.blocks.iter() {
+        let mut users: Vec<(Option<Temp>, Vec<&Operand>)> = block
+            .insts
+            .iter()
+            .map(|inst| (inst.dst(), inst.operands()))
+            .collect();
+        users.push((None, block.terminator.operands()));
+        for (dst, operands) in users {
+            let inner = dst.is_some_and(|dst| derived.contains_key(&dst));
+            for operand in operands {
+                if let Operand::Temp(temp) = operand {
+                    let reducible = derived.get(temp).is_some_and(|value| value.has_mul());
+                    if reducible && !inner && !candidates.contains(temp) {
+                        candidates.push(*temp);
+                    }
+                }
+            }
+        }
+    }
+    candidates.sort_unstable();
+
+    let latch = lp.latches[0];
+    let mut replace: HashMap<Temp, Operand> = HashMap::new();
+    for temp in candidates {
+        let value = &derived[&temp];
+        let induction = &inductions[value.induction];
+        let stride = value.stride(induction.step);
+        let start = materialize(
+            function,
+            preheader,
+            value,
+            &induction.init,
+            induction.typename,
+        );
+        // 前の繰り返しの値に一定の量を足して求める
+        let phi = function.new_temp(value.typename);
+        let next = function.new_temp(value.typename);
+        let count = function
+            .block(lp.header)
+            .insts
+            .iter()
+            .take_while(|inst| matches!(inst, Inst::Phi(_, _, _)))
+            .count();
+        function.block_mut(lp.header).insts.insert(
+            count,
+            Inst::Phi(
+                phi,
+                value.typename,
+                vec![(preheader, start), (latch, Operand::Temp(next))],
+            ),
+        );
+        let (update_block, update) = induction.update;
+        let insts = &mut function.block_mut(update_block).insts;
+        let position = insts
+            .iter()
+            .position(|inst| inst.dst() == Some(update))
+            .unwrap();
+        insts.insert(
+            position + 1,
+            Inst::Binary(
+                next,
+                BinaryOp::Add,
+                value.typename,
+                Operand::Temp(phi),
+                Operand::Int(stride),
+            ),
+        );
+        replace.insert(temp, Operand::Temp(phi));
+    }
+    if replace.is_empty() {
+        return false;
+    }
+    for block in function.blocks.iter_mut() {
+        let operands = block
+            .insts
+            .iter_mut()
+            .flat_map(|inst| inst.operands_mut())
+            .chain(block.terminator.operands_mut());
+        for operand in operands {
+            if let Operand::Temp(temp) = operand {
+                if let Some(value) = replace.get(temp) {
+                    *operand = value.clone();
+                }
+            }
+        }
+    }
+    true
+}
+
+// 配列の添字の計算のように、帰納変数の一次式を掛け算で求めている値を
+// 繰り返しごとに定数を足すphiに置き換える (元の計算はdceで消える)
+// 符号付きの帰納変数のsextは、オーバーフローしない (未定義動作) として一次式に含める
+pub fn indvars(function: &mut Function) -> bool {
+    let mut changed = insert_preheaders(function);
+    let count = LoopForest::new(function).loops.len();
+    // 置き換えるとブロックの中身が変わるので、ループごとに調べ直す
+    for index in 0..count {
+        let forest = LoopForest::new(function);
+        changed |= reduce_loop(function, &forest.loops[index]);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::dce::dce;
+    use crate::ir::indvars::indvars;
+    use crate::ir::lower::lower;
+    use crate::ir::rotate::rotate;
+    use crate::ir::sprint_function;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn indvars_test() {
+        let program = parse(tokenize(
+            "int f(int *a, int n) { int s; int i; s = 0; for (i = 0; i < n; i = i + 1) s = s + a[i]; return s; }"
+                .to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        rotate(&mut function);
+        ssa::construct(&mut function);
+        indvars(&mut function);
+        dce(&mut function);
+        // &a[i] は前の繰り返しのアドレスに4を足して求める
+        assert_eq!(
+            sprint_function(&function),
+            "function @f(ptr %0, i32 %1) -> i32 {
+bb0:
+    %4 = slt i32 0, %1
+    br i32 %4, bb1, bb3
+bb1:
+    %23 = sext i32 0 to i64
+    %24 = mul i64 %23, 4
+    %25 = add ptr %0, %24
+    jmp bb2
+bb2:
+    %19 = phi i32 [bb1: 0], [bb2: %12]
+    %21 = phi i32 [bb1: 0], [bb2: %14]
+    %26 = phi ptr [bb1: %25], [bb2: %27]
+    %11 = load i32 %26
+    %12 = add i32 %19, %11
+    %14 = add i32 %21, 1
+    %27 = add ptr %26, 4
+    %18 = slt i32 %14, %1
+    br i32 %18, bb2, bb3
+bb3:
+    %20 = phi i32 [bb0: 0], [bb2: %12]
+    ret i32 %20
+}
+"
+        );
+    }
+}
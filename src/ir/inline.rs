@@ -0,0 +1,406 @@
+use crate::typename::Inlining;
+
+use super::{zero, BlockId, ConvertOp, Function, Inst, Module, Operand, Temp, Terminator, Type};
+
+// inlineを付けた関数を展開する命令数の上限
+const INLINE_LIMIT: usize = 60;
+// 指定のない関数を展開する命令数の上限
+const SMALL_LIMIT: usize = 12;
+// 呼び出し側がこれより大きくなったら展開しない (always_inlineは除く)
+const CALLER_LIMIT: usize = 2000;
+
+fn size(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .map(|block| block.insts.len() + 1)
+        .sum()
+}
+
+// 呼び出しグラフ (関数の番号 -> 直接呼び出す関数の番号)
+pub struct CallGraph {
+    pub callees: Vec<Vec<usize>>,
+}
+
+impl CallGraph {
+    pub fn new(module: &Module) -> CallGraph {
+        let callees = module
+            .functions
+            .iter()
+            .map(|function| {
+                let mut callees: Vec<usize> = Vec::new();
+                for block in function.blocks.iter() {
+                    let calls = block.insts.iter().filter_map(|inst| match inst {
+                        Inst::Call(_, Operand::Function(name), _, _) => Some(name),
+                        _ => None,
+                    });
+                    let tail_call = match &block.terminator {
+                        Terminator::TailCall(Operand::Function(name), _, _) => Some(name),
+                        _ => None,
+                    };
+                    for name in calls.chain(tail_call) {
+                        if let Some(callee) = find_function(module, name) {
+                            if !callees.contains(&callee) {
+                                callees.push(callee);
+                            }
+                        }
+                    }
+                }
+                callees
+            })
+            .collect();
+        CallGraph { callees }
+    }
+
+    // 呼び出しをたどって自分自身に戻ってくる関数
+    pub fn recursive(&self) -> Vec<bool> {
+        (0..self.callees.len())
+            .map(|start| {
+                let mut visited = vec![false; self.callees.len()];
+                let mut worklist: Vec<usize> = self.callees[start].clone();
+                while let Some(function) = worklist.pop() {
+                    if function == start {
+                        return true;
+                    }
+                    if !visited[function] {
+                        visited[function] = true;
+                        worklist.extend(self.callees[function].iter().cloned());
+                    }
+                }
+                false
+            })
+            .collect()
+    }
+
+    // 呼ばれる関数が呼ぶ関数より先に来る順序 (帰りがけ順)
+    pub fn bottom_up(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.callees.len()];
+        let mut order: Vec<usize> = Vec::new();
+        for root in 0..self.callees.len() {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            // (関数, 次に調べる呼び出し先の番号)
+            let mut stack: Vec<(usize, usize)> = vec![(root, 0)];
+            while let Some((function, index)) = stack.pop() {
+                if index < self.callees[function].len() {
+                    stack.push((function, index + 1));
+                    let callee = self.callees[function][index];
+                    if !visited[callee] {
+                        visited[callee] = true;
+                        stack.push((callee, 0));
+                    }
+                } else {
+                    order.push(function);
+                }
+            }
+        }
+        order
+    }
+}
+
+fn find_function(module: &Module, name: &str) -> Option<usize> {
+    module
+        .functions
+        .iter()
+        .position(|function| function.name == name)
+}
+
+// 引数と戻り値の型が定義と合っているか (intに拡張して渡した小さい整数は切り詰めて受け取る)
+pub fn is_compatible_call(
+    callee: &Function,
+    dst: &Option<(Temp, Type)>,
+    args: &[(Operand, Type)],
+) -> bool {
+    if callee.variadic || args.len() != callee.params.len() {
+        return false;
+    }
+    let args_match = args
+        .iter()
+        .zip(callee.params.iter())
+        .all(|((_, typename), param)| {
+            let param_type = callee.temp_types[param.0];
+            *typename == param_type
+                || (!typename.is_float()
+                    && !param_type.is_float()
+                    && typename.size() > param_type.size())
+        });
+    let ret_match = match (dst, callee.ret) {
+        (Some((_, typename)), Some(ret)) => *typename == ret,
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    args_match && ret_match
+}
+
+fn should_inline(caller: &Function, callee: &Function, recursive: bool) -> bool {
+    let limit = match callee.inlining {
+        Inlining::Never => return false,
+        Inlining::Always => usize::MAX,
+        Inlining::Inline => INLINE_LIMIT,
+        Inlining::Default => SMALL_LIMIT,
+    };
+    if recursive || caller.name == callee.name {
+        return false;
+    }
+    if let Inlining::Always = callee.inlining {
+        return true;
+    }
+    size(callee) <= limit && size(caller) + size(callee) <= CALLER_LIMIT
+}
+
+// callerのblockのindex番目にあるcalleeの呼び出しを本体で置き換える
+// 呼び出しの後ろは新しいブロックに分け、calleeのreturnはそこへのジャンプにする
+fn inline_call(caller: &mut Function, block: BlockId, index: usize, callee: &Function) {
+    let (dst, args) = match caller.block(block).insts[index].clone() {
+        Inst::Call(dst, _, args, _) => (dst, args),
+        _ => unreachable!(),
+    };
+    // calleeのローカル変数は呼び出し側の領域の後ろに置く
+    let local_base = caller.local_var_size.div_ceil(16) * 16;
+    caller.local_var_size = local_base + callee.local_var_size;
+
+    // 仮引数は実引数に置き換え、それ以外の一時変数は呼び出し側に作り直す
+    let mut temps: Vec<Option<Operand>> = vec![None; callee.temp_types.len()];
+    let mut entry_insts: Vec<Inst> = Vec::new();
+    for ((arg, typename), param) in args.iter().zip(callee.params.iter()) {
+        let param_type = callee.temp_types[param.0];
+        temps[param.0] = Some(if *typename == param_type {
+            arg.clone()
+        } else {
+            let narrowed = caller.new_temp(param_type);
+            entry_insts.push(Inst::Convert(
+                narrowed,
+                ConvertOp::Trunc,
+                *typename,
+                param_type,
+                arg.clone(),
+            ));
+            Operand::Temp(narrowed)
+        });
+    }
+    for (temp, typename) in callee.temp_types.iter().enumerate() {
+        if temps[temp].is_none() {
+            temps[temp] = Some(Operand::Temp(caller.new_temp(*typename)));
+        }
+    }
+    let remap = |operand: &mut Operand| match operand {
+        Operand::Temp(temp) => *operand = temps[temp.0].clone().unwrap(),
+        Operand::Local(offset) => *offset += local_base,
+        _ => {}
+    };
+
+    // 呼び出しの後ろを続きのブロックに移す
+    let continuation = caller.new_block();
+    let block_base = caller.blocks.len();
+    let rest = caller.block_mut(block).insts.split_off(index + 1);
+    caller.block_mut(block).insts.pop();
+    caller.block_mut(block).insts.extend(entry_insts);
+    let terminator = std::mem::replace(
+        &mut caller.block_mut(block).terminator,
+        Terminator::Jump(BlockId(block_base)),
+    );
+    for successor in terminator.successors() {
+        for inst in caller.block_mut(successor).insts.iter_mut() {
+            if let Inst::Phi(_, _, incoming) = inst {
+                for (predecessor, _) in incoming.iter_mut() {
+                    if *predecessor == block {
+                        *predecessor = continuation;
+                    }
+                }
+            }
+        }
+    }
+    caller.block_mut(continuation).insts = rest;
+    caller.block_mut(continuation).terminator = terminator;
+
+    // calleeのブロックを複製する
+    let mut returns: Vec<(BlockId, Operand)> = Vec::new();
+    for (id, callee_block) in callee.blocks.iter().enumerate() {
+        let mut insts = callee_block.insts.clone();
+        for inst in insts.iter_mut() {
+            if let Some(dst) = inst.dst_mut() {
+                if let Some(Operand::Temp(temp)) = &temps[dst.0] {
+                    *dst = *temp;
+                }
+            }
+            for operand in inst.operands_mut() {
+                remap(operand);
+            }
+            if let Inst::Phi(_, _, incoming) = inst {
+                for (predecessor, _) in incoming.iter_mut() {
+                    predecessor.0 += block_base;
+                }
+            }
+        }
+        let terminator = match callee_block.terminator.clone() {
+            // 末尾呼び出しは普通の呼び出しとその結果のreturnに戻す
+            Terminator::TailCall(mut callee_operand, mut call_args, variadic) => {
+                remap(&mut callee_operand);
+                for (arg, _) in call_args.iter_mut() {
+                    remap(arg);
+                }
+                let call_dst = dst.map(|(_, typename)| (caller.new_temp(typename), typename));
+                insts.push(Inst::Call(call_dst, callee_operand, call_args, variadic));
+                if let Some((call_dst, _)) = call_dst {
+                    returns.push((BlockId(block_base + id), Operand::Temp(call_dst)));
+                }
+                Terminator::Jump(continuation)
+            }
+            Terminator::Return(value) => {
+                if let Some((_, typename)) = dst {
+                    let value = match value {
+                        Some((mut value, _)) => {
+                            remap(&mut value);
+                            value
+                        }
+                        // 値を返さずに終わった場合 (未定義)
+                        None => zero(&typename),
+                    };
+                    returns.push((BlockId(block_base + id), value));
+                }
+                Terminator::Jump(continuation)
+            }
+            mut terminator => {
+                for operand in terminator.operands_mut() {
+                    remap(operand);
+                }
+                for successor in terminator.successors_mut() {
+                    successor.0 += block_base;
+                }
+                terminator
+            }
+        };
+        let new_block = caller.new_block();
+        caller.block_mut(new_block).insts = insts;
+        caller.block_mut(new_block).terminator = terminator;
+    }
+
+    // 戻り値は続きのブロックの先頭で受け取る
+    if let Some((dst, typename)) = dst {
+        let inst = if returns.len() == 1 {
+            Inst::Copy(dst, typename, returns.pop().unwrap().1)
+        } else if returns.is_empty() {
+            Inst::Copy(dst, typename, zero(&typename))
+        } else {
+            Inst::Phi(dst, typename, returns)
+        };
+        caller.block_mut(continuation).insts.insert(0, inst);
+    }
+}
+
+// 呼び出しグラフを葉の側からたどり、小さい関数やinlineを付けた関数の呼び出しを展開する
+// 再帰する関数は展開しない
+pub fn inline(module: &mut Module) -> bool {
+    let graph = CallGraph::new(module);
+    let recursive = graph.recursive();
+    for (function, recursive) in module.functions.iter().zip(recursive.iter()) {
+        if *recursive && function.inlining == Inlining::Always {
+            eprintln!(
+                "warning: inlining failed in call to always_inline '{}': recursive",
+                function.name
+            );
+        }
+    }
+    let mut changed = false;
+    for caller in graph.bottom_up() {
+        // 展開した呼び出しの後ろと展開した本体は末尾に足したブロックに移るので、
+        // ブロックを前から1度ずつ調べればよい
+        let mut id: usize = 0;
+        while id < module.functions[caller].blocks.len() {
+            let function = &module.functions[caller];
+            let site = function.blocks[id]
+                .insts
+                .iter()
+                .enumerate()
+                .find_map(|(index, inst)| match inst {
+                    Inst::Call(dst, Operand::Function(name), args, _) => {
+                        let callee = find_function(module, name)?;
+                        let definition = &module.functions[callee];
+                        if is_compatible_call(definition, dst, args)
+                            && should_inline(function, definition, recursive[callee])
+                        {
+                            Some((index, callee))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                });
+            if let Some((index, callee)) = site {
+                let callee = module.functions[callee].clone();
+                inline_call(&mut module.functions[caller], BlockId(id), index, &callee);
+                changed = true;
+            }
+            id += 1;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::inline::inline;
+    use crate::ir::lower::lower;
+    use crate::ir::sprint_module;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn inline_test() {
+        let program = parse(tokenize(
+            "static inline int twice(char x) { return x + x; } int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } int f(int a) { return twice(a) + fib(a); }"
+                .to_string(),
+        ));
+        let mut module = lower(&program, false);
+        for function in module.functions.iter_mut() {
+            ssa::construct(function);
+        }
+        inline(&mut module);
+        // 再帰するfibは展開しない
+        assert_eq!(
+            sprint_module(&module),
+            "static function @twice(i8 %0) -> i32 {
+bb0:
+    %2 = sext i8 %0 to i32
+    %4 = sext i8 %0 to i32
+    %5 = add i32 %2, %4
+    ret i32 %5
+}
+function @fib(i32 %0) -> i32 {
+bb0:
+    %2 = slt i32 %0, 2
+    br i32 %2, bb1, bb2
+bb1:
+    ret i32 %0
+bb2:
+    %5 = sub i32 %0, 1
+    %6 = call i32 @fib(i32 %5)
+    %8 = sub i32 %0, 2
+    %9 = call i32 @fib(i32 %8)
+    %10 = add i32 %6, %9
+    ret i32 %10
+}
+function @f(i32 %0) -> i32 {
+bb0:
+    %2 = trunc i32 %0 to i8
+    %3 = sext i8 %2 to i32
+    %8 = trunc i32 %3 to i8
+    jmp bb2
+bb1:
+    %4 = copy i32 %13
+    %6 = call i32 @fib(i32 %0)
+    %7 = add i32 %4, %6
+    ret i32 %7
+bb2:
+    %10 = sext i8 %8 to i32
+    %12 = sext i8 %8 to i32
+    %13 = add i32 %10, %12
+    jmp bb1
+}
+"
+        );
+    }
+}
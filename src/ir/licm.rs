@@ -0,0 +1,168 @@
+use super::dominator::DominatorTree;
+use super::gvn::may_alias;
+use super::liveness::inst_uses;
+use super::loops::{insert_preheaders, preheader, Loop, LoopForest};
+use super::{BinaryOp, BlockId, Function, Inst, Operand, Type};
+
+// ループの中で書き込まれるメモリ
+struct Writes {
+    stores: Vec<(Type, Operand)>,
+    // 呼び出しや可変長引数の操作がある (どこに書き込むか分からない)
+    unknown: bool,
+}
+
+impl Writes {
+    fn new(function: &Function, lp: &Loop) -> Writes {
+        let mut writes = Writes {
+            stores: Vec::new(),
+            unknown: false,
+        };
+        for block in lp.blocks.iter() {
+            for inst in function.block(*block).insts.iter() {
+                match inst {
+                    Inst::Store(typename, address, _, _) => {
+                        writes.stores.push((*typename, address.clone()))
+                    }
+                    Inst::Call(_, _, _, _)
+                    | Inst::VaStart(_)
+                    | Inst::VaArg(_, _, _)
+                    | Inst::VaCopy(_, _) => writes.unknown = true,
+                    _ => {}
+                }
+            }
+        }
+        writes
+    }
+
+    fn may_write(&self, typename: &Type, address: &Operand) -> bool {
+        self.unknown
+            || self.stores.iter().any(|(store_type, store_address)| {
+                may_alias(store_address, store_type.size(), address, typename.size())
+            })
+    }
+}
+
+// ループの前に移してよい命令か (オペランドが不変かどうかは呼び出し側で調べる)
+// always はループに入れば必ず実行されるブロックにあるか
+fn is_hoistable(inst: &Inst, writes: &Writes, always: bool) -> bool {
+    match inst {
+        // 0や-1での割り算は例外になりうるので、定数で割るものだけ
+        Inst::Binary(_, BinaryOp::SDiv, _, _, rhs) | Inst::Binary(_, BinaryOp::UDiv, _, _, rhs) => {
+            matches!(rhs, Operand::Int(n) if *n != 0 && *n != -1)
+        }
+        Inst::Copy(_, _, _)
+        | Inst::Binary(_, _, _, _, _)
+        | Inst::Compare(_, _, _, _, _)
+        | Inst::Convert(_, _, _, _, _) => true,
+        // ループの中で書き換わらないメモリの読み出し
+        // ポインタの先は、ループに入らなかったときに読むと不正なアドレスかもしれない
+        Inst::Load(_, typename, address, false) => {
+            let safe = always || matches!(address, Operand::Local(_) | Operand::Global(_));
+            safe && !writes.may_write(typename, address)
+        }
+        _ => false,
+    }
+}
+
+// ループの中で値が変わらない命令を前置ブロックに移す
+// 内側のループから順に移すので、多重ループでは外側のループの前まで移ることもある
+pub fn licm(function: &mut Function) -> bool {
+    let mut changed = insert_preheaders(function);
+    let forest = LoopForest::new(function);
+    let tree = DominatorTree::new(function);
+    // 一時変数を定義するブロックと定義の数 (仮引数は定義がない)
+    let mut definitions: Vec<(Option<BlockId>, usize)> = vec![(None, 0); function.temp_types.len()];
+    for (id, block) in function.blocks.iter().enumerate() {
+        for inst in block.insts.iter() {
+            if let Some(dst) = inst.dst() {
+                definitions[dst.0].0 = Some(BlockId(id));
+                definitions[dst.0].1 += 1;
+            }
+        }
+    }
+    for lp in forest.loops.iter() {
+        let preheader = match preheader(function, lp) {
+            Some(preheader) => preheader,
+            None => continue,
+        };
+        let writes = Writes::new(function, lp);
+        let exiting = lp.exiting_blocks(function);
+        // 逆後順にたどれば、移した命令の結果を使う命令も続けて移せる
+        for block in lp.blocks.iter() {
+            let always = exiting.iter().all(|exit| tree.dominates(*block, *exit));
+            let mut hoisted: Vec<Inst> = Vec::new();
+            let mut kept: Vec<Inst> = Vec::new();
+            for inst in std::mem::take(&mut function.block_mut(*block).insts) {
+                let invariant = inst_uses(&inst)
+                    .iter()
+                    .all(|temp| match definitions[temp.0].0 {
+                        Some(definition) => !lp.contains(definition),
+                        None => true,
+                    });
+                let single = inst.dst().is_some_and(|dst| definitions[dst.0].1 == 1);
+                if invariant && single && is_hoistable(&inst, &writes, always) {
+                    definitions[inst.dst().unwrap().0].0 = Some(preheader);
+                    hoisted.push(inst);
+                } else {
+                    kept.push(inst);
+                }
+            }
+            function.block_mut(*block).insts = kept;
+            if !hoisted.is_empty() {
+                function.block_mut(preheader).insts.extend(hoisted);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::licm::licm;
+    use crate::ir::lower::lower;
+    use crate::ir::rotate::rotate;
+    use crate::ir::sprint_function;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn licm_test() {
+        let program = parse(tokenize(
+            "int f(int *a, int *b, int n, int k) { int i; for (i = 0; i < n; i = i + 1) a[i] = k * 3 + *b; return 0; }"
+                .to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        rotate(&mut function);
+        ssa::construct(&mut function);
+        licm(&mut function);
+        // k * 3 は移し、aへの書き込みと重なるかもしれない *b は移さない
+        assert_eq!(
+            sprint_function(&function),
+            "function @f(ptr %0, ptr %1, i32 %2, i32 %3) -> i32 {
+bb0:
+    %6 = slt i32 0, %2
+    br i32 %6, bb1, bb3
+bb1:
+    %13 = mul i32 %3, 3
+    jmp bb2
+bb2:
+    %22 = phi i32 [bb1: 0], [bb2: %18]
+    %9 = sext i32 %22 to i64
+    %10 = mul i64 %9, 4
+    %11 = add ptr %0, %10
+    %15 = load i32 %1
+    %16 = add i32 %13, %15
+    store i32 %11, %16
+    %18 = add i32 %22, 1
+    %21 = slt i32 %18, %2
+    br i32 %21, bb2, bb3
+bb3:
+    %23 = phi i32 [bb0: 0], [bb2: %18]
+    ret i32 0
+}
+"
+        );
+    }
+}
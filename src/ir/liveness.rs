@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use super::{Function, Inst, Operand, Temp};
+
+// ブロックの入口と出口で生きている一時変数
+pub struct Liveness {
+    pub live_in: Vec<HashSet<Temp>>,
+    pub live_out: Vec<HashSet<Temp>>,
+}
+
+// 命令が読む一時変数
+pub fn inst_uses(inst: &Inst) -> Vec<Temp> {
+    temps(inst.operands())
+}
+
+pub fn temps(operands: Vec<&Operand>) -> Vec<Temp> {
+    operands
+        .into_iter()
+        .filter_map(|operand| match operand {
+            Operand::Temp(temp) => Some(*temp),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Liveness {
+    pub fn new(function: &Function) -> Liveness {
+        let count = function.blocks.len();
+        // ブロック内で定義より先に読まれる一時変数と、定義される一時変数
+        let mut uses: Vec<HashSet<Temp>> = vec![HashSet::new(); count];
+        let mut defs: Vec<HashSet<Temp>> = vec![HashSet::new(); count];
+        for (id, block) in function.blocks.iter().enumerate() {
+            for inst in block.insts.iter() {
+                for temp in inst_uses(inst) {
+                    if !defs[id].contains(&temp) {
+                        uses[id].insert(temp);
+                    }
+                }
+                if let Some(dst) = inst.dst() {
+                    defs[id].insert(dst);
+                }
+            }
+            for temp in temps(block.terminator.operands()) {
+                if !defs[id].contains(&temp) {
+                    uses[id].insert(temp);
+                }
+            }
+        }
+
+        let mut live_in: Vec<HashSet<Temp>> = vec![HashSet::new(); count];
+        let mut live_out: Vec<HashSet<Temp>> = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..count).rev() {
+                let mut out: HashSet<Temp> = HashSet::new();
+                for successor in function.blocks[id].terminator.successors() {
+                    out.extend(live_in[successor.0].iter().cloned());
+                }
+                let mut input: HashSet<Temp> = out.difference(&defs[id]).cloned().collect();
+                input.extend(uses[id].iter().cloned());
+                if input != live_in[id] || out != live_out[id] {
+                    live_in[id] = input;
+                    live_out[id] = out;
+                    changed = true;
+                }
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+}
@@ -0,0 +1,188 @@
+use super::dominator::DominatorTree;
+use super::{BlockId, Function, Inst, Operand, Terminator};
+
+// 自然ループ (ヘッダと、後退辺からヘッダを通らずに戻れるブロック)
+pub struct Loop {
+    pub header: BlockId,
+    pub latches: Vec<BlockId>, // ヘッダへの後退辺の始点
+    pub blocks: Vec<BlockId>,  // ヘッダを含む本体 (逆後順)
+    pub parent: Option<usize>, // すぐ外側のループ
+    pub children: Vec<usize>,  // すぐ内側のループ
+    member: Vec<bool>,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.member[block.0]
+    }
+
+    // ループの外に出る辺を持つブロック (returnで抜けるブロックも含む)
+    pub fn exiting_blocks(&self, function: &Function) -> Vec<BlockId> {
+        self.blocks
+            .iter()
+            .filter(|block| {
+                let terminator = &function.block(**block).terminator;
+                matches!(terminator, Terminator::Return(_))
+                    || terminator
+                        .successors()
+                        .iter()
+                        .any(|successor| !self.contains(*successor))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+// ループの入れ子の木
+pub struct LoopForest {
+    pub loops: Vec<Loop>, // 内側のループが外側のループより先に来る
+}
+
+impl LoopForest {
+    pub fn new(function: &Function) -> LoopForest {
+        let tree = DominatorTree::new(function);
+        let predecessors = function.predecessors();
+        // ヘッダごとに後退辺 (ヘッダが始点を支配する辺) をまとめる
+        let mut headers: Vec<(BlockId, Vec<BlockId>)> = Vec::new();
+        for block in tree.reverse_postorder.iter() {
+            for successor in function.block(*block).terminator.successors() {
+                if !tree.dominates(successor, *block) {
+                    continue;
+                }
+                match headers.iter_mut().find(|(header, _)| *header == successor) {
+                    Some((_, latches)) => latches.push(*block),
+                    None => headers.push((successor, vec![*block])),
+                }
+            }
+        }
+        let mut loops: Vec<Loop> = headers
+            .into_iter()
+            .map(|(header, latches)| {
+                let mut member = vec![false; function.blocks.len()];
+                member[header.0] = true;
+                let mut worklist: Vec<BlockId> = latches.clone();
+                while let Some(block) = worklist.pop() {
+                    if member[block.0] {
+                        continue;
+                    }
+                    member[block.0] = true;
+                    worklist.extend(
+                        predecessors[block.0]
+                            .iter()
+                            .filter(|predecessor| tree.is_reachable(**predecessor)),
+                    );
+                }
+                let blocks = tree
+                    .reverse_postorder
+                    .iter()
+                    .filter(|block| member[block.0])
+                    .cloned()
+                    .collect();
+                Loop {
+                    header,
+                    latches,
+                    blocks,
+                    parent: None,
+                    children: Vec::new(),
+                    member,
+                }
+            })
+            .collect();
+        // 小さいループから並べると、外側のループは内側のループより後に来る
+        loops.sort_by_key(|lp| lp.blocks.len());
+        for index in 0..loops.len() {
+            // ヘッダを含む最小のループが親
+            let parent =
+                (index + 1..loops.len()).find(|outer| loops[*outer].contains(loops[index].header));
+            loops[index].parent = parent;
+            if let Some(parent) = parent {
+                loops[parent].children.push(index);
+            }
+        }
+        LoopForest { loops }
+    }
+}
+
+// ループの外からヘッダに入る唯一のブロックで、ヘッダだけに進むもの
+pub fn preheader(function: &Function, lp: &Loop) -> Option<BlockId> {
+    let predecessors = function.predecessors();
+    let outside: Vec<BlockId> = predecessors[lp.header.0]
+        .iter()
+        .filter(|predecessor| !lp.contains(**predecessor))
+        .cloned()
+        .collect();
+    match outside.as_slice() {
+        [block] if function.block(*block).terminator.successors() == vec![lp.header] => {
+            Some(*block)
+        }
+        _ => None,
+    }
+}
+
+// 前置ブロックのないループのヘッダの前に空のブロックを挟む (入口のブロックがヘッダなら挟まない)
+// ヘッダのphiのループの外から来る引数は前置ブロックのphiにまとめる
+pub fn insert_preheaders(function: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let forest = LoopForest::new(function);
+        let missing = forest
+            .loops
+            .iter()
+            .find(|lp| lp.header != BlockId(0) && preheader(function, lp).is_none());
+        let lp = match missing {
+            Some(lp) => lp,
+            None => return changed,
+        };
+        let header = lp.header;
+        let outside: Vec<BlockId> = function.predecessors()[header.0]
+            .iter()
+            .filter(|predecessor| !lp.contains(**predecessor))
+            .cloned()
+            .collect();
+        let block = function.new_block();
+        function.block_mut(block).terminator = Terminator::Jump(header);
+        for predecessor in outside.iter() {
+            for successor in function.block_mut(*predecessor).terminator.successors_mut() {
+                if *successor == header {
+                    *successor = block;
+                }
+            }
+        }
+        let mut phis: Vec<Inst> = Vec::new();
+        let count = function
+            .block(header)
+            .insts
+            .iter()
+            .take_while(|inst| matches!(inst, Inst::Phi(_, _, _)))
+            .count();
+        for index in 0..count {
+            let (typename, entering) = match &mut function.block_mut(header).insts[index] {
+                Inst::Phi(_, typename, incoming) => {
+                    let entering: Vec<(BlockId, Operand)> = incoming
+                        .iter()
+                        .filter(|(predecessor, _)| outside.contains(predecessor))
+                        .cloned()
+                        .collect();
+                    incoming.retain(|(predecessor, _)| !outside.contains(predecessor));
+                    (*typename, entering)
+                }
+                _ => unreachable!(),
+            };
+            let value = match entering.first() {
+                Some((_, first)) if entering.iter().all(|(_, value)| value == first) => {
+                    first.clone()
+                }
+                _ => {
+                    let dst = function.new_temp(typename);
+                    phis.push(Inst::Phi(dst, typename, entering));
+                    Operand::Temp(dst)
+                }
+            };
+            if let Inst::Phi(_, _, incoming) = &mut function.block_mut(header).insts[index] {
+                incoming.insert(0, (block, value));
+            }
+        }
+        function.block_mut(block).insts = phis;
+        changed = true;
+    }
+}
@@ -0,0 +1,684 @@
+use crate::node::{lvalue_typename_of, typename_of, BinaryType, Node, UnaryType};
+use crate::typename::{
+    alignof, is_const, is_volatile, sizeof, unqualified, Inlining, SignedFlag, Typename,
+};
+
+use super::fold::fold;
+use super::tailcall::{make_tail_call, tail_call_error};
+use super::{
+    BinaryOp, BlockId, CompareOp, ConvertOp, Function, Global, Inst, Module, Operand, Temp,
+    Terminator, Type,
+};
+
+// 関数名 (またはその参照外しやアドレス) を直接呼ぶときはその名前
+fn direct_callee_name(callee: &Node) -> Option<String> {
+    match callee {
+        Node::FunctionName(name, _typename) => Some(name.clone()),
+        Node::Unary(unary_arg, UnaryType::Deref) | Node::Unary(unary_arg, UnaryType::Address) => {
+            match typename_of(unary_arg) {
+                Typename::Function(_, _, _) => direct_callee_name(unary_arg),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// 符号なしとして扱う型 (比較や変換の向きを決める)
+fn is_unsigned(typename: &Typename) -> bool {
+    !matches!(
+        unqualified(typename),
+        Typename::Integer(SignedFlag::Signed, _) | Typename::Floating(_)
+    )
+}
+
+fn value_type(typename: &Typename) -> Type {
+    Type::from_typename(typename).expect("void value not ignored as it ought to be")
+}
+
+// 構文木 (program) を中間表現に変換する
+// warn_unreachableなら到達しない文を警告する (-Wunreachable-code)
+pub fn lower(program: &Node, warn_unreachable: bool) -> Module {
+    let mut lowering = Lowering {
+        module: Module::default(),
+        function: None,
+        current: None,
+        warn_unreachable,
+        in_unreachable: false,
+        musttail: Vec::new(),
+    };
+    match program {
+        Node::Block(definitions) => {
+            for definition in definitions.iter() {
+                lowering.definition(definition);
+            }
+        }
+        _ => lowering.definition(program),
+    }
+    lowering.module
+}
+
+// 整数を型の幅に切り詰めて64bitに拡張し直す (構文木の整数定数の形)
+fn wrap_integer(n: i64, typename: &Typename) -> i64 {
+    match unqualified(typename) {
+        Typename::Boolean => (n != 0) as i64,
+        Typename::Integer(SignedFlag::Signed, 1) => n as i8 as i64,
+        Typename::Integer(SignedFlag::Signed, 2) => n as i16 as i64,
+        Typename::Integer(SignedFlag::Signed, 4) => n as i32 as i64,
+        Typename::Integer(SignedFlag::Unsigned, 1) => n as u8 as i64,
+        Typename::Integer(SignedFlag::Unsigned, 2) => n as u16 as i64,
+        Typename::Integer(SignedFlag::Unsigned, 4) => n as u32 as i64,
+        _ => n,
+    }
+}
+
+// アドレス定数の指す先の型 (整数に変換したアドレスはその型のまま)
+fn pointee(typename: Typename) -> Typename {
+    match typename {
+        Typename::Pointer(typename) => *typename,
+        typename => typename,
+    }
+}
+
+// 定数式を定数 (Num, FloatNum, Str, 静的変数や関数のアドレス) に畳み込む
+// 式を使い捨ての関数に変換して畳み込み、命令が残らなければ定数とみなす
+pub fn eval_constant(node: &Node) -> Option<Node> {
+    let typename = typename_of(node);
+    let mut function = Function {
+        name: String::new(),
+        params: Vec::new(),
+        ret: None,
+        variadic: false,
+        is_static: true,
+        inlining: Inlining::Default,
+        blocks: Vec::new(),
+        temp_types: Vec::new(),
+        local_var_size: 0,
+    };
+    let entry = function.new_block();
+    let mut lowering = Lowering {
+        module: Module::default(),
+        function: Some(function),
+        current: Some(entry),
+        warn_unreachable: false,
+        in_unreachable: false,
+        musttail: Vec::new(),
+    };
+    let value = lowering.expression(node);
+    let ir_type = Type::from_typename(&typename)?;
+    lowering.terminate(Terminator::Return(Some((value, ir_type))));
+    let mut function = lowering.function.take().unwrap();
+    fold(&mut function);
+    if function.blocks.iter().any(|block| !block.insts.is_empty()) {
+        return None;
+    }
+    match &function.block(entry).terminator {
+        Terminator::Return(Some((Operand::Int(n), _))) => {
+            Some(Node::Num(wrap_integer(*n, &typename), typename))
+        }
+        Terminator::Return(Some((Operand::Float(x), _))) => Some(Node::FloatNum(*x, typename)),
+        Terminator::Return(Some((Operand::Global(label), _))) => {
+            match label.strip_prefix(".LC") {
+                Some(index) => {
+                    let index: usize = index.parse().ok()?;
+                    Some(Node::Str(lowering.module.strings[index].clone()))
+                }
+                // 静的変数のアドレス
+                None => Some(Node::Unary(
+                    Box::new(Node::GVar(label.clone(), pointee(typename))),
+                    UnaryType::Address,
+                )),
+            }
+        }
+        Terminator::Return(Some((Operand::Function(name), _))) => Some(Node::Unary(
+            Box::new(Node::FunctionName(name.clone(), pointee(typename))),
+            UnaryType::Address,
+        )),
+        _ => None,
+    }
+}
+
+pub struct Lowering {
+    pub module: Module,
+    pub function: Option<Function>,
+    // 命令を追加するブロック (分岐の直後はNone)
+    pub current: Option<BlockId>,
+    pub warn_unreachable: bool,
+    // 到達しない文を警告済み (続く文では警告しない)
+    pub in_unreachable: bool,
+    // musttailを付けたreturnで終わるブロック
+    pub musttail: Vec<BlockId>,
+}
+
+impl Lowering {
+    fn definition(&mut self, node: &Node) {
+        match node {
+            Node::Function(
+                name,
+                return_type,
+                params,
+                variadic,
+                is_static,
+                inlining,
+                block,
+                local_var_size,
+            ) => {
+                let mut function = Function {
+                    name: name.clone(),
+                    params: Vec::new(),
+                    ret: Type::from_typename(return_type),
+                    variadic: *variadic,
+                    is_static: *is_static,
+                    inlining: *inlining,
+                    blocks: Vec::new(),
+                    temp_types: Vec::new(),
+                    local_var_size: *local_var_size,
+                };
+                let entry = function.new_block();
+                self.function = Some(function);
+                self.current = Some(entry);
+                // 引数は仮引数の領域に書き込んでおく
+                for (offset, typename) in params.iter() {
+                    let ir_type = value_type(typename);
+                    let param = self.function().new_temp(ir_type);
+                    self.function().params.push(param);
+                    self.emit(Inst::Store(
+                        ir_type,
+                        Operand::Local(*offset),
+                        Operand::Temp(param),
+                        is_volatile(typename),
+                    ));
+                }
+                self.statement(block);
+                if self.current.is_some() {
+                    // main関数の末尾に達したら0を返す
+                    let ret = self.function().ret;
+                    let value = match ret {
+                        Some(ret) if name == "main" => Some((Operand::Int(0), ret)),
+                        _ => None,
+                    };
+                    self.terminate(Terminator::Return(value));
+                }
+                let mut function = self.function.take().unwrap();
+                // musttailの呼び出しは最適化しなくても末尾呼び出しにする
+                for block in std::mem::take(&mut self.musttail) {
+                    if let Some(reason) = tail_call_error(&function, block) {
+                        panic!("cannot perform 'musttail' call in '{}': {}", name, reason);
+                    }
+                    make_tail_call(&mut function, block);
+                }
+                self.module.functions.push(function);
+            }
+            Node::GlobalVar(label, typename, is_static, initializer) => {
+                let initializer = initializer.as_ref().map(|value| match &**value {
+                    Node::Num(n, _) => Operand::Int(*n),
+                    Node::FloatNum(x, _) => Operand::Float(*x),
+                    Node::Str(bytes) => self.string_literal(bytes),
+                    Node::Unary(unary_arg, UnaryType::Address) => self.address(unary_arg),
+                    _ => panic!("initializer element is not constant"),
+                });
+                self.module.globals.push(Global {
+                    label: label.clone(),
+                    size: sizeof(typename),
+                    align: alignof(typename),
+                    is_static: *is_static,
+                    // 初期値のあるconstな変数は読み取り専用にする
+                    readonly: initializer.is_some() && is_const(typename),
+                    initializer,
+                });
+            }
+            Node::Empty => {}
+            _ => panic!("unexpected node at top level"),
+        }
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.function.as_mut().unwrap()
+    }
+
+    fn string_literal(&mut self, bytes: &[u8]) -> Operand {
+        self.module.strings.push(bytes.to_vec());
+        Operand::Global(format!(".LC{}", self.module.strings.len() - 1))
+    }
+
+    // 現在のブロックに命令を追加する (到達しない位置なら新しいブロックを作る)
+    fn emit(&mut self, inst: Inst) {
+        let current = match self.current {
+            Some(current) => current,
+            None => {
+                let block = self.function().new_block();
+                self.current = Some(block);
+                block
+            }
+        };
+        self.function().block_mut(current).insts.push(inst);
+    }
+
+    // 現在のブロックを終えて、そのブロックを返す
+    fn terminate(&mut self, terminator: Terminator) -> BlockId {
+        if self.current.is_none() {
+            let block = self.function().new_block();
+            self.current = Some(block);
+        }
+        let current = self.current.take().unwrap();
+        self.function().block_mut(current).terminator = terminator;
+        current
+    }
+
+    // 現在のブロックからblockに進み、以降はblockに命令を追加する
+    fn enter(&mut self, block: BlockId) {
+        if self.current.is_some() {
+            self.terminate(Terminator::Jump(block));
+        }
+        self.current = Some(block);
+    }
+
+    fn new_temp(&mut self, typename: Type) -> Temp {
+        self.function().new_temp(typename)
+    }
+
+    // 現在の位置に入口から来られるか (まだ飛び込む辺のないブロックは到達しない)
+    fn is_reachable(&self) -> bool {
+        let function = self.function.as_ref().unwrap();
+        match self.current {
+            Some(BlockId(0)) => true,
+            Some(current) => function
+                .blocks
+                .iter()
+                .any(|block| block.terminator.successors().contains(&current)),
+            None => false,
+        }
+    }
+
+    fn statement(&mut self, node: &Node) {
+        if self.warn_unreachable && !matches!(node, Node::Block(_) | Node::Empty) {
+            let reachable = self.is_reachable();
+            if !reachable && !self.in_unreachable {
+                eprintln!(
+                    "warning: statement in '{}' will never be executed",
+                    self.function.as_ref().unwrap().name
+                );
+            }
+            self.in_unreachable = !reachable;
+        }
+        match node {
+            Node::Block(statements) => {
+                for statement in statements.iter() {
+                    self.statement(statement);
+                }
+            }
+            Node::If(if_arg) => {
+                let then_block = self.function().new_block();
+                let end_block = self.function().new_block();
+                self.branch(&if_arg.0, then_block, end_block);
+                self.current = Some(then_block);
+                self.statement(&if_arg.1);
+                self.enter(end_block);
+            }
+            Node::IfElse(if_arg) => {
+                let then_block = self.function().new_block();
+                let else_block = self.function().new_block();
+                let end_block = self.function().new_block();
+                self.branch(&if_arg.0, then_block, else_block);
+                self.current = Some(then_block);
+                self.statement(&if_arg.1);
+                if self.current.is_some() {
+                    self.terminate(Terminator::Jump(end_block));
+                }
+                self.current = Some(else_block);
+                self.statement(&if_arg.2);
+                self.enter(end_block);
+            }
+            Node::While(while_arg) => {
+                let cond_block = self.function().new_block();
+                let body_block = self.function().new_block();
+                let end_block = self.function().new_block();
+                self.enter(cond_block);
+                self.branch(&while_arg.0, body_block, end_block);
+                self.current = Some(body_block);
+                self.statement(&while_arg.1);
+                if self.current.is_some() {
+                    self.terminate(Terminator::Jump(cond_block));
+                }
+                self.current = Some(end_block);
+            }
+            Node::For(for_arg) => {
+                let cond_block = self.function().new_block();
+                let body_block = self.function().new_block();
+                let end_block = self.function().new_block();
+                self.statement(&for_arg.0);
+                self.enter(cond_block);
+                self.branch(&for_arg.1, body_block, end_block);
+                self.current = Some(body_block);
+                self.statement(&for_arg.3);
+                self.statement(&for_arg.2);
+                if self.current.is_some() {
+                    self.terminate(Terminator::Jump(cond_block));
+                }
+                self.current = Some(end_block);
+            }
+            Node::Return(value, musttail) => {
+                let value = match value {
+                    Some(value) => {
+                        let operand = self.expression(value);
+                        self.function().ret.map(|ret| (operand, ret))
+                    }
+                    None => None,
+                };
+                let block = self.terminate(Terminator::Return(value));
+                if *musttail {
+                    self.musttail.push(block);
+                }
+            }
+            Node::Empty => {}
+            _ => {
+                self.expression(node);
+            }
+        }
+    }
+
+    // 条件式の真偽で分岐する
+    fn branch(&mut self, cond: &Node, then_block: BlockId, else_block: BlockId) {
+        let typename = typename_of(cond);
+        let value = self.expression(cond);
+        let (value, ir_type) = if is_floating_type(&typename) {
+            let dst = self.new_temp(Type::I32);
+            let operand_type = value_type(&typename);
+            self.emit(Inst::Compare(
+                dst,
+                CompareOp::FNe,
+                operand_type,
+                value,
+                Operand::Float(0.0),
+            ));
+            (Operand::Temp(dst), Type::I32)
+        } else {
+            (value, value_type(&typename))
+        };
+        self.terminate(Terminator::Branch(value, ir_type, then_block, else_block));
+    }
+
+    // 左辺値のアドレス
+    fn address(&mut self, node: &Node) -> Operand {
+        match node {
+            Node::LVar(offset, _typename) => Operand::Local(*offset),
+            Node::GVar(label, _typename) => Operand::Global(label.clone()),
+            Node::FunctionName(name, _typename) => Operand::Function(name.clone()),
+            Node::Unary(unary_arg, UnaryType::Deref) => self.expression(unary_arg),
+            _ => panic!("lvalue required"),
+        }
+    }
+
+    // addressから型typenameの値を読む (配列と関数はアドレスのまま)
+    fn load(&mut self, address: Operand, typename: &Typename) -> Operand {
+        match unqualified(typename) {
+            Typename::Array(_, _) | Typename::Function(_, _, _) | Typename::VaList => address,
+            _ => {
+                let ir_type = value_type(typename);
+                let dst = self.new_temp(ir_type);
+                self.emit(Inst::Load(dst, ir_type, address, is_volatile(typename)));
+                Operand::Temp(dst)
+            }
+        }
+    }
+
+    // 式の値 (voidの式は0)
+    fn expression(&mut self, node: &Node) -> Operand {
+        match node {
+            Node::Num(n, _typename) => Operand::Int(*n),
+            Node::FloatNum(x, _typename) => Operand::Float(*x),
+            Node::Boolean(flag) => Operand::Int(*flag as i64),
+            Node::Str(bytes) => self.string_literal(bytes),
+            Node::FunctionName(name, _typename) => Operand::Function(name.clone()),
+            Node::LVar(_, typename) | Node::GVar(_, typename) => {
+                let address = self.address(node);
+                self.load(address, typename)
+            }
+            Node::Unary(unary_arg, UnaryType::Address) => self.address(unary_arg),
+            Node::Unary(unary_arg, UnaryType::Deref) => {
+                let address = self.expression(unary_arg);
+                self.load(address, &lvalue_typename_of(node))
+            }
+            Node::Unary(unary_arg, UnaryType::Not) => {
+                let typename = typename_of(unary_arg);
+                let value = self.expression(unary_arg);
+                let (op, zero) = if is_floating_type(&typename) {
+                    (CompareOp::FEq, Operand::Float(0.0))
+                } else {
+                    (CompareOp::Eq, Operand::Int(0))
+                };
+                let dst = self.new_temp(Type::I32);
+                self.emit(Inst::Compare(dst, op, value_type(&typename), value, zero));
+                Operand::Temp(dst)
+            }
+            Node::Binary(binary_arg, binary_type) => {
+                // 両辺は通常の算術型変換で同じ型になっている (ポインタ演算の右辺はlong)
+                let typename = typename_of(&binary_arg.0);
+                let lhs = self.expression(&binary_arg.0);
+                let rhs = self.expression(&binary_arg.1);
+                self.binary(binary_type, &typename, lhs, rhs)
+            }
+            Node::Assign(assign_arg) => {
+                let typename = lvalue_typename_of(&assign_arg.0);
+                let address = self.address(&assign_arg.0);
+                let value = self.expression(&assign_arg.1);
+                self.emit(Inst::Store(
+                    value_type(&typename),
+                    address,
+                    value.clone(),
+                    is_volatile(&typename),
+                ));
+                value
+            }
+            Node::Cast(cast_arg, typename) => {
+                let value = self.expression(cast_arg);
+                self.convert(value, &typename_of(cast_arg), typename)
+            }
+            Node::FunctionCall(callee, args, return_type, variadic) => {
+                let callee = match direct_callee_name(callee) {
+                    Some(name) => Operand::Function(name),
+                    None => self.expression(callee),
+                };
+                // 引数は右から順に評価する
+                let mut arg_operands: Vec<(Operand, Type)> = Vec::new();
+                for arg in args.iter().rev() {
+                    let typename = typename_of(arg);
+                    let value = self.expression(arg);
+                    // intより小さい整数は呼び出し側で32bitに拡張して渡す
+                    let value = match unqualified(&typename) {
+                        Typename::Boolean | Typename::Integer(_, 1) | Typename::Integer(_, 2) => {
+                            let int = Typename::Integer(
+                                if is_unsigned(&typename) {
+                                    SignedFlag::Unsigned
+                                } else {
+                                    SignedFlag::Signed
+                                },
+                                4,
+                            );
+                            (self.convert(value, &typename, &int), Type::I32)
+                        }
+                        _ => (value, value_type(&typename)),
+                    };
+                    arg_operands.push(value);
+                }
+                arg_operands.reverse();
+                let dst = Type::from_typename(return_type).map(|ret| (self.new_temp(ret), ret));
+                self.emit(Inst::Call(dst, callee, arg_operands, *variadic));
+                match dst {
+                    Some((dst, _)) => Operand::Temp(dst),
+                    None => Operand::Int(0),
+                }
+            }
+            Node::VaStart(ap) => {
+                let ap = self.expression(ap);
+                self.emit(Inst::VaStart(ap));
+                Operand::Int(0)
+            }
+            Node::VaArg(ap, typename) => {
+                let ap = self.expression(ap);
+                let ir_type = value_type(typename);
+                let dst = self.new_temp(ir_type);
+                self.emit(Inst::VaArg(dst, ir_type, ap));
+                Operand::Temp(dst)
+            }
+            Node::VaCopy(va_copy_arg) => {
+                let dest = self.expression(&va_copy_arg.0);
+                let src = self.expression(&va_copy_arg.1);
+                self.emit(Inst::VaCopy(dest, src));
+                Operand::Int(0)
+            }
+            Node::Empty => Operand::Int(0),
+            _ => {
+                // 式の位置に現れた文
+                self.statement(node);
+                Operand::Int(0)
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        binary_type: &BinaryType,
+        typename: &Typename,
+        lhs: Operand,
+        rhs: Operand,
+    ) -> Operand {
+        let ir_type = value_type(typename);
+        let floating = is_floating_type(typename);
+        let unsigned = is_unsigned(typename);
+        let arithmetic = |op: BinaryOp, float_op: BinaryOp| if floating { float_op } else { op };
+        let compare = |op: CompareOp, unsigned_op: CompareOp, float_op: CompareOp| {
+            if floating {
+                float_op
+            } else if unsigned {
+                unsigned_op
+            } else {
+                op
+            }
+        };
+        let dst_op = match binary_type {
+            BinaryType::Add => Ok(arithmetic(BinaryOp::Add, BinaryOp::FAdd)),
+            BinaryType::Sub => Ok(arithmetic(BinaryOp::Sub, BinaryOp::FSub)),
+            BinaryType::Mul => Ok(arithmetic(BinaryOp::Mul, BinaryOp::FMul)),
+            BinaryType::Div => Ok(if floating {
+                BinaryOp::FDiv
+            } else if unsigned {
+                BinaryOp::UDiv
+            } else {
+                BinaryOp::SDiv
+            }),
+            BinaryType::Equal => Err(compare(CompareOp::Eq, CompareOp::Eq, CompareOp::FEq)),
+            BinaryType::NotEqual => Err(compare(CompareOp::Ne, CompareOp::Ne, CompareOp::FNe)),
+            BinaryType::Lt => Err(compare(CompareOp::Slt, CompareOp::Ult, CompareOp::FLt)),
+            BinaryType::LtEq => Err(compare(CompareOp::Sle, CompareOp::Ule, CompareOp::FLe)),
+        };
+        match dst_op {
+            Ok(op) => {
+                let dst = self.new_temp(ir_type);
+                self.emit(Inst::Binary(dst, op, ir_type, lhs, rhs));
+                Operand::Temp(dst)
+            }
+            Err(op) => {
+                let dst = self.new_temp(Type::I32);
+                self.emit(Inst::Compare(dst, op, ir_type, lhs, rhs));
+                Operand::Temp(dst)
+            }
+        }
+    }
+
+    // 型fromの値を型toに変換する
+    fn convert(&mut self, value: Operand, from: &Typename, to: &Typename) -> Operand {
+        let (from, to) = (unqualified(from), unqualified(to));
+        let to_type = match Type::from_typename(to) {
+            Some(to_type) => to_type,
+            // voidへのキャストは値を捨てる
+            None => return Operand::Int(0),
+        };
+        let from_type = value_type(from);
+        let op = match to {
+            Typename::Boolean => {
+                // 0と比べた結果を1byteにする
+                let (op, zero) = if from_type.is_float() {
+                    (CompareOp::FNe, Operand::Float(0.0))
+                } else {
+                    (CompareOp::Ne, Operand::Int(0))
+                };
+                let flag = self.new_temp(Type::I32);
+                self.emit(Inst::Compare(flag, op, from_type, value, zero));
+                let dst = self.new_temp(Type::I8);
+                self.emit(Inst::Convert(
+                    dst,
+                    ConvertOp::Trunc,
+                    Type::I32,
+                    Type::I8,
+                    Operand::Temp(flag),
+                ));
+                return Operand::Temp(dst);
+            }
+            _ => match (from_type.is_float(), to_type.is_float()) {
+                (true, true) if from_type.size() < to_type.size() => ConvertOp::FpExt,
+                (true, true) if from_type.size() > to_type.size() => ConvertOp::FpTrunc,
+                (true, false) if is_unsigned(to) => ConvertOp::FpToUi,
+                (true, false) => ConvertOp::FpToSi,
+                (false, true) if is_unsigned(from) => ConvertOp::UiToFp,
+                (false, true) => ConvertOp::SiToFp,
+                (false, false) if from_type.size() < to_type.size() => {
+                    if is_unsigned(from) {
+                        ConvertOp::Zext
+                    } else {
+                        ConvertOp::Sext
+                    }
+                }
+                (false, false) if from_type.size() > to_type.size() => ConvertOp::Trunc,
+                // 同じ大きさの整数・ポインタはビット列のまま
+                _ => return value,
+            },
+        };
+        let dst = self.new_temp(to_type);
+        self.emit(Inst::Convert(dst, op, from_type, to_type, value));
+        Operand::Temp(dst)
+    }
+}
+
+fn is_floating_type(typename: &Typename) -> bool {
+    matches!(unqualified(typename), Typename::Floating(_))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::lower::lower;
+    use crate::ir::sprint_function;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn lower_test() {
+        let program = parse(tokenize(
+            "int f(int n) { int s; s = 0; while (s < n) s = s + 2; return s; }".to_string(),
+        ));
+        let module = lower(&program, false);
+        assert_eq!(
+            sprint_function(&module.functions[0]),
+            "function @f(i32 %0) -> i32 {
+bb0:
+    store i32 local(4), %0
+    store i32 local(8), 0
+    jmp bb1
+bb1:
+    %1 = load i32 local(8)
+    %2 = load i32 local(4)
+    %3 = slt i32 %1, %2
+    br i32 %3, bb2, bb3
+bb2:
+    %4 = load i32 local(8)
+    %5 = add i32 %4, 2
+    store i32 local(8), %5
+    jmp bb1
+bb3:
+    %6 = load i32 local(8)
+    ret i32 %6
+}
+"
+        );
+    }
+}
@@ -0,0 +1,599 @@
+// 構文木とアセンブリの間の中間表現
+//
+// 関数は基本ブロックの列で、各ブロックは命令の列と末尾の分岐 (Terminator) からなる
+// 値は型付きの一時変数 (Temp) で、命令は高々2つのオペランドを取る三番地コード
+pub mod dce;
+pub mod dominator;
+pub mod fold;
+pub mod gvn;
+pub mod indvars;
+pub mod inline;
+pub mod licm;
+pub mod liveness;
+pub mod loops;
+pub mod lower;
+pub mod rotate;
+pub mod ssa;
+pub mod tailcall;
+
+use crate::typename::{unqualified, Inlining, Typename};
+
+// 中間表現の型 (符号の有無は演算の側が持つ)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Type {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Ptr,
+}
+
+impl Type {
+    // C の型に対応する値の型 (voidはNone)
+    pub fn from_typename(typename: &Typename) -> Option<Type> {
+        match unqualified(typename) {
+            Typename::Void => None,
+            Typename::Boolean => Some(Type::I8),
+            Typename::Integer(_, 1) => Some(Type::I8),
+            Typename::Integer(_, 2) => Some(Type::I16),
+            Typename::Integer(_, 4) => Some(Type::I32),
+            Typename::Integer(_, _) => Some(Type::I64),
+            Typename::Floating(4) => Some(Type::F32),
+            Typename::Floating(_) => Some(Type::F64),
+            // 配列と関数は先頭のアドレスとして扱う
+            _ => Some(Type::Ptr),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Type::I8 => 1,
+            Type::I16 => 2,
+            Type::I32 | Type::F32 => 4,
+            Type::I64 | Type::F64 | Type::Ptr => 8,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Temp(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct BlockId(pub usize);
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Operand {
+    Temp(Temp),
+    Int(i64),
+    Float(f64),
+    Local(usize),     // ローカル変数 [base-offset] のアドレス
+    Global(String),   // 静的変数や文字列リテラルのアドレス
+    Function(String), // 関数のアドレス
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    SDiv,
+    UDiv,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+}
+
+// 比較の結果はi32の0か1
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Slt,
+    Sle,
+    Ult,
+    Ule,
+    FEq,
+    FNe, // NaNとの比較は真
+    FLt,
+    FLe,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConvertOp {
+    Sext,
+    Zext,
+    Trunc,
+    SiToFp,
+    UiToFp,
+    FpToSi,
+    FpToUi,
+    FpExt,
+    FpTrunc,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Inst {
+    Copy(Temp, Type, Operand),                        // dst, type, src
+    Binary(Temp, BinaryOp, Type, Operand, Operand),   // dst, op, type, lhs, rhs
+    Compare(Temp, CompareOp, Type, Operand, Operand), // dst, op, operand type, lhs, rhs
+    Convert(Temp, ConvertOp, Type, Type, Operand),    // dst, op, from, to, src
+    Load(Temp, Type, Operand, bool),                  // dst, type, address, volatile
+    Store(Type, Operand, Operand, bool),              // type, address, value, volatile
+    Call(Option<(Temp, Type)>, Operand, Vec<(Operand, Type)>, bool), // dst, callee, arg[], variadic
+    VaStart(Operand),                                 // va_list address
+    VaArg(Temp, Type, Operand),                       // dst, type, va_list address
+    VaCopy(Operand, Operand),                         // dest, src
+    Phi(Temp, Type, Vec<(BlockId, Operand)>),         // dst, type, (predecessor, value)[]
+}
+
+impl Inst {
+    // 命令が値を書き込む一時変数
+    pub fn dst(&self) -> Option<Temp> {
+        match self {
+            Inst::Copy(dst, _, _)
+            | Inst::Binary(dst, _, _, _, _)
+            | Inst::Compare(dst, _, _, _, _)
+            | Inst::Convert(dst, _, _, _, _)
+            | Inst::Load(dst, _, _, _)
+            | Inst::VaArg(dst, _, _)
+            | Inst::Phi(dst, _, _) => Some(*dst),
+            Inst::Call(dst, _, _, _) => dst.map(|(dst, _)| dst),
+            Inst::Store(_, _, _, _) | Inst::VaStart(_) | Inst::VaCopy(_, _) => None,
+        }
+    }
+
+    pub fn dst_mut(&mut self) -> Option<&mut Temp> {
+        match self {
+            Inst::Copy(dst, _, _)
+            | Inst::Binary(dst, _, _, _, _)
+            | Inst::Compare(dst, _, _, _, _)
+            | Inst::Convert(dst, _, _, _, _)
+            | Inst::Load(dst, _, _, _)
+            | Inst::VaArg(dst, _, _)
+            | Inst::Phi(dst, _, _) => Some(dst),
+            Inst::Call(dst, _, _, _) => dst.as_mut().map(|(dst, _)| dst),
+            Inst::Store(_, _, _, _) | Inst::VaStart(_) | Inst::VaCopy(_, _) => None,
+        }
+    }
+
+    // 命令が読むオペランド
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Inst::Copy(_, _, src) | Inst::Convert(_, _, _, _, src) => vec![src],
+            Inst::Binary(_, _, _, lhs, rhs) | Inst::Compare(_, _, _, lhs, rhs) => vec![lhs, rhs],
+            Inst::Load(_, _, address, _) => vec![address],
+            Inst::Store(_, address, value, _) => vec![address, value],
+            Inst::Call(_, callee, args, _) => {
+                let mut operands = vec![callee];
+                operands.extend(args.iter().map(|(arg, _)| arg));
+                operands
+            }
+            Inst::VaStart(ap) | Inst::VaArg(_, _, ap) => vec![ap],
+            Inst::VaCopy(dest, src) => vec![dest, src],
+            Inst::Phi(_, _, incoming) => incoming.iter().map(|(_, value)| value).collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Copy(_, _, src) | Inst::Convert(_, _, _, _, src) => vec![src],
+            Inst::Binary(_, _, _, lhs, rhs) | Inst::Compare(_, _, _, lhs, rhs) => vec![lhs, rhs],
+            Inst::Load(_, _, address, _) => vec![address],
+            Inst::Store(_, address, value, _) => vec![address, value],
+            Inst::Call(_, callee, args, _) => {
+                let mut operands = vec![callee];
+                operands.extend(args.iter_mut().map(|(arg, _)| arg));
+                operands
+            }
+            Inst::VaStart(ap) | Inst::VaArg(_, _, ap) => vec![ap],
+            Inst::VaCopy(dest, src) => vec![dest, src],
+            Inst::Phi(_, _, incoming) => incoming.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch(Operand, Type, BlockId, BlockId), // cond (0以外なら真), type, then, else
+    Return(Option<(Operand, Type)>),
+    // 呼び出し先へ飛んで、呼び出し先の戻り値をそのまま返す
+    TailCall(Operand, Vec<(Operand, Type)>, bool), // callee, arg[], variadic
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, _, then_block, else_block) => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::TailCall(_, _, _) => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, _, then_block, else_block) => vec![then_block, else_block],
+            Terminator::Return(_) | Terminator::TailCall(_, _, _) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+            Terminator::Branch(cond, _, _, _) => vec![cond],
+            Terminator::Return(Some((value, _))) => vec![value],
+            Terminator::TailCall(callee, args, _) => {
+                let mut operands = vec![callee];
+                operands.extend(args.iter().map(|(arg, _)| arg));
+                operands
+            }
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+            Terminator::Branch(cond, _, _, _) => vec![cond],
+            Terminator::Return(Some((value, _))) => vec![value],
+            Terminator::TailCall(callee, args, _) => {
+                let mut operands = vec![callee];
+                operands.extend(args.iter_mut().map(|(arg, _)| arg));
+                operands
+            }
+        }
+    }
+}
+
+// 未定義の値 (初期化していない変数の読み出しなど) の代わりに使う0
+pub fn zero(typename: &Type) -> Operand {
+    if typename.is_float() {
+        Operand::Float(0.0)
+    } else {
+        Operand::Int(0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Temp>,
+    pub ret: Option<Type>,
+    pub variadic: bool,
+    pub is_static: bool,
+    pub inlining: Inlining,
+    pub blocks: Vec<Block>, // blocks[0] が入口
+    pub temp_types: Vec<Type>,
+    pub local_var_size: usize,
+}
+
+impl Function {
+    pub fn new_temp(&mut self, typename: Type) -> Temp {
+        self.temp_types.push(typename);
+        Temp(self.temp_types.len() - 1)
+    }
+
+    pub fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block {
+            insts: Vec::new(),
+            terminator: Terminator::Return(None),
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0]
+    }
+
+    // 各ブロックの先行ブロック
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                predecessors[successor.0].push(BlockId(id));
+            }
+        }
+        predecessors
+    }
+}
+
+// 静的記憶域の変数
+#[derive(Clone, Debug)]
+pub struct Global {
+    pub label: String,
+    pub size: usize,
+    pub align: usize,
+    pub is_static: bool,
+    pub readonly: bool,
+    pub initializer: Option<Operand>, // Int, Float, Global (アドレス) のいずれか
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+    pub strings: Vec<Vec<u8>>, // .LC{n}
+}
+
+pub fn sprint_type(typename: &Type) -> String {
+    match typename {
+        Type::I8 => "i8",
+        Type::I16 => "i16",
+        Type::I32 => "i32",
+        Type::I64 => "i64",
+        Type::F32 => "f32",
+        Type::F64 => "f64",
+        Type::Ptr => "ptr",
+    }
+    .to_string()
+}
+
+pub fn sprint_operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Temp(temp) => format!("%{}", temp.0),
+        Operand::Int(n) => n.to_string(),
+        Operand::Float(x) => format!("{:?}", x),
+        Operand::Local(offset) => format!("local({})", offset),
+        Operand::Global(label) | Operand::Function(label) => format!("@{}", label),
+    }
+}
+
+fn sprint_binary_op(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Sub => "sub",
+        BinaryOp::Mul => "mul",
+        BinaryOp::SDiv => "sdiv",
+        BinaryOp::UDiv => "udiv",
+        BinaryOp::FAdd => "fadd",
+        BinaryOp::FSub => "fsub",
+        BinaryOp::FMul => "fmul",
+        BinaryOp::FDiv => "fdiv",
+    }
+}
+
+fn sprint_compare_op(op: &CompareOp) -> &'static str {
+    match op {
+        CompareOp::Eq => "eq",
+        CompareOp::Ne => "ne",
+        CompareOp::Slt => "slt",
+        CompareOp::Sle => "sle",
+        CompareOp::Ult => "ult",
+        CompareOp::Ule => "ule",
+        CompareOp::FEq => "feq",
+        CompareOp::FNe => "fne",
+        CompareOp::FLt => "flt",
+        CompareOp::FLe => "fle",
+    }
+}
+
+fn sprint_convert_op(op: &ConvertOp) -> &'static str {
+    match op {
+        ConvertOp::Sext => "sext",
+        ConvertOp::Zext => "zext",
+        ConvertOp::Trunc => "trunc",
+        ConvertOp::SiToFp => "sitofp",
+        ConvertOp::UiToFp => "uitofp",
+        ConvertOp::FpToSi => "fptosi",
+        ConvertOp::FpToUi => "fptoui",
+        ConvertOp::FpExt => "fpext",
+        ConvertOp::FpTrunc => "fptrunc",
+    }
+}
+
+fn sprint_volatile(volatile: bool) -> &'static str {
+    if volatile {
+        "volatile "
+    } else {
+        ""
+    }
+}
+
+pub fn sprint_inst(inst: &Inst) -> String {
+    match inst {
+        Inst::Copy(dst, typename, src) => format!(
+            "%{} = copy {} {}",
+            dst.0,
+            sprint_type(typename),
+            sprint_operand(src)
+        ),
+        Inst::Binary(dst, op, typename, lhs, rhs) => format!(
+            "%{} = {} {} {}, {}",
+            dst.0,
+            sprint_binary_op(op),
+            sprint_type(typename),
+            sprint_operand(lhs),
+            sprint_operand(rhs)
+        ),
+        Inst::Compare(dst, op, typename, lhs, rhs) => format!(
+            "%{} = {} {} {}, {}",
+            dst.0,
+            sprint_compare_op(op),
+            sprint_type(typename),
+            sprint_operand(lhs),
+            sprint_operand(rhs)
+        ),
+        Inst::Convert(dst, op, from, to, src) => format!(
+            "%{} = {} {} {} to {}",
+            dst.0,
+            sprint_convert_op(op),
+            sprint_type(from),
+            sprint_operand(src),
+            sprint_type(to)
+        ),
+        Inst::Load(dst, typename, address, volatile) => format!(
+            "%{} = load {}{} {}",
+            dst.0,
+            sprint_volatile(*volatile),
+            sprint_type(typename),
+            sprint_operand(address)
+        ),
+        Inst::Store(typename, address, value, volatile) => format!(
+            "store {}{} {}, {}",
+            sprint_volatile(*volatile),
+            sprint_type(typename),
+            sprint_operand(address),
+            sprint_operand(value)
+        ),
+        Inst::Call(dst, callee, args, variadic) => format!(
+            "{}call {} {}({})",
+            match dst {
+                Some((dst, _)) => format!("%{} = ", dst.0),
+                None => String::new(),
+            },
+            match dst {
+                Some((_, typename)) => sprint_type(typename),
+                None => "void".to_string(),
+            },
+            sprint_operand(callee),
+            sprint_args(
+                args.iter()
+                    .map(|(arg, typename)| format!(
+                        "{} {}",
+                        sprint_type(typename),
+                        sprint_operand(arg)
+                    ))
+                    .collect(),
+                *variadic
+            )
+        ),
+        Inst::VaStart(ap) => format!("va_start {}", sprint_operand(ap)),
+        Inst::VaArg(dst, typename, ap) => format!(
+            "%{} = va_arg {} {}",
+            dst.0,
+            sprint_type(typename),
+            sprint_operand(ap)
+        ),
+        Inst::VaCopy(dest, src) => {
+            format!("va_copy {}, {}", sprint_operand(dest), sprint_operand(src))
+        }
+        Inst::Phi(dst, typename, incoming) => format!(
+            "%{} = phi {} {}",
+            dst.0,
+            sprint_type(typename),
+            incoming
+                .iter()
+                .map(|(block, value)| format!("[bb{}: {}]", block.0, sprint_operand(value)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
+
+// 引数の並び (可変長なら最後に ... を付ける)
+fn sprint_args(args: Vec<String>, variadic: bool) -> String {
+    match (variadic, args.is_empty()) {
+        (false, _) => args.join(", "),
+        (true, true) => "...".to_string(),
+        (true, false) => format!("{}, ...", args.join(", ")),
+    }
+}
+
+pub fn sprint_terminator(terminator: &Terminator) -> String {
+    match terminator {
+        Terminator::Jump(target) => format!("jmp bb{}", target.0),
+        Terminator::Branch(cond, typename, then_block, else_block) => format!(
+            "br {} {}, bb{}, bb{}",
+            sprint_type(typename),
+            sprint_operand(cond),
+            then_block.0,
+            else_block.0
+        ),
+        Terminator::Return(None) => "ret void".to_string(),
+        Terminator::Return(Some((value, typename))) => {
+            format!("ret {} {}", sprint_type(typename), sprint_operand(value))
+        }
+        Terminator::TailCall(callee, args, variadic) => format!(
+            "tail call {}({})",
+            sprint_operand(callee),
+            sprint_args(
+                args.iter()
+                    .map(|(arg, typename)| format!(
+                        "{} {}",
+                        sprint_type(typename),
+                        sprint_operand(arg)
+                    ))
+                    .collect(),
+                *variadic
+            )
+        ),
+    }
+}
+
+pub fn sprint_function(function: &Function) -> String {
+    let params: Vec<String> = function
+        .params
+        .iter()
+        .map(|param| {
+            format!(
+                "{} %{}",
+                sprint_type(&function.temp_types[param.0]),
+                param.0
+            )
+        })
+        .collect();
+    let mut output = format!(
+        "{}function @{}({}) -> {} {{\n",
+        if function.is_static { "static " } else { "" },
+        function.name,
+        sprint_args(params, function.variadic),
+        match &function.ret {
+            Some(typename) => sprint_type(typename),
+            None => "void".to_string(),
+        }
+    );
+    for (id, block) in function.blocks.iter().enumerate() {
+        output += &format!("bb{}:\n", id);
+        for inst in block.insts.iter() {
+            output += &format!("    {}\n", sprint_inst(inst));
+        }
+        output += &format!("    {}\n", sprint_terminator(&block.terminator));
+    }
+    output + "}\n"
+}
+
+pub fn sprint_module(module: &Module) -> String {
+    let mut output = String::new();
+    for global in module.globals.iter() {
+        output += &format!(
+            "{}{}global @{} (size {}, align {}){}\n",
+            if global.is_static { "static " } else { "" },
+            if global.readonly { "const " } else { "" },
+            global.label,
+            global.size,
+            global.align,
+            match &global.initializer {
+                Some(value) => format!(" = {}", sprint_operand(value)),
+                None => String::new(),
+            }
+        );
+    }
+    for (label, bytes) in module.strings.iter().enumerate() {
+        output += &format!(
+            "string @.LC{} = {:?}\n",
+            label,
+            String::from_utf8_lossy(bytes)
+        );
+    }
+    for function in module.functions.iter() {
+        output += &sprint_function(function);
+    }
+    output
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use super::loops::{Loop, LoopForest};
+use super::{BlockId, Function, Inst, Operand, Temp, Terminator};
+
+// 複製するヘッダの命令数の上限
+const ROTATE_LIMIT: usize = 16;
+
+fn has_phi(function: &Function, block: BlockId) -> bool {
+    matches!(
+        function.block(block).insts.first(),
+        Some(Inst::Phi(_, _, _))
+    )
+}
+
+// 回転できるループなら (ループの外から入るブロック, ヘッダの次に進むループ内のブロック)
+fn rotatable(function: &Function, lp: &Loop) -> Option<(BlockId, BlockId)> {
+    let header = function.block(lp.header);
+    if lp.header == BlockId(0) || has_phi(function, lp.header) || header.insts.len() > ROTATE_LIMIT
+    {
+        return None;
+    }
+    // ヘッダの分岐の片方だけがループの中に進む
+    let inside = match header.terminator {
+        Terminator::Branch(_, _, then_block, else_block) => {
+            match (lp.contains(then_block), lp.contains(else_block)) {
+                (true, false) => then_block,
+                (false, true) => else_block,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if inside == lp.header {
+        return None;
+    }
+    let outside: Vec<BlockId> = function.predecessors()[lp.header.0]
+        .iter()
+        .filter(|predecessor| !lp.contains(**predecessor))
+        .cloned()
+        .collect();
+    let entering = match outside.as_slice() {
+        [block] if function.block(*block).terminator == Terminator::Jump(lp.header) => *block,
+        _ => return None,
+    };
+    let latches_jump = lp
+        .latches
+        .iter()
+        .all(|latch| function.block(*latch).terminator == Terminator::Jump(lp.header));
+    let successors_have_phi = header
+        .terminator
+        .successors()
+        .iter()
+        .any(|successor| has_phi(function, *successor));
+    if !latches_jump || successors_have_phi {
+        return None;
+    }
+    // ヘッダで計算した値はヘッダの中でしか使わない
+    let defined: Vec<Temp> = header.insts.iter().filter_map(|inst| inst.dst()).collect();
+    for (id, block) in function.blocks.iter().enumerate() {
+        if BlockId(id) == lp.header {
+            continue;
+        }
+        let operands = block
+            .insts
+            .iter()
+            .flat_map(|inst| inst.operands())
+            .chain(block.terminator.operands());
+        for operand in operands {
+            if let Operand::Temp(temp) = operand {
+                if defined.contains(temp) {
+                    return None;
+                }
+            }
+        }
+    }
+    Some((entering, inside))
+}
+
+// ヘッダの判定をループの手前と各後退辺の始点に複製し、ループを
+//   if (cond) { do { body } while (cond); }
+// の形にする
+// 元のヘッダは空にして前置ブロックとして残す
+fn rotate_loop(function: &mut Function, lp: &Loop, entering: BlockId, inside: BlockId) {
+    let insts = std::mem::take(&mut function.block_mut(lp.header).insts);
+    let terminator = std::mem::replace(
+        &mut function.block_mut(lp.header).terminator,
+        Terminator::Jump(inside),
+    );
+    for latch in lp.latches.iter() {
+        // 複製した命令の結果は新しい一時変数に書く
+        let mut renamed: HashMap<Temp, Temp> = HashMap::new();
+        let remap = |operand: &mut Operand, renamed: &HashMap<Temp, Temp>| {
+            if let Operand::Temp(temp) = operand {
+                if let Some(new_temp) = renamed.get(temp) {
+                    *temp = *new_temp;
+                }
+            }
+        };
+        let mut copies: Vec<Inst> = Vec::new();
+        for inst in insts.iter() {
+            let mut inst = inst.clone();
+            for operand in inst.operands_mut() {
+                remap(operand, &renamed);
+            }
+            if let Some(dst) = inst.dst_mut() {
+                let new_temp = function.new_temp(function.temp_types[dst.0]);
+                renamed.insert(*dst, new_temp);
+                *dst = new_temp;
+            }
+            copies.push(inst);
+        }
+        let mut latch_terminator = terminator.clone();
+        for operand in latch_terminator.operands_mut() {
+            remap(operand, &renamed);
+        }
+        let block = function.block_mut(*latch);
+        block.insts.extend(copies);
+        block.terminator = latch_terminator;
+    }
+    // ループに入る前の判定はループに入るなら元のヘッダに進む
+    let mut guard = terminator;
+    for successor in guard.successors_mut() {
+        if *successor == inside {
+            *successor = lp.header;
+        }
+    }
+    let block = function.block_mut(entering);
+    block.insts.extend(insts);
+    block.terminator = guard;
+}
+
+// 判定が先頭にあるループを、判定が末尾にあるループに変える
+// 1回の繰り返しで通る分岐が「先頭へのjmp」と「条件分岐」から条件分岐1つになる
+pub fn rotate(function: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let forest = LoopForest::new(function);
+        let target = forest
+            .loops
+            .iter()
+            .find_map(|lp| rotatable(function, lp).map(|blocks| (lp, blocks)));
+        match target {
+            Some((lp, (entering, inside))) => {
+                rotate_loop(function, lp, entering, inside);
+                changed = true;
+            }
+            None => return changed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::lower::lower;
+    use crate::ir::rotate::rotate;
+    use crate::ir::sprint_function;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn rotate_test() {
+        let program = parse(tokenize(
+            "int f(int n) { int s; s = 0; while (s < n) s = s + 2; return s; }".to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        rotate(&mut function);
+        // 判定は手前と末尾に1つずつ、bb1は前置ブロックとして残る
+        assert_eq!(
+            sprint_function(&function),
+            "function @f(i32 %0) -> i32 {
+bb0:
+    store i32 local(4), %0
+    store i32 local(8), 0
+    %1 = load i32 local(8)
+    %2 = load i32 local(4)
+    %3 = slt i32 %1, %2
+    br i32 %3, bb1, bb3
+bb1:
+    jmp bb2
+bb2:
+    %4 = load i32 local(8)
+    %5 = add i32 %4, 2
+    store i32 local(8), %5
+    %7 = load i32 local(8)
+    %8 = load i32 local(4)
+    %9 = slt i32 %7, %8
+    br i32 %9, bb2, bb3
+bb3:
+    %6 = load i32 local(8)
+    ret i32 %6
+}
+"
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+
+use super::dominator::DominatorTree;
+use super::liveness::{temps, Liveness};
+use super::{zero, BlockId, Function, Inst, Operand, Temp, Terminator, Type};
+
+// アドレスを取られず、同じ型で読み書きされるだけのローカル変数 (offset -> 型)
+fn promotable_locals(function: &Function) -> HashMap<usize, Type> {
+    let mut types: HashMap<usize, Option<Type>> = HashMap::new();
+    let mut access = |offset: usize, typename: Option<Type>| {
+        let entry = types.entry(offset).or_insert(typename);
+        if *entry != typename {
+            *entry = None;
+        }
+    };
+    for block in function.blocks.iter() {
+        for inst in block.insts.iter() {
+            // 読み書きの対象以外の位置に現れたらアドレスが漏れている
+            let address = match inst {
+                Inst::Load(_, typename, Operand::Local(offset), volatile)
+                | Inst::Store(typename, Operand::Local(offset), _, volatile) => {
+                    access(*offset, if *volatile { None } else { Some(*typename) });
+                    Some(*offset)
+                }
+                _ => None,
+            };
+            let mut operands = inst.operands();
+            if address.is_some() {
+                // Load, Storeの先頭のオペランドはアドレス
+                operands.remove(0);
+            }
+            for operand in operands {
+                if let Operand::Local(offset) = operand {
+                    access(*offset, None);
+                }
+            }
+        }
+        for operand in block.terminator.operands() {
+            if let Operand::Local(offset) = operand {
+                access(*offset, None);
+            }
+        }
+    }
+    types
+        .into_iter()
+        .filter_map(|(offset, typename)| typename.map(|typename| (offset, typename)))
+        .collect()
+}
+
+// スカラーのローカル変数を一時変数に昇格してSSA形式にする
+// phiは支配辺境の反復で置き、支配木をたどって名前を付け替える
+// 昇格した変数があればtrueを返す
+pub fn construct(function: &mut Function) -> bool {
+    let locals = promotable_locals(function);
+    if locals.is_empty() {
+        return false;
+    }
+    let tree = DominatorTree::new(function);
+    let frontiers = tree.frontiers(function);
+    let predecessors = function.predecessors();
+
+    // 変数ごとに、代入のあるブロックの反復支配辺境にphiを置く
+    let mut offsets: Vec<usize> = locals.keys().cloned().collect();
+    offsets.sort_unstable();
+    let mut phis: Vec<Vec<(usize, Temp)>> = vec![Vec::new(); function.blocks.len()];
+    for offset in offsets.iter() {
+        let typename = locals[offset];
+        let mut worklist: Vec<BlockId> = Vec::new();
+        for (id, block) in function.blocks.iter().enumerate() {
+            let defines = block.insts.iter().any(
+                |inst| matches!(inst, Inst::Store(_, Operand::Local(target), _, _) if target == offset),
+            );
+            if defines && tree.is_reachable(BlockId(id)) {
+                worklist.push(BlockId(id));
+            }
+        }
+        let mut placed = vec![false; function.blocks.len()];
+        while let Some(block) = worklist.pop() {
+            for frontier in frontiers[block.0].iter() {
+                if placed[frontier.0] {
+                    continue;
+                }
+                placed[frontier.0] = true;
+                let dst = function.new_temp(typename);
+                phis[frontier.0].push((*offset, dst));
+                worklist.push(*frontier);
+            }
+        }
+    }
+    for (id, block_phis) in phis.iter().enumerate() {
+        let incoming: Vec<Inst> = block_phis
+            .iter()
+            .map(|(offset, dst)| {
+                let typename = locals[offset];
+                Inst::Phi(
+                    *dst,
+                    typename,
+                    predecessors[id]
+                        .iter()
+                        .map(|predecessor| (*predecessor, zero(&typename)))
+                        .collect(),
+                )
+            })
+            .collect();
+        function.blocks[id].insts.splice(0..0, incoming);
+    }
+
+    let mut renaming = Renaming {
+        locals,
+        phis,
+        stacks: HashMap::new(),
+        replace: HashMap::new(),
+    };
+    renaming.rename(function, &tree, BlockId(0));
+    true
+}
+
+struct Renaming {
+    locals: HashMap<usize, Type>,
+    phis: Vec<Vec<(usize, Temp)>>, // ブロックごとの (変数のoffset, phiの値)
+    stacks: HashMap<usize, Vec<Operand>>, // 変数の現在の値
+    replace: HashMap<Temp, Operand>, // 昇格した変数の読み出しを置き換える値
+}
+
+impl Renaming {
+    fn current(&self, offset: usize) -> Operand {
+        match self.stacks.get(&offset).and_then(|stack| stack.last()) {
+            Some(value) => value.clone(),
+            // 代入より前の読み出しは未定義
+            None => zero(&self.locals[&offset]),
+        }
+    }
+
+    fn rewrite(&self, operand: &mut Operand) {
+        if let Operand::Temp(temp) = operand {
+            if let Some(value) = self.replace.get(temp) {
+                *operand = value.clone();
+            }
+        }
+    }
+
+    fn rename(&mut self, function: &mut Function, tree: &DominatorTree, block: BlockId) {
+        let mut pushed: Vec<usize> = Vec::new();
+        for (offset, dst) in self.phis[block.0].clone() {
+            self.stacks
+                .entry(offset)
+                .or_default()
+                .push(Operand::Temp(dst));
+            pushed.push(offset);
+        }
+        let insts = std::mem::take(&mut function.block_mut(block).insts);
+        let mut renamed: Vec<Inst> = Vec::new();
+        for mut inst in insts {
+            if let Inst::Phi(_, _, _) = inst {
+                // phiの引数は先行ブロックの側で埋める
+                renamed.push(inst);
+                continue;
+            }
+            for operand in inst.operands_mut() {
+                self.rewrite(operand);
+            }
+            match inst {
+                Inst::Load(dst, _, Operand::Local(offset), _)
+                    if self.locals.contains_key(&offset) =>
+                {
+                    let value = self.current(offset);
+                    self.replace.insert(dst, value);
+                }
+                Inst::Store(_, Operand::Local(offset), value, _)
+                    if self.locals.contains_key(&offset) =>
+                {
+                    self.stacks.entry(offset).or_default().push(value);
+                    pushed.push(offset);
+                }
+                inst => renamed.push(inst),
+            }
+        }
+        function.block_mut(block).insts = renamed;
+        let mut terminator = function.block(block).terminator.clone();
+        for operand in terminator.operands_mut() {
+            self.rewrite(operand);
+        }
+        function.block_mut(block).terminator = terminator;
+
+        // 後続ブロックのphiにこのブロックから来たときの値を入れる
+        // 前に作ったphiの引数も、昇格した変数の読み出しなら置き換える
+        for successor in function.block(block).terminator.successors() {
+            let values: Vec<(Temp, Operand)> = self.phis[successor.0]
+                .iter()
+                .map(|(offset, dst)| (*dst, self.current(*offset)))
+                .collect();
+            for inst in function.block_mut(successor).insts.iter_mut() {
+                if let Inst::Phi(dst, _, incoming) = inst {
+                    let value = values.iter().find(|(phi, _)| phi == dst);
+                    for (predecessor, incoming_value) in incoming.iter_mut() {
+                        if *predecessor != block {
+                            continue;
+                        }
+                        match value {
+                            Some((_, value)) => *incoming_value = value.clone(),
+                            None => self.rewrite(incoming_value),
+                        }
+                    }
+                }
+            }
+        }
+
+        for child in tree.children[block.0].clone() {
+            self.rename(function, tree, child);
+        }
+        for offset in pushed {
+            self.stacks.get_mut(&offset).unwrap().pop();
+        }
+    }
+}
+
+// phiを先行ブロックの末尾のコピーに置き換えてSSA形式から戻す
+pub fn destruct(function: &mut Function) {
+    split_critical_edges(function);
+    let mut copies: Vec<Vec<(Temp, Type, Operand)>> = vec![Vec::new(); function.blocks.len()];
+    for block in function.blocks.iter_mut() {
+        let count = block
+            .insts
+            .iter()
+            .take_while(|inst| matches!(inst, Inst::Phi(_, _, _)))
+            .count();
+        for phi in block.insts.drain(0..count) {
+            if let Inst::Phi(dst, typename, incoming) = phi {
+                for (predecessor, value) in incoming {
+                    copies[predecessor.0].push((dst, typename, value));
+                }
+            }
+        }
+    }
+    for (id, parallel) in copies.into_iter().enumerate() {
+        let sequence = sequentialize(function, parallel);
+        function.blocks[id].insts.extend(sequence);
+    }
+}
+
+// phiのコピーをpredecessorの分岐の前に置けるか
+// コピー先が分岐の条件にも、もう一方の後続ブロックの入口で生きている値にも使われていなければよい
+// (ループの後退辺ではコピーを末尾に置けば、1回の繰り返しの分岐が1つで済む)
+fn can_copy_before_branch(
+    function: &Function,
+    liveness: &Liveness,
+    predecessor: BlockId,
+    successor: BlockId,
+) -> bool {
+    let terminator = &function.block(predecessor).terminator;
+    let successors = terminator.successors();
+    if successors.iter().filter(|block| **block == successor).count() != 1 {
+        return false;
+    }
+    let dsts: Vec<Temp> = function
+        .block(successor)
+        .insts
+        .iter()
+        .take_while(|inst| matches!(inst, Inst::Phi(_, _, _)))
+        .filter_map(|inst| inst.dst())
+        .collect();
+    let cond = temps(terminator.operands());
+    dsts.iter().all(|dst| {
+        !cond.contains(dst)
+            && successors
+                .iter()
+                .filter(|other| **other != successor)
+                .all(|other| !liveness.live_in[other.0].contains(dst))
+    })
+}
+
+// phiのあるブロックへの、後続が複数あるブロックからの辺に空のブロックを挟む
+// 後続ブロックのうち1つ分のコピーは、置けるなら分岐の前に置いて辺を分けない
+fn split_critical_edges(function: &mut Function) {
+    // 生存区間は元のブロックについてだけ調べる (辺を分ける前にコピーの置き場所を決める)
+    let liveness = Liveness::new(function);
+    for id in 0..function.blocks.len() {
+        let successors = function.blocks[id].terminator.successors();
+        if successors.len() < 2 {
+            continue;
+        }
+        let has_phi: Vec<bool> = successors
+            .iter()
+            .map(|successor| {
+                matches!(
+                    function.block(*successor).insts.first(),
+                    Some(Inst::Phi(_, _, _))
+                )
+            })
+            .collect();
+        let copied_before_branch = (0..successors.len()).find(|index| {
+            has_phi[*index]
+                && can_copy_before_branch(function, &liveness, BlockId(id), successors[*index])
+        });
+        for (index, successor) in successors.into_iter().enumerate() {
+            if !has_phi[index] || copied_before_branch == Some(index) {
+                continue;
+            }
+            let middle = function.new_block();
+            function.block_mut(middle).terminator = Terminator::Jump(successor);
+            *function.blocks[id].terminator.successors_mut()[index] = middle;
+            // 同じ辺が2本あるときは1本ずつ付け替える
+            for inst in function.block_mut(successor).insts.iter_mut() {
+                if let Inst::Phi(_, _, incoming) = inst {
+                    if let Some(entry) =
+                        incoming.iter_mut().find(|(block, _)| *block == BlockId(id))
+                    {
+                        entry.0 = middle;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 並行コピー (全ての右辺を読んでから左辺に書く) を順に実行できるコピーの列にする
+fn sequentialize(function: &mut Function, parallel: Vec<(Temp, Type, Operand)>) -> Vec<Inst> {
+    let mut pending: Vec<(Temp, Type, Operand)> = parallel
+        .into_iter()
+        .filter(|(dst, _, src)| *src != Operand::Temp(*dst))
+        .collect();
+    let mut sequence: Vec<Inst> = Vec::new();
+    while !pending.is_empty() {
+        // 他のコピーが読まない左辺なら先に書いてよい
+        let ready = (0..pending.len()).find(|index| {
+            let dst = Operand::Temp(pending[*index].0);
+            pending.iter().all(|(_, _, src)| *src != dst)
+        });
+        match ready {
+            Some(index) => {
+                let (dst, typename, src) = pending.remove(index);
+                sequence.push(Inst::Copy(dst, typename, src));
+            }
+            None => {
+                // 循環しているので、1つの左辺の値を逃がしてから読み替える
+                let (dst, typename, _) = pending[0].clone();
+                let saved = function.new_temp(typename);
+                sequence.push(Inst::Copy(saved, typename, Operand::Temp(dst)));
+                for (_, _, src) in pending.iter_mut() {
+                    if *src == Operand::Temp(dst) {
+                        *src = Operand::Temp(saved);
+                    }
+                }
+            }
+        }
+    }
+    sequence
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::dce::dce;
+    use crate::ir::lower::lower;
+    use crate::ir::ssa::{construct, destruct};
+    use crate::ir::{sprint_function, Inst};
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn ssa_test() {
+        let program = parse(tokenize(
+            "int f(int n) { int s; int *p; s = 0; p = &n; while (s < *p) s = s + 2; return s; }"
+                .to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        construct(&mut function);
+        // sとpは昇格し、アドレスを取られたnは昇格しない
+        assert_eq!(
+            sprint_function(&function),
+            "function @f(i32 %0) -> i32 {
+bb0:
+    store i32 local(4), %0
+    jmp bb1
+bb1:
+    %8 = phi i32 [bb0: 0], [bb2: %6]
+    %3 = load i32 local(4)
+    %4 = slt i32 %8, %3
+    br i32 %4, bb2, bb3
+bb2:
+    %6 = add i32 %8, 2
+    jmp bb1
+bb3:
+    ret i32 %8
+}
+"
+        );
+        destruct(&mut function);
+        let phis = function
+            .blocks
+            .iter()
+            .flat_map(|block| block.insts.iter())
+            .filter(|inst| matches!(inst, Inst::Phi(_, _, _)))
+            .count();
+        assert_eq!(phis, 0);
+    }
+
+    #[test]
+    fn construct_twice_test() {
+        let program = parse(tokenize(
+            "int f() { int a; int b; int i; int t; int *p; a = 1; b = 2; for (i = 0; i < 5; i = i + 1) { t = a; a = b; b = t; } p = &a; return a * 10 + b; }"
+                .to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        construct(&mut function);
+        dce(&mut function);
+        // p = &a が消えたのでaも昇格する (前に作ったphiの引数もaの値に置き換わる)
+        construct(&mut function);
+        dce(&mut function);
+        assert_eq!(
+            sprint_function(&function),
+            "function @f() -> i32 {
+bb0:
+    jmp bb1
+bb1:
+    %14 = phi i32 [bb0: 1], [bb2: %11]
+    %11 = phi i32 [bb0: 2], [bb2: %14]
+    %12 = phi i32 [bb0: 0], [bb2: %6]
+    %1 = slt i32 %12, 5
+    br i32 %1, bb2, bb3
+bb2:
+    %6 = add i32 %12, 1
+    jmp bb1
+bb3:
+    %8 = mul i32 %14, 10
+    %10 = add i32 %8, %11
+    ret i32 %10
+}
+"
+        );
+    }
+}
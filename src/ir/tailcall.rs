@@ -0,0 +1,256 @@
+use super::inline::is_compatible_call;
+use super::{BlockId, ConvertOp, Function, Inst, Operand, Temp, Terminator, Type};
+
+// レジスタで渡せる引数の数 (rdi, rsi, rdx, rcx, r8, r9 と xmm0-7)
+const INT_ARG_REGISTERS: usize = 6;
+const FLOAT_ARG_REGISTERS: usize = 8;
+
+// blockが「呼び出しの結果をそのまま返す」形なら (callee, arg[])
+fn tail_site(function: &Function, block: BlockId) -> Option<(&Operand, &[(Operand, Type)])> {
+    let block = function.block(block);
+    let (dst, callee, args) = match block.insts.last() {
+        Some(Inst::Call(dst, callee, args, _)) => (dst, callee, args),
+        _ => return None,
+    };
+    let returns_result = match (&block.terminator, dst) {
+        // 値を返さない関数では呼び出しの結果は捨ててよい
+        (Terminator::Return(None), _) => function.ret.is_none(),
+        (Terminator::Return(Some((Operand::Temp(value), typename))), Some((dst, dst_type))) => {
+            value == dst && typename == dst_type
+        }
+        _ => false,
+    };
+    if returns_result {
+        Some((callee, args))
+    } else {
+        None
+    }
+}
+
+// ローカル変数のアドレスを値として使う (呼び出し先がこのフレームを読むかもしれない)
+fn has_escaping_local(function: &Function) -> bool {
+    let is_local = |operand: &&Operand| matches!(operand, Operand::Local(_));
+    function.blocks.iter().any(|block| {
+        block.insts.iter().any(|inst| {
+            let mut operands = inst.operands();
+            if let Inst::Load(_, _, _, _) | Inst::Store(_, _, _, _) = inst {
+                // Load, Storeの先頭のオペランドはアドレス
+                operands.remove(0);
+            }
+            operands.iter().any(is_local)
+        }) || block.terminator.operands().iter().any(is_local)
+    })
+}
+
+// 引数を全てレジスタで渡せるか (スタック渡しの引数は呼び出し元のフレームに積むことになる)
+fn fits_in_registers(args: &[(Operand, Type)]) -> bool {
+    let floats = args
+        .iter()
+        .filter(|(_, typename)| typename.is_float())
+        .count();
+    floats <= FLOAT_ARG_REGISTERS && args.len() - floats <= INT_ARG_REGISTERS
+}
+
+// blockの末尾の呼び出しを末尾呼び出しにできない理由 (できるならNone)
+pub fn tail_call_error(function: &Function, block: BlockId) -> Option<&'static str> {
+    let args = match tail_site(function, block) {
+        Some((_, args)) => args,
+        None => return Some("the call is not followed by a return of its result"),
+    };
+    if has_escaping_local(function) {
+        Some("the address of a local variable may be used by the callee")
+    } else if !fits_in_registers(args) {
+        Some("the arguments do not fit in registers")
+    } else {
+        None
+    }
+}
+
+// blockの末尾の呼び出しと戻りを末尾呼び出しに置き換える
+pub fn make_tail_call(function: &mut Function, block: BlockId) {
+    let block = function.block_mut(block);
+    match block.insts.pop() {
+        Some(Inst::Call(_, callee, args, variadic)) => {
+            block.terminator = Terminator::TailCall(callee, args, variadic)
+        }
+        _ => unreachable!(),
+    }
+}
+
+// 自分自身の末尾呼び出しをループにする
+// 入口ブロックの中身を新しいブロックに移してループの先頭とし、仮引数はそこのphiで受け取る
+fn loop_self_calls(function: &mut Function, sites: &[BlockId]) {
+    let header = function.new_block();
+    let insts = std::mem::take(&mut function.block_mut(BlockId(0)).insts);
+    let terminator = std::mem::replace(
+        &mut function.block_mut(BlockId(0)).terminator,
+        Terminator::Jump(header),
+    );
+    for successor in terminator.successors() {
+        for inst in function.block_mut(successor).insts.iter_mut() {
+            if let Inst::Phi(_, _, incoming) = inst {
+                for (predecessor, _) in incoming.iter_mut() {
+                    if *predecessor == BlockId(0) {
+                        *predecessor = header;
+                    }
+                }
+            }
+        }
+    }
+    function.block_mut(header).insts = insts;
+    function.block_mut(header).terminator = terminator;
+    let sites: Vec<BlockId> = sites
+        .iter()
+        .map(|site| if *site == BlockId(0) { header } else { *site })
+        .collect();
+
+    // 仮引数の読み出しは全てphiの値に置き換える
+    let params = function.params.clone();
+    let phis: Vec<Temp> = params
+        .iter()
+        .map(|param| function.new_temp(function.temp_types[param.0]))
+        .collect();
+    let replace = |operand: &mut Operand| {
+        if let Operand::Temp(temp) = operand {
+            if let Some(index) = params.iter().position(|param| param == temp) {
+                *temp = phis[index];
+            }
+        }
+    };
+    for block in function.blocks.iter_mut() {
+        for inst in block.insts.iter_mut() {
+            for operand in inst.operands_mut() {
+                replace(operand);
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            replace(operand);
+        }
+    }
+
+    let mut incoming: Vec<Vec<(BlockId, Operand)>> = params
+        .iter()
+        .map(|param| vec![(BlockId(0), Operand::Temp(*param))])
+        .collect();
+    for site in sites.iter() {
+        let args = match std::mem::replace(
+            &mut function.block_mut(*site).terminator,
+            Terminator::Jump(header),
+        ) {
+            Terminator::TailCall(_, args, _) => args,
+            _ => match function.block_mut(*site).insts.pop() {
+                Some(Inst::Call(_, _, args, _)) => args,
+                _ => unreachable!(),
+            },
+        };
+        for (index, (arg, typename)) in args.into_iter().enumerate() {
+            // intに拡張して渡した小さい整数は切り詰める
+            let param_type = function.temp_types[params[index].0];
+            let value = if typename == param_type {
+                arg
+            } else {
+                let narrowed = function.new_temp(param_type);
+                function.block_mut(*site).insts.push(Inst::Convert(
+                    narrowed,
+                    ConvertOp::Trunc,
+                    typename,
+                    param_type,
+                    arg,
+                ));
+                Operand::Temp(narrowed)
+            };
+            incoming[index].push((*site, value));
+        }
+    }
+    let phi_insts: Vec<Inst> = phis
+        .iter()
+        .zip(incoming)
+        .map(|(phi, incoming)| Inst::Phi(*phi, function.temp_types[phi.0], incoming))
+        .collect();
+    function.block_mut(header).insts.splice(0..0, phi_insts);
+}
+
+// 呼び出しの結果をそのまま返す呼び出しを、呼び出し先へのジャンプにする
+// 自分自身の呼び出しは関数の先頭に戻るループにする
+pub fn tailcall(function: &mut Function) -> bool {
+    if has_escaping_local(function) {
+        return false;
+    }
+    let name = Operand::Function(function.name.clone());
+    // 入口ブロックに戻る辺があれば、入口ブロックをループの先頭にはできない
+    let loopable = !function.variadic && function.predecessors()[0].is_empty();
+    let mut self_calls: Vec<BlockId> = Vec::new();
+    let mut changed = false;
+    for id in 0..function.blocks.len() {
+        let block = BlockId(id);
+        let site = match &function.block(block).terminator {
+            Terminator::TailCall(callee, args, _) => Some((callee, args.as_slice())),
+            _ => tail_site(function, block),
+        };
+        let self_call = match site {
+            Some((callee, args)) => {
+                loopable && *callee == name && is_compatible_call(function, &None, args)
+            }
+            None => continue,
+        };
+        if self_call {
+            self_calls.push(block);
+        } else if tail_call_error(function, block).is_none() {
+            make_tail_call(function, block);
+            changed = true;
+        }
+    }
+    if !self_calls.is_empty() {
+        loop_self_calls(function, &self_calls);
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::lower::lower;
+    use crate::ir::sprint_module;
+    use crate::ir::ssa;
+    use crate::ir::tailcall::tailcall;
+    use crate::parser::parse::parse;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn tailcall_test() {
+        let program = parse(tokenize(
+            "int g(int x); int sum(int n, int acc) { if (n == 0) return acc; return sum(n - 1, acc + n); } int h(int x) { return g(x + 1); }"
+                .to_string(),
+        ));
+        let mut module = lower(&program, false);
+        for function in module.functions.iter_mut() {
+            ssa::construct(function);
+            tailcall(function);
+        }
+        // sumの再帰はループになり、gの呼び出しはジャンプになる
+        assert_eq!(
+            sprint_module(&module),
+            "function @sum(i32 %0, i32 %1) -> i32 {
+bb0:
+    jmp bb3
+bb1:
+    ret i32 %12
+bb2:
+    %7 = add i32 %12, %11
+    %9 = sub i32 %11, 1
+    jmp bb3
+bb3:
+    %11 = phi i32 [bb0: %0], [bb2: %9]
+    %12 = phi i32 [bb0: %1], [bb2: %7]
+    %3 = eq i32 %11, 0
+    br i32 %3, bb1, bb2
+}
+function @h(i32 %0) -> i32 {
+bb0:
+    %2 = add i32 %0, 1
+    tail call @g(i32 %2)
+}
+"
+        );
+    }
+}
@@ -1,18 +1,46 @@
 pub mod codegen;
+pub mod frame;
+pub mod ir;
 pub mod node;
 pub mod parser;
+pub mod pass;
+pub mod peephole;
+pub mod regalloc;
 pub mod register;
 pub mod token;
 pub mod tokenizer;
 pub mod typename;
 
+use std::collections::{BTreeSet, HashSet};
+
 use codegen::CodeGenerator;
+use frame::Frame;
+use ir::lower::lower;
+use ir::sprint_module;
+use ir::ssa;
 use node::sprint_node;
 use parser::parse::parse;
+use pass::PassManager;
 use tokenizer::{sprint_token_iter, tokenize};
 
 fn main() {
-    let prog_string = std::env::args().nth(1).unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let prog_string = args
+        .iter()
+        .find(|arg| !arg.starts_with('-'))
+        .expect("no program is given")
+        .clone();
+    let stack_check = args.iter().any(|arg| arg == "--debug-stack-check");
+    // 後に指定した方が優先される
+    let omit_frame_pointer = args
+        .iter()
+        .rev()
+        .find(|arg| *arg == "-fomit-frame-pointer" || *arg == "-fno-omit-frame-pointer")
+        .is_some_and(|arg| arg == "-fomit-frame-pointer");
+    // --emit=ir ならアセンブリの代わりに中間表現を出力する
+    let emit_ir = args.iter().any(|arg| arg == "--emit=ir");
+    let passes = PassManager::from_args(&args);
+    let warn_unreachable = args.iter().any(|arg| arg == "-Wunreachable-code");
 
     // Tokenize
     let token_iter = tokenize(prog_string);
@@ -22,19 +50,44 @@ fn main() {
     // Parse
     let code = parse(token_iter);
 
+    eprintln!("parse result: {}", &sprint_node(&code));
+
+    // Lowering
+    let mut module = lower(&code, warn_unreachable);
+    passes.run(&mut module);
+    if emit_ir {
+        print!("{}", sprint_module(&module));
+        return;
+    }
+    for function in module.functions.iter_mut() {
+        ssa::destruct(function);
+    }
+
     // Code Generation
     let mut generator = CodeGenerator {
         lines: Vec::new(),
         label_count: 0,
-        rsp_sub_size: 0,
-        label_func: 0,
+        stack_depth: 0,
+        stack_check,
+        va_frame: None,
+        omit_frame_pointer,
+        frame: Frame::new(0, false, omit_frame_pointer),
+        locations: Vec::new(),
+        allocate_registers: passes.is_enabled("regalloc"),
+        fuse_compare_branch: passes.is_enabled("peephole"),
+        function_name: String::new(),
+        static_symbols: HashSet::new(),
+        aliases: BTreeSet::new(),
     };
-    eprintln!("parse result: {}", &sprint_node(&code));
 
     println!(".intel_syntax noprefix");
-    println!(".globl main");
-    generator.gen(&code);
-    for line in generator.lines {
+    generator.gen(&module);
+    let lines = if passes.is_enabled("peephole") {
+        peephole::optimize(generator.lines)
+    } else {
+        generator.lines
+    };
+    for line in lines {
         println!("{}", line);
     }
 }
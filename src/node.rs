@@ -1,13 +1,27 @@
-use crate::typename::{sprint_typename, Typename};
+use crate::typename::{
+    is_const, is_floating, is_volatile, sizeof, sprint_typename, unqualified, Inlining, SignedFlag,
+    Typename,
+};
 
 pub struct LVar {
     pub offset: usize,
     pub typename: Typename,
 }
 
+// 静的記憶域の変数 (グローバル変数と関数内のstatic変数)
+pub struct GVar {
+    pub label: String,
+    pub typename: Typename,
+    pub defined: bool, // externでない宣言があったか
+}
+
 pub struct Function {
     pub ret_typename: Typename,
     pub arg_typename: Vec<Typename>,
+    pub variadic: bool,
+    pub is_static: bool,
+    pub defined: bool,
+    pub inlining: Inlining,
 }
 
 //pub struct Block<'a> {
@@ -32,24 +46,35 @@ pub struct Function {
 pub enum Node {
     Unary(Box<Node>, UnaryType),           // arg, unary_type
     Binary(Box<(Node, Node)>, BinaryType), // (arg1, arg2), binary_type
-    Num(i32),                              // n
+    Num(i64, Typename),                    // n, typename
+    FloatNum(f64, Typename),               // x, typename
+    Cast(Box<Node>, Typename),             // arg, typename converted to
     Boolean(bool),                         // boolean_value
     LVar(usize, Typename),                 // offset, typename
+    GVar(String, Typename),                // label, typename
     Assign(Box<(Node, Node)>),             // lvalue, rvalue
-    Return(Option<Box<Node>>),             // return arg
+    Return(Option<Box<Node>>, bool),       // return arg, musttail
     If(Box<(Node, Node)>),                 // (cond, if_true)
     IfElse(Box<(Node, Node, Node)>),       // (cond, if_true, else)
     For(Box<(Node, Node, Node, Node)>),    // (init, cond, update, loop_content)
     While(Box<(Node, Node)>),              // (cond, loop_content)
     Block(Vec<Node>),                      // statement[]
-    Function(String, Typename, Vec<Typename>, Box<Node>, usize), // name,  return_type, arg_type[], block, local_var_size
-    FunctionCall(String, Vec<Node>),                             // name, arg[]
+    Function(String, Typename, Vec<(usize, Typename)>, bool, bool, Inlining, Box<Node>, usize), // name,  return_type, (offset, arg_type)[], variadic, is_static, inlining, block, local_var_size
+    GlobalVar(String, Typename, bool, Option<Box<Node>>),                    // label, typename, is_static, initializer
+    FunctionCall(Box<Node>, Vec<Node>, Typename, bool),                // callee, arg[], return_type, variadic
+    FunctionName(String, Typename),                                    // name, function_type
+    Str(Vec<u8>),                                                      // string literal
+    VaStart(Box<Node>),                                                // va_list
+    VaArg(Box<Node>, Typename),                                        // va_list, typename
+    VaCopy(Box<(Node, Node)>),                                         // (dest, src)
     Empty,
 }
 
 #[derive(Clone)]
 pub enum UnaryType {
     Not,
+    Address,
+    Deref,
 }
 
 #[derive(Clone)]
@@ -80,12 +105,209 @@ impl Node {
     }
 }
 
+// 左辺値の型 (型修飾子を保つ)
+pub fn lvalue_typename_of(node: &Node) -> Typename {
+    match node {
+        Node::LVar(_, typename) | Node::GVar(_, typename) => typename.clone(),
+        Node::Unary(arg, UnaryType::Deref) => match typename_of(arg) {
+            Typename::Pointer(typename) => *typename,
+            // 関数の参照外しは関数のまま
+            typename => typename,
+        },
+        _ => typename_of(node),
+    }
+}
+
+// 式の型 (左辺値変換で最上位の型修飾子は外れ、配列はポインタになる)
+pub fn typename_of(node: &Node) -> Typename {
+    use Node::*;
+    let typename = match node {
+        Unary(arg, unary_type) => match unary_type {
+            UnaryType::Not => Typename::Integer(SignedFlag::Signed, 4),
+            UnaryType::Address => Typename::Pointer(Box::new(lvalue_typename_of(arg))),
+            UnaryType::Deref => lvalue_typename_of(node),
+        },
+        Binary(binary_arg, binary_type) => match binary_type {
+            BinaryType::Add | BinaryType::Sub | BinaryType::Mul | BinaryType::Div => {
+                typename_of(&binary_arg.0)
+            }
+            _ => Typename::Integer(SignedFlag::Signed, 4),
+        },
+        Num(_n, typename) => typename.clone(),
+        FloatNum(_x, typename) => typename.clone(),
+        Cast(_arg, typename) => typename.clone(),
+        Boolean(_b) => Typename::Integer(SignedFlag::Signed, 4),
+        LVar(_offset, typename) => typename.clone(),
+        GVar(_label, typename) => typename.clone(),
+        Assign(assign_arg) => typename_of(&assign_arg.0),
+        FunctionCall(_callee, _args, return_type, _variadic) => return_type.clone(),
+        FunctionName(_name, typename) => typename.clone(),
+        Str(_bytes) => Typename::Pointer(Box::new(Typename::Integer(SignedFlag::Signed, 1))),
+        VaArg(_ap, typename) => typename.clone(),
+        _ => Typename::Void,
+    };
+    match unqualified(&typename) {
+        Typename::Array(element, _length) => Typename::Pointer(element.clone()),
+        typename => typename.clone(),
+    }
+}
+
+// 代入と同じ規則で暗黙に型を変換する (C11 6.5.16.1)
+// ポインタの指す先の型修飾子が落ちるときは警告する
+pub fn new_assign_cast(node: Node, typename: &Typename) -> Node {
+    if let (Typename::Pointer(from), Typename::Pointer(to)) = (typename_of(&node), unqualified(typename)) {
+        if is_const(&from) && !is_const(to) {
+            eprintln!("warning: conversion discards 'const' qualifier from pointer target type");
+        }
+        if is_volatile(&from) && !is_volatile(to) {
+            eprintln!("warning: conversion discards 'volatile' qualifier from pointer target type");
+        }
+    }
+    new_cast(node, unqualified(typename))
+}
+
+// 値を型typenameに変換するノード (型が同じなら何もしない)
+pub fn new_cast(node: Node, typename: &Typename) -> Node {
+    if same_arithmetic_type(&typename_of(&node), typename) {
+        node
+    } else {
+        Node::Cast(Box::new(node), typename.clone())
+    }
+}
+
+fn same_arithmetic_type(lhs: &Typename, rhs: &Typename) -> bool {
+    match (lhs, rhs) {
+        (Typename::Boolean, Typename::Boolean) => true,
+        (Typename::Floating(l), Typename::Floating(r)) => l == r,
+        (Typename::Integer(lflag, l), Typename::Integer(rflag, r)) => {
+            l == r && matches!(lflag, SignedFlag::Signed) == matches!(rflag, SignedFlag::Signed)
+        }
+        _ => false,
+    }
+}
+
+// 既定の実引数拡張 (C11 6.5.2.2): 可変長引数に渡すときに施す
+pub fn default_argument_promotion(node: Node) -> Node {
+    match typename_of(&node) {
+        Typename::Floating(4) => new_cast(node, &Typename::Floating(8)),
+        Typename::Boolean => new_cast(node, &Typename::Integer(SignedFlag::Signed, 4)),
+        Typename::Integer(_, size) if size < 4 => {
+            new_cast(node, &Typename::Integer(SignedFlag::Signed, 4))
+        }
+        _ => node,
+    }
+}
+
+// 通常の算術型変換 (C11 6.3.1.8)
+pub fn usual_arithmetic_conversion(lhs: &Typename, rhs: &Typename) -> Typename {
+    if is_floating(lhs) || is_floating(rhs) {
+        return Typename::Floating(std::cmp::max(
+            if is_floating(lhs) { sizeof(lhs) } else { 0 },
+            if is_floating(rhs) { sizeof(rhs) } else { 0 },
+        ));
+    }
+    // 整数拡張: intより小さい型はintにする
+    let promote = |typename: &Typename| match typename {
+        Typename::Integer(flag, size) if *size >= 4 => (*flag, *size),
+        _ => (SignedFlag::Signed, 4),
+    };
+    let (lflag, lsize) = promote(lhs);
+    let (rflag, rsize) = promote(rhs);
+    let size = std::cmp::max(lsize, rsize);
+    let unsigned = |flag: SignedFlag, flag_size: usize| {
+        matches!(flag, SignedFlag::Unsigned) && flag_size == size
+    };
+    if unsigned(lflag, lsize) || unsigned(rflag, rsize) {
+        Typename::Integer(SignedFlag::Unsigned, size)
+    } else {
+        Typename::Integer(SignedFlag::Signed, size)
+    }
+}
+
+// ポインタに足し引きする整数を指す先の大きさ倍にする
+fn scale_index(index: Node, pointee: &Typename) -> Node {
+    if !matches!(typename_of(&index), Typename::Integer(_, _) | Typename::Boolean) {
+        panic!("invalid operands to pointer arithmetic");
+    }
+    let long = Typename::Integer(SignedFlag::Signed, 8);
+    // void* の演算は1byte単位 (GNU拡張)
+    let size = std::cmp::max(sizeof(pointee), 1) as i64;
+    new_binary(BinaryType::Mul, new_cast(index, &long), Node::Num(size, long))
+}
+
+// 加算 (ポインタ + 整数 はポインタ演算)
+pub fn new_add(lhs: Node, rhs: Node) -> Node {
+    match (typename_of(&lhs), typename_of(&rhs)) {
+        (Typename::Pointer(_), Typename::Pointer(_)) => panic!("invalid operands to binary +"),
+        (Typename::Pointer(pointee), _) => {
+            let offset = scale_index(rhs, &pointee);
+            Node::Binary(Box::new((lhs, offset)), BinaryType::Add)
+        }
+        (_, Typename::Pointer(_)) => new_add(rhs, lhs),
+        _ => new_binary(BinaryType::Add, lhs, rhs),
+    }
+}
+
+// 減算 (ポインタ同士の差は要素数)
+pub fn new_sub(lhs: Node, rhs: Node) -> Node {
+    match (typename_of(&lhs), typename_of(&rhs)) {
+        (Typename::Pointer(pointee), Typename::Pointer(_)) => {
+            let long = Typename::Integer(SignedFlag::Signed, 8);
+            let size = std::cmp::max(sizeof(&pointee), 1) as i64;
+            let diff = Node::Binary(
+                Box::new((new_cast(lhs, &long), new_cast(rhs, &long))),
+                BinaryType::Sub,
+            );
+            Node::Binary(Box::new((diff, Node::Num(size, long))), BinaryType::Div)
+        }
+        (Typename::Pointer(pointee), _) => {
+            let offset = scale_index(rhs, &pointee);
+            Node::Binary(Box::new((lhs, offset)), BinaryType::Sub)
+        }
+        (_, Typename::Pointer(_)) => panic!("invalid operands to binary -"),
+        _ => new_binary(BinaryType::Sub, lhs, rhs),
+    }
+}
+
+// 二項演算のノードを作り、両辺に通常の算術型変換を施す
+pub fn new_binary(binary_type: BinaryType, lhs: Node, rhs: Node) -> Node {
+    // ポインタの比較はもう一方をポインタに合わせる
+    match (typename_of(&lhs), typename_of(&rhs)) {
+        (typename @ Typename::Pointer(_), _) => {
+            return Node::Binary(Box::new((lhs, new_cast(rhs, &typename))), binary_type);
+        }
+        (_, typename @ Typename::Pointer(_)) => {
+            return Node::Binary(Box::new((new_cast(lhs, &typename), rhs)), binary_type);
+        }
+        _ => {}
+    }
+    let typename = usual_arithmetic_conversion(&typename_of(&lhs), &typename_of(&rhs));
+    Node::Binary(
+        Box::new((new_cast(lhs, &typename), new_cast(rhs, &typename))),
+        binary_type,
+    )
+}
+
 pub fn sprint_node(node: &Node) -> String {
     use Node::*;
     match node {
-        Num(n) => n.to_string(),
+        Num(n, _typename) => n.to_string(),
+        FloatNum(x, _typename) => x.to_string(),
+        Cast(cast_arg, typename) => format!(
+            "({0}){1}",
+            sprint_typename(typename),
+            sprint_node(cast_arg)
+        ),
         Boolean(b) => if *b { "True" } else { "False" }.into(),
-        Unary(_unary_arg, _unary_type) => String::new(),
+        Unary(unary_arg, unary_type) => {
+            match unary_type {
+                UnaryType::Not => "!",
+                UnaryType::Address => "&",
+                UnaryType::Deref => "*",
+            }
+            .to_string()
+                + format!("({})", &sprint_node(unary_arg)).as_str()
+        }
         Binary(binary_arg, binary_type) => {
             use BinaryType::*;
             return match binary_type {
@@ -116,8 +338,11 @@ pub fn sprint_node(node: &Node) -> String {
                 &sprint_node(&assign_arg.1)
             )
         }
-        Return(return_arg_optional) => match return_arg_optional {
+        Return(return_arg_optional, musttail) => match return_arg_optional {
             None => format!("return nothing"),
+            Some(return_arg) if *musttail => {
+                format!("musttail return {}", sprint_node(return_arg))
+            }
             Some(return_arg) => format!("return {}", &sprint_node(&*return_arg)),
         },
         If(if_arg) => format!(
@@ -143,24 +368,67 @@ pub fn sprint_node(node: &Node) -> String {
             &sprint_node(&while_arg.0),
             &sprint_node(&while_arg.1)
         ),
-        FunctionCall(name, arg_list) => {
+        FunctionName(name, _typename) => format!("[func {}]", name),
+        FunctionCall(callee, arg_list, _return_type, _variadic) => {
             arg_list
                 .iter()
-                .fold(format!("Call {} (", name), |out, arg| {
+                .fold(format!("Call {} (", &sprint_node(callee)), |out, arg| {
                     out + &sprint_node(&arg) + ", "
                 })
                 + ")"
         }
-        Function(name, return_type, arg_types, block, _local_var_size) => {
+        GVar(label, typename) => {
+            format!("[global {0}:{1}]", label, sprint_typename(typename))
+        }
+        GlobalVar(label, typename, is_static, initializer) => format!(
+            "{0}global variable (type: {1}, name: {2}){3}",
+            if *is_static { "static " } else { "" },
+            sprint_typename(typename),
+            label,
+            match initializer {
+                Some(value) => format!(" = {}", &sprint_node(value)),
+                None => String::new(),
+            }
+        ),
+        Function(
+            name,
+            return_type,
+            params,
+            variadic,
+            is_static,
+            inlining,
+            block,
+            _local_var_size,
+        ) => {
             format!(
-                "function (type: {0}({1}), name: {2})\n",
+                "{0}{5}function (type: {1}({2}{3}), name: {4})\n",
+                if *is_static { "static " } else { "" },
                 sprint_typename(return_type),
-                arg_types.iter().fold(String::new(), |out, arg_type| {
-                    out + &sprint_typename(&arg_type) + ", "
+                params.iter().fold(String::new(), |out, (offset, arg_type)| {
+                    out + &format!("[{}:{}], ", offset, sprint_typename(arg_type))
                 }),
-                name
+                if *variadic { "..." } else { "" },
+                name,
+                match inlining {
+                    Inlining::Default => "",
+                    Inlining::Inline => "inline ",
+                    Inlining::Always => "always_inline ",
+                    Inlining::Never => "noinline ",
+                }
             ) + &sprint_node(&block)
         }
+        Str(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+        VaStart(ap) => format!("va_start({})", sprint_node(ap)),
+        VaArg(ap, typename) => format!(
+            "va_arg({0}, {1})",
+            sprint_node(ap),
+            sprint_typename(typename)
+        ),
+        VaCopy(va_copy_arg) => format!(
+            "va_copy({0}, {1})",
+            &sprint_node(&va_copy_arg.0),
+            &sprint_node(&va_copy_arg.1)
+        ),
         Block(statements) => {
             statements
                 .iter()
@@ -1,4 +1,4 @@
-use crate::node::{BinaryType, Node};
+use crate::node::{new_add, new_sub, Node};
 use crate::token::Token;
 
 use super::Parser;
@@ -10,11 +10,11 @@ impl Parser {
             match self.token_iter.peep().unwrap_or(Token::Eof) {
                 Token::Plus => {
                     self.token_iter.ignore(1);
-                    node = Node::Binary(Box::new((node, self.mul())), BinaryType::Add);
+                    node = new_add(node, self.mul());
                 }
                 Token::Minus => {
                     self.token_iter.ignore(1);
-                    node = Node::Binary(Box::new((node, self.mul())), BinaryType::Sub);
+                    node = new_sub(node, self.mul());
                 }
                 _ => {
                     return node;
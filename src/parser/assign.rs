@@ -1,4 +1,5 @@
-use crate::node::Node;
+use crate::node::{lvalue_typename_of, new_assign_cast, Node};
+use crate::typename::{is_const, sprint_typename, Typename};
 use crate::token::Token;
 
 use super::Parser;
@@ -9,7 +10,19 @@ impl Parser {
         return match self.token_iter.peep().unwrap_or(Token::Eof) {
             Token::Equal => {
                 self.token_iter.ignore(1);
-                Node::Assign(Box::new((node, self.assign())))
+                let typename = lvalue_typename_of(&node);
+                if let Typename::Array(_, _) = typename {
+                    panic!("assignment to expression with array type");
+                }
+                if is_const(&typename) {
+                    panic!(
+                        "assignment of read-only location (type: {})",
+                        sprint_typename(&typename)
+                    );
+                }
+                // 右辺は左辺の型に変換する
+                let rvalue = new_assign_cast(self.assign(), &typename);
+                Node::Assign(Box::new((node, rvalue)))
             }
             _ => node,
         };
@@ -1,66 +1,492 @@
-use crate::node::{LVar, Node};
-use crate::token::{sprint_token, Token};
-use crate::typename::{is_typename_token, parse_typename, sizeof, Typename};
+use crate::ir::lower::eval_constant;
+use crate::node::{new_assign_cast, GVar, LVar, Node};
+use crate::token::Token;
+use crate::typename::{
+    alignof, is_typename_token, parse_typename, qualify, sizeof, sprint_typename, unqualified,
+    Inlining, Mutability, StorageClass, Typename,
+};
 
 use super::Parser;
+
+// 宣言子を読んだ結果
+pub struct Declarator {
+    pub typename: Typename,
+    pub name: Option<String>,
+    // 識別子の直後に仮引数の並びがあれば、その (型, 仮引数名)
+    pub parameters: Option<Vec<(Typename, Option<String>)>>,
+}
+
+// 仮引数の型の調整 (C11 6.7.6.3)
+// 配列と関数はポインタになり、最上位の型修飾子は関数の型に含めない
+pub fn adjust_parameter(typename: &Typename) -> Typename {
+    match unqualified(typename) {
+        Typename::Array(element, _length) => Typename::Pointer(element.clone()),
+        Typename::Function(_, _, _) | Typename::VaList => {
+            Typename::Pointer(Box::new(unqualified(typename).clone()))
+        }
+        typename => typename.clone(),
+    }
+}
+
 impl Parser {
-    pub fn parse_declaration(&mut self) -> Option<(Typename, String)> {
-        // Tokenの列を作る
+    // 記憶域クラス指定子 (auto, registerは自動変数と同じ扱い)
+    pub fn storage_class(&mut self) -> Option<StorageClass> {
+        let (storage_class, inlining) = self.storage_class_and_inlining();
+        if inlining != Inlining::Default {
+            panic!("'inline' and function attributes are only allowed on functions");
+        }
+        storage_class
+    }
+
+    // 記憶域クラス指定子と、その前後に並ぶ関数指定子 (inline, __attribute__)
+    pub fn storage_class_and_inlining(&mut self) -> (Option<StorageClass>, Inlining) {
+        let mut storage_class: Option<StorageClass> = None;
+        let mut inlining = Inlining::Default;
+        let mut count: usize = 0;
+        loop {
+            match self.token_iter.peep().unwrap_or(Token::Eof) {
+                Token::Static => storage_class = Some(StorageClass::Static),
+                Token::Extern => storage_class = Some(StorageClass::Extern),
+                Token::Auto | Token::Register => {}
+                Token::Inline => {
+                    inlining = std::cmp::max(inlining, Inlining::Inline);
+                    self.token_iter.ignore(1);
+                    continue;
+                }
+                Token::Attribute => {
+                    self.token_iter.ignore(1);
+                    inlining = std::cmp::max(inlining, self.attribute());
+                    continue;
+                }
+                _ => break,
+            }
+            self.token_iter.ignore(1);
+            count += 1;
+        }
+        if count > 1 {
+            panic!("multiple storage classes in declaration specifiers");
+        }
+        (storage_class, inlining)
+    }
+
+    // 宣言子の後ろに並ぶ __attribute__
+    pub fn trailing_attributes(&mut self) -> Inlining {
+        let mut inlining = Inlining::Default;
+        while let Token::Attribute = self.token_iter.peep().unwrap_or(Token::Eof) {
+            self.token_iter.ignore(1);
+            inlining = std::cmp::max(inlining, self.attribute());
+        }
+        inlining
+    }
+
+    // __attribute__ の後の "((" attribute ( "," attribute )* "))" の属性名
+    pub fn attribute_names(&mut self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for _ in 0..2 {
+            if !self.token_iter.next().unwrap_or(Token::Eof).is_leftparen() {
+                panic!("missing '(' after __attribute__");
+            }
+        }
+        loop {
+            match self.token_iter.next().unwrap_or(Token::Eof) {
+                Token::Identity(name) => names.push(name),
+                Token::RightParen => break,
+                _ => panic!("invalid attribute"),
+            }
+            // 属性の引数は読み飛ばす
+            if self.token_iter.peep().unwrap_or(Token::Eof).is_leftparen() {
+                let mut depth: usize = 0;
+                loop {
+                    match self.token_iter.next().unwrap_or(Token::Eof) {
+                        Token::LeftParen => depth += 1,
+                        Token::RightParen => depth -= 1,
+                        Token::Eof => panic!("missing ')' in attribute"),
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            match self.token_iter.next().unwrap_or(Token::Eof) {
+                Token::Comma => {}
+                Token::RightParen => break,
+                _ => panic!("missing ')' in attribute"),
+            }
+        }
+        if !self.token_iter.next().unwrap_or(Token::Eof).is_rightparen() {
+            panic!("missing ')' in attribute");
+        }
+        names
+    }
+
+    // 宣言に付いた属性 (always_inlineとnoinline以外は無視する)
+    fn attribute(&mut self) -> Inlining {
+        let mut inlining = Inlining::Default;
+        for name in self.attribute_names() {
+            match &*name {
+                "always_inline" => inlining = std::cmp::max(inlining, Inlining::Always),
+                "noinline" => inlining = std::cmp::max(inlining, Inlining::Never),
+                _ => eprintln!("warning: '{}' attribute directive ignored", name),
+            }
+        }
+        inlining
+    }
+
+    // 宣言の始まりか (記憶域クラス指定子か型指定子・型修飾子)
+    pub fn is_declaration_start(&mut self) -> bool {
+        match self.token_iter.peep().unwrap_or(Token::Eof) {
+            Token::Static | Token::Extern | Token::Auto | Token::Register => true,
+            Token::Inline | Token::Attribute => true,
+            token => is_typename_token(&token),
+        }
+    }
+
+    // 型指定子と型修飾子の並び
+    pub fn declaration_specifier(&mut self) -> Typename {
         let mut ident_list: Vec<Token> = Vec::new();
-        let mut token_iter_clone = self.token_iter.clone();
-        ident_list.push(token_iter_clone.next().unwrap());
-        ident_list.push(token_iter_clone.next().unwrap());
-        eprintln!("first token: {}", sprint_token(&ident_list[0]));
-        eprintln!("second token: {}", sprint_token(&ident_list[1]));
-        if !is_typename_token(&ident_list[0]) || !is_typename_token(&ident_list[1]) {
-            return None;
+        while is_typename_token(&self.token_iter.peep().unwrap_or(Token::Eof)) {
+            ident_list.push(self.token_iter.next().unwrap());
         }
-        while is_typename_token(&token_iter_clone.peep().unwrap_or(Token::Eof)) {
-            ident_list.push(token_iter_clone.next().unwrap());
+        if ident_list.is_empty() {
+            panic!("type name expected");
         }
-        // パース
-        match ident_list.pop().unwrap_or(Token::Eof) {
+        parse_typename(ident_list)
+    }
+
+    // "*" と後ろに続く型修飾子を読んでbaseへのポインタにする
+    fn pointer(&mut self, mut typename: Typename) -> Typename {
+        while let Token::Asterisk = self.token_iter.peep().unwrap_or(Token::Eof) {
+            self.token_iter.ignore(1);
+            let mut mutability = Mutability::Mutable;
+            let mut volatile = false;
+            loop {
+                match self.token_iter.peep().unwrap_or(Token::Eof) {
+                    Token::Const => mutability = Mutability::Const,
+                    Token::Volatile => volatile = true,
+                    _ => break,
+                }
+                self.token_iter.ignore(1);
+            }
+            typename = qualify(Typename::Pointer(Box::new(typename)), mutability, volatile);
+        }
+        typename
+    }
+
+    // declarator = pointer direct_declarator
+    // 名前のない抽象宣言子も読む
+    pub fn declarator(&mut self, base: Typename) -> Declarator {
+        let typename = self.pointer(base);
+        self.direct_declarator(typename)
+    }
+
+    // direct_declarator = ( ident | "(" declarator ")" )? type_suffix
+    fn direct_declarator(&mut self, base: Typename) -> Declarator {
+        match self.token_iter.peep().unwrap_or(Token::Eof) {
             Token::Identity(name) => {
-                self.token_iter.ignore(ident_list.len() + 1);
-                Some((parse_typename(ident_list), name))
+                self.token_iter.ignore(1);
+                let mut parameters = None;
+                let typename = if self.token_iter.peep().unwrap_or(Token::Eof).is_leftparen() {
+                    // 関数の定義で使うので仮引数名も残す
+                    let (typename, params) = self.function_suffix(base);
+                    parameters = Some(params);
+                    typename
+                } else {
+                    self.type_suffix(base)
+                };
+                Declarator {
+                    typename,
+                    name: Some(name),
+                    parameters,
+                }
             }
-            _ => None,
+            Token::LeftParen if self.is_nested_declarator() => {
+                // 括弧の中は外側の接尾辞を付けた型に対する宣言子なので、
+                // 一旦読み飛ばして接尾辞を先に読む
+                self.token_iter.ignore(1);
+                let inner = self.token_iter.clone();
+                let mut depth: usize = 1;
+                while depth > 0 {
+                    match self.token_iter.next().unwrap_or(Token::Eof) {
+                        Token::LeftParen => depth += 1,
+                        Token::RightParen => depth -= 1,
+                        Token::Eof => panic!("missing ')' in declarator"),
+                        _ => {}
+                    }
+                }
+                let typename = self.type_suffix(base);
+                let rest = std::mem::replace(&mut self.token_iter, inner);
+                let declarator = self.declarator(typename);
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_rightparen() {
+                    panic!("missing ')' in declarator");
+                }
+                self.token_iter = rest;
+                declarator
+            }
+            _ => Declarator {
+                typename: self.type_suffix(base),
+                name: None,
+                parameters: None,
+            },
         }
     }
 
-    pub fn declaration_impl(&mut self) -> Option<Typename> {
-        match self.parse_declaration() {
-            None => None,
-            Some(parsed_result) => {
-                let (typename, name) = parsed_result;
-                if let Typename::Void = typename {
-                    panic!("variable cannot be declared as void type")
+    // "(" が入れ子の宣言子か仮引数の並びか
+    fn is_nested_declarator(&mut self) -> bool {
+        let mut lookahead = self.token_iter.clone();
+        lookahead.ignore(1);
+        matches!(
+            lookahead.peep().unwrap_or(Token::Eof),
+            Token::Asterisk | Token::LeftParen | Token::LeftBracket | Token::Identity(_)
+        )
+    }
+
+    // type_suffix = ( "[" num? "]" )* | "(" parameter_list
+    fn type_suffix(&mut self, base: Typename) -> Typename {
+        match self.token_iter.peep().unwrap_or(Token::Eof) {
+            Token::LeftBracket => {
+                self.token_iter.ignore(1);
+                // 長さを省略した配列は長さ0として扱う
+                let length =
+                    if let Token::RightBracket = self.token_iter.peep().unwrap_or(Token::Eof) {
+                        0
+                    } else {
+                        match eval_constant(&self.assign()) {
+                            Some(Node::Num(n, _)) if n >= 0 => n as usize,
+                            Some(Node::Num(_, _)) => panic!("size of array is negative"),
+                            _ => panic!("size of array is not an integer constant"),
+                        }
+                    };
+                if let Token::RightBracket = self.token_iter.next().unwrap_or(Token::Eof) {
+                } else {
+                    panic!("missing ']' in array declarator");
+                }
+                let element = self.type_suffix(base);
+                if let Typename::Function(_, _, _) | Typename::Void = unqualified(&element) {
+                    panic!("invalid array element type '{}'", sprint_typename(&element));
                 }
-                match self.local_vars.get(&name) {
-                    Some(_) => panic!("redelaration of variable '{}' in this scope", name),
-                    None => {
-                        self.local_vars.insert(
-                            name,
-                            LVar {
-                                offset: self.offset_last,
-                                typename: typename.clone(),
-                            },
-                        );
-                        self.offset_last += sizeof(&typename);
+                Typename::Array(Box::new(element), length)
+            }
+            Token::LeftParen => self.function_suffix(base).0,
+            _ => base,
+        }
+    }
+
+    // "(" parameter_list を読んでbaseを返す関数の型にする
+    fn function_suffix(
+        &mut self,
+        return_type: Typename,
+    ) -> (Typename, Vec<(Typename, Option<String>)>) {
+        self.token_iter.ignore(1);
+        let (params, variadic) = self.parameter_list();
+        if let Typename::Function(_, _, _) | Typename::Array(_, _) = unqualified(&return_type) {
+            panic!("function cannot return '{}'", sprint_typename(&return_type));
+        }
+        // 戻り値の型修飾子は意味を持たない
+        let return_type = unqualified(&return_type).clone();
+        let arg_types = params
+            .iter()
+            .map(|(typename, _)| typename.clone())
+            .collect();
+        (
+            Typename::Function(Box::new(return_type), arg_types, variadic),
+            params,
+        )
+    }
+
+    // "(" を読んだ後の仮引数の並び
+    // -> ((調整済みの型, 仮引数名)[], variadic)
+    pub fn parameter_list(&mut self) -> (Vec<(Typename, Option<String>)>, bool) {
+        let mut params: Vec<(Typename, Option<String>)> = Vec::new();
+        if self.token_iter.peep().unwrap_or(Token::Eof).is_rightparen() {
+            // () は仮引数の並びがないので、任意の実引数を可変長部分として渡す
+            self.token_iter.ignore(1);
+            return (params, true);
+        }
+        loop {
+            if let Token::Dot = self.token_iter.peep().unwrap_or(Token::Eof) {
+                for _ in 0..3 {
+                    if let Token::Dot = self.token_iter.next().unwrap_or(Token::Eof) {
+                    } else {
+                        panic!("expected '...' in parameter list");
                     }
-                };
-                Some(typename)
+                }
+                if params.is_empty() {
+                    panic!("ISO C requires a named argument before '...'");
+                }
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_rightparen() {
+                    panic!("'...' must be the last parameter");
+                }
+                return (params, true);
+            }
+            // 仮引数に付けられる記憶域クラスはregisterだけ
+            if self.storage_class().is_some() {
+                panic!("storage class specified for parameter");
+            }
+            let base = self.declaration_specifier();
+            let declarator = self.declarator(base);
+            params.push((declarator.typename, declarator.name));
+            match self.token_iter.next().unwrap_or(Token::Eof) {
+                Token::RightParen => break,
+                Token::Comma => {}
+                _ => panic!("invalid parameter list"),
             }
         }
+        // (void) は引数なし
+        if let [(Typename::Void, None)] = params.as_slice() {
+            params.clear();
+        }
+        let params = params
+            .into_iter()
+            .map(|(typename, name)| {
+                if let Typename::Void = unqualified(&typename) {
+                    panic!("parameter has void type");
+                }
+                (adjust_parameter(&typename), name)
+            })
+            .collect();
+        (params, false)
     }
 
-    pub fn declaration(&mut self) -> Option<Node> {
-        match self.declaration_impl() {
-            None => None,
-            Some(typename) => match typename {
-                Typename::Void => panic!("variable cannot be declared as void type"),
-                _ => Some(Node::Empty),
+    // 変数名のない型名 (va_argやキャストで使う)
+    pub fn type_name(&mut self) -> Typename {
+        let base = self.declaration_specifier();
+        let declarator = self.declarator(base);
+        if let Some(name) = declarator.name {
+            panic!("unexpected identifier '{}' in type name", name);
+        }
+        declarator.typename
+    }
+
+    pub fn declare_local(&mut self, name: String, typename: &Typename) {
+        if let Typename::Void = typename {
+            panic!("variable cannot be declared as void type")
+        }
+        if let Typename::Array(_, 0) = typename {
+            panic!("array size missing in '{}'", name);
+        }
+        if self.local_statics.contains_key(&name) {
+            panic!("redelaration of variable '{}' in this scope", name);
+        }
+        match self.local_vars.get(&name) {
+            Some(_) => panic!("redelaration of variable '{}' in this scope", name),
+            None => {
+                // 変数は [base-offset] から上位アドレスに向かって置かれるので、
+                // offsetは領域の下端を指す
+                let align = alignof(typename);
+                self.offset_last = (self.offset_last + sizeof(typename)).div_ceil(align) * align;
+                self.local_vars.insert(
+                    name,
+                    LVar {
+                        offset: self.offset_last,
+                        typename: typename.clone(),
+                    },
+                );
+            }
+        };
+    }
+
+    // 関数内のstatic変数は "name.N" というラベルで静的領域に置く
+    fn declare_local_static(&mut self, name: String, typename: &Typename) {
+        if let Typename::Void = typename {
+            panic!("variable cannot be declared as void type")
+        }
+        if self.local_vars.contains_key(&name) || self.local_statics.contains_key(&name) {
+            panic!("redelaration of variable '{}' in this scope", name);
+        }
+        let label = format!("{}.{}", name, self.static_count);
+        self.static_count += 1;
+        let initializer = self.static_initializer(typename);
+        self.static_definitions.push(Node::GlobalVar(
+            label.clone(),
+            typename.clone(),
+            true,
+            initializer.map(Box::new),
+        ));
+        self.local_statics.insert(
+            name,
+            GVar {
+                label,
+                typename: typename.clone(),
+                defined: true,
+            },
+        );
+    }
+
+    // 関数内のextern宣言はグローバル変数を参照する
+    fn declare_local_extern(&mut self, name: String, typename: &Typename) {
+        if self.local_vars.contains_key(&name) || self.local_statics.contains_key(&name) {
+            panic!("redelaration of variable '{}' in this scope", name);
+        }
+        if let Token::Equal = self.token_iter.peep().unwrap_or(Token::Eof) {
+            panic!("'{}' has both 'extern' and initializer", name);
+        }
+        let label = self.declare_global(name.clone(), typename, false);
+        self.local_statics.insert(
+            name,
+            GVar {
+                label,
+                typename: typename.clone(),
+                defined: false,
             },
+        );
+    }
+
+    // 自動変数を1つ宣言し、初期化子があれば代入のノードを返す
+    fn declare_auto(&mut self, name: String, typename: &Typename) -> Option<Node> {
+        self.declare_local(name.clone(), typename);
+        if let Token::Equal = self.token_iter.peep().unwrap_or(Token::Eof) {
+            if let Typename::Array(_, _) = typename {
+                panic!("array initializer is not supported");
+            }
+            // 初期化子は代入と同じ
+            self.token_iter.ignore(1);
+            let var = self.local_vars.get(&name).unwrap();
+            let lvar = Node::LVar(var.offset, var.typename.clone());
+            let value = new_assign_cast(self.assign(), typename);
+            Some(Node::Assign(Box::new((lvar, value))))
+        } else {
+            None
+        }
+    }
+
+    // storage_class? declaration_specifier init_declarator ( "," init_declarator )*
+    pub fn declaration(&mut self) -> Option<Node> {
+        if !self.is_declaration_start() {
+            return None;
+        }
+        let storage_class = self.storage_class();
+        let base = self.declaration_specifier();
+        let mut initializers: Vec<Node> = Vec::new();
+        loop {
+            let declarator = self.declarator(base.clone());
+            let name = declarator
+                .name
+                .expect("missing variable name in declaration");
+            let typename = declarator.typename;
+            if let Typename::Function(_, _, _) = typename {
+                // 関数内の関数宣言
+                if let Some(StorageClass::Static) = storage_class {
+                    panic!("invalid storage class for function '{}'", name);
+                }
+                self.declare_function(&name, &typename, None, Inlining::Default, false);
+            } else {
+                match storage_class {
+                    Some(StorageClass::Static) => self.declare_local_static(name, &typename),
+                    Some(StorageClass::Extern) => self.declare_local_extern(name, &typename),
+                    None => initializers.extend(self.declare_auto(name, &typename)),
+                }
+            }
+            if let Token::Comma = self.token_iter.peep().unwrap_or(Token::Eof) {
+                self.token_iter.ignore(1);
+            } else {
+                break;
+            }
         }
+        Some(match initializers.len() {
+            0 => Node::Empty,
+            1 => initializers.pop().unwrap(),
+            _ => Node::Block(initializers),
+        })
     }
 }
@@ -1,4 +1,4 @@
-use crate::node::{BinaryType, Node};
+use crate::node::{new_binary, BinaryType, Node};
 use crate::token::Token;
 
 use super::Parser;
@@ -15,7 +15,7 @@ impl Parser {
                     // ==
                     if let Token::Equal = token_iter_cp.next().unwrap_or(Token::Eof) {
                         self.token_iter.ignore(2);
-                        node = Node::Binary(Box::new((node, self.inequality())), BinaryType::Equal);
+                        node = new_binary(BinaryType::Equal, node, self.inequality());
                     } else {
                         return node;
                     }
@@ -25,7 +25,7 @@ impl Parser {
                     if let Token::Equal = token_iter_cp.next().unwrap_or(Token::Eof) {
                         self.token_iter.ignore(2);
                         node =
-                            Node::Binary(Box::new((node, self.inequality())), BinaryType::NotEqual);
+                            new_binary(BinaryType::NotEqual, node, self.inequality());
                     } else {
                         return node;
                     }
@@ -1,90 +1,136 @@
 use crate::node::{Function, Node};
-use crate::token::Token;
-use crate::typename::Typename;
+use crate::typename::{
+    is_compatible, is_unprototyped, sprint_typename, Inlining, SignedFlag, StorageClass, Typename,
+};
 
+use super::declaration::Declarator;
 use super::Parser;
 impl Parser {
-    fn parse_arglist(&mut self) -> Vec<Typename> {
-        let mut arg_list: Vec<Typename> = Vec::new();
-        match self.token_iter.peep().unwrap() {
-            Token::RightParen => {
-                self.token_iter.ignore(1);
-                return arg_list;
-            }
-            _ => {
-                let typename = self
-                    .declaration_impl()
-                    .expect("invalid function argument list");
-                arg_list.push(typename);
-            }
-        }
-        loop {
-            match self.token_iter.next().unwrap() {
-                Token::RightParen => {
-                    return arg_list;
-                }
-                Token::Comma => {
-                    let typename = self
-                        .declaration_impl()
-                        .expect("invalid function argument list");
-                    arg_list.push(typename);
-                }
-                _ => {
-                    panic!("Invaid argument expression for function call");
-                }
+    // 関数の型を (戻り値の型, 引数の型[], variadic) に分解する
+    fn function_signature(typename: &Typename) -> (Typename, Vec<Typename>, bool) {
+        match typename {
+            Typename::Function(return_typename, arg_typenames, variadic) => {
+                ((**return_typename).clone(), arg_typenames.clone(), *variadic)
             }
+            _ => unreachable!(),
         }
     }
 
-    pub fn function(&mut self) -> Node {
-        self.local_vars.clear();
-        self.offset_last = 0;
-
-        // return_typename funcname
-        let (return_typename, name) = self
-            .parse_declaration()
-            .expect("invalid function declaration");
-        // (
-        if !self.token_iter.next().unwrap_or(Token::Eof).is_leftparen() {
-            panic!("missing '(' before argument list");
+    // 関数を登録する
+    // 同じ名前の宣言が既にあれば型が適合するか調べ、仮引数の並びがある方を残す
+    pub fn declare_function(
+        &mut self,
+        name: &str,
+        typename: &Typename,
+        storage_class: Option<StorageClass>,
+        inlining: Inlining,
+        define: bool,
+    ) {
+        if self.global_vars.contains_key(name) {
+            panic!("'{}' redeclared as different kind of symbol", name);
         }
-        // arg1, arg2, arg3, ...)
-        let arg_list = self.parse_arglist();
-
-        // { // do something }
-        if self.token_iter.peep().unwrap_or(Token::Eof).is_semicolon() {
-            self.token_iter.ignore(1);
-            match self.functions.get(&name) {
-                Some(_) => panic!("redelaration of function named {}", name),
-                None => {
-                    self.functions.insert(
+        let is_static = matches!(storage_class, Some(StorageClass::Static));
+        let (ret_typename, arg_typename, variadic) = Parser::function_signature(typename);
+        match self.functions.get_mut(name) {
+            Some(function) => {
+                let previous = Typename::Function(
+                    Box::new(function.ret_typename.clone()),
+                    function.arg_typename.clone(),
+                    function.variadic,
+                );
+                if !is_compatible(&previous, typename) {
+                    panic!(
+                        "conflicting types for '{}' (previous declaration: {}, now: {})",
                         name,
-                        Function {
-                            ret_typename: return_typename,
-                            arg_typename: arg_list,
-                        },
+                        sprint_typename(&previous),
+                        sprint_typename(typename)
                     );
                 }
-            };
-            Node::Empty
-        } else {
-            if self.functions.get(&name).is_none() {
+                if define && function.defined {
+                    panic!("redefinition of '{}'", name);
+                }
+                if is_static && !function.is_static {
+                    panic!("static declaration of '{}' follows non-static declaration", name);
+                }
+                if is_unprototyped(&previous) {
+                    function.arg_typename = arg_typename;
+                    function.variadic = variadic;
+                }
+                function.defined |= define;
+                function.inlining = std::cmp::max(function.inlining, inlining);
+            }
+            None => {
                 self.functions.insert(
-                    name.clone(),
+                    name.to_string(),
                     Function {
-                        ret_typename: return_typename.clone(),
-                        arg_typename: arg_list.clone(),
+                        ret_typename,
+                        arg_typename,
+                        variadic,
+                        is_static,
+                        defined: define,
+                        inlining,
                     },
                 );
             }
-            let block = self.block();
-            Node::Function(
-                name,
-                return_typename,
-                arg_list,
-                Box::new(block),
-                self.offset_last,
-            )
+        };
+    }
+
+    // 呼び出された未宣言の関数は int name() と暗黙に宣言する
+    pub fn declare_implicit_function(&mut self, name: &str) -> Typename {
+        eprintln!("warning: implicit declaration of function '{}'", name);
+        let typename = Typename::Function(
+            Box::new(Typename::Integer(SignedFlag::Signed, 4)),
+            Vec::new(),
+            true,
+        );
+        self.declare_function(name, &typename, None, Inlining::Default, false);
+        typename
+    }
+
+    // 関数の宣言子を読んだ後の block
+    pub fn function(
+        &mut self,
+        storage_class: Option<StorageClass>,
+        inlining: Inlining,
+        declarator: Declarator,
+    ) -> Node {
+        self.local_vars.clear();
+        self.local_statics.clear();
+        self.offset_last = 0;
+
+        let name = declarator.name.expect("missing function name");
+        self.declare_function(&name, &declarator.typename, storage_class, inlining, true);
+        // 先にstaticで宣言されていれば内部結合のまま
+        let is_static = self.functions.get(&name).unwrap().is_static;
+        // 先の宣言に付けた指定も引き継ぐ
+        let inlining = self.functions.get(&name).unwrap().inlining;
+        let (return_typename, arg_list, variadic) =
+            Parser::function_signature(&declarator.typename);
+        // 定義の () は引数なし
+        let variadic = variadic && !arg_list.is_empty();
+
+        // 仮引数はローカル変数として宣言順に領域を取り、
+        // プロローグではその領域に引数を書き込む
+        let mut params: Vec<(usize, Typename)> = Vec::new();
+        for (typename, param_name) in declarator.parameters.unwrap_or_default() {
+            let param_name = param_name.expect("parameter name omitted in function definition");
+            self.declare_local(param_name.clone(), &typename);
+            let var = self.local_vars.get(&param_name).unwrap();
+            params.push((var.offset, var.typename.clone()));
         }
+
+        // { // do something }
+        self.return_typename = return_typename.clone();
+        let block = self.block();
+        Node::Function(
+            name,
+            return_typename,
+            params,
+            variadic,
+            is_static,
+            inlining,
+            Box::new(block),
+            self.offset_last,
+        )
     }
 }
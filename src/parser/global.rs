@@ -0,0 +1,88 @@
+use crate::ir::lower::eval_constant;
+use crate::node::{new_assign_cast, GVar, Node};
+use crate::token::Token;
+use crate::typename::{is_compatible, StorageClass, Typename};
+
+use super::Parser;
+
+impl Parser {
+    // "=" があれば静的変数の初期化子を読む
+    pub fn static_initializer(&mut self, typename: &Typename) -> Option<Node> {
+        if let Token::Equal = self.token_iter.peep().unwrap_or(Token::Eof) {
+            if let Typename::Array(_, _) = typename {
+                panic!("array initializer is not supported");
+            }
+            self.token_iter.ignore(1);
+            let value = new_assign_cast(self.assign(), typename);
+            match eval_constant(&value) {
+                Some(constant) => Some(constant),
+                None => panic!("initializer element is not constant"),
+            }
+        } else {
+            None
+        }
+    }
+
+    // グローバル変数を登録してラベルを返す
+    pub fn declare_global(&mut self, name: String, typename: &Typename, define: bool) -> String {
+        if let Typename::Void = typename {
+            panic!("variable cannot be declared as void type")
+        }
+        if self.functions.contains_key(&name) {
+            panic!("'{}' redeclared as different kind of symbol", name);
+        }
+        match self.global_vars.get_mut(&name) {
+            Some(var) => {
+                if !is_compatible(&var.typename, typename) {
+                    panic!("conflicting types for '{}'", name);
+                }
+                if define && var.defined {
+                    panic!("redefinition of '{}'", name);
+                }
+                var.defined |= define;
+                var.label.clone()
+            }
+            None => {
+                self.global_vars.insert(
+                    name.clone(),
+                    GVar {
+                        label: name.clone(),
+                        typename: typename.clone(),
+                        defined: define,
+                    },
+                );
+                name
+            }
+        }
+    }
+
+    // 型と変数名を読んだ後の "=" 初期化子
+    pub fn global_variable(
+        &mut self,
+        storage_class: Option<StorageClass>,
+        typename: Typename,
+        name: String,
+    ) -> Node {
+        let has_initializer = matches!(self.token_iter.peep(), Some(Token::Equal));
+        match storage_class {
+            Some(StorageClass::Extern) if !has_initializer => {
+                // 他の翻訳単位で定義される
+                self.declare_global(name, &typename, false);
+                Node::Empty
+            }
+            _ => {
+                if let Some(StorageClass::Extern) = storage_class {
+                    eprintln!("warning: '{}' initialized and declared 'extern'", name);
+                }
+                let label = self.declare_global(name, &typename, true);
+                let initializer = self.static_initializer(&typename);
+                Node::GlobalVar(
+                    label,
+                    typename,
+                    matches!(storage_class, Some(StorageClass::Static)),
+                    initializer.map(Box::new),
+                )
+            }
+        }
+    }
+}
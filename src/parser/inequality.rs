@@ -1,4 +1,4 @@
-use crate::node::{BinaryType, Node};
+use crate::node::{new_binary, BinaryType, Node};
 use crate::token::Token;
 
 use super::Parser;
@@ -14,11 +14,11 @@ impl Parser {
                         Token::Equal => {
                             // <=
                             self.token_iter.ignore(1);
-                            node = Node::Binary(Box::new((node, self.add())), BinaryType::LtEq);
+                            node = new_binary(BinaryType::LtEq, node, self.add());
                         }
                         _ => {
                             // <
-                            node = Node::Binary(Box::new((node, self.add())), BinaryType::Lt);
+                            node = new_binary(BinaryType::Lt, node, self.add());
                         }
                     }
                 }
@@ -28,11 +28,11 @@ impl Parser {
                         Token::Equal => {
                             // >=
                             self.token_iter.ignore(1);
-                            node = Node::Binary(Box::new((self.add(), node)), BinaryType::LtEq);
+                            node = new_binary(BinaryType::LtEq, self.add(), node);
                         }
                         _ => {
                             // >
-                            node = Node::Binary(Box::new((self.add(), node)), BinaryType::Lt);
+                            node = new_binary(BinaryType::Lt, self.add(), node);
                         }
                     }
                 }
@@ -1,16 +1,25 @@
-use crate::node::{Function, LVar};
+use crate::node::{Function, GVar, LVar, Node};
 use crate::tokenizer::TokenIter;
+use crate::typename::Typename;
 use std::collections::HashMap;
 
 /*
  * 生成文法
  *
- * program = function*
- * function = declaration "(" declaration? ("," declaration)* ")" block?
- * declaration = ident+ ident
+ * program = ( storage_class? declaration_specifier ( function | declarator_list ) )*
+ * function = declarator block
+ * declarator_list = init_declarator ( "," init_declarator )* ";"
+ * init_declarator = declarator ( "=" assign )?
+ * storage_class = "static" | "extern" | "auto" | "register"
+ * declaration_specifier = ( type_specifier | type_qualifier )+
+ * declarator = ( "*" type_qualifier* )* direct_declarator
+ * direct_declarator = ( ident | "(" declarator ")" )? type_suffix
+ * type_suffix = ( "[" assign? "]" )* | "(" parameter_list
+ * parameter_list = ( parameter ( "," parameter )* ( "," "..." )? )? ")"
+ * parameter = declaration_specifier declarator
  * block = "{" statement* "}"
  * statement    = expression ";"
- *              | declaration ";"
+ *              | storage_class? declaration_specifier declarator_list
  *              | block
  *              | "if" "(" expression ")" statement ( "else" statement )?
  *              | "while" "(" expression ")" statement
@@ -23,9 +32,12 @@ use std::collections::HashMap;
  * inequality = add ( "<" add | "<=" add | ">" add | ">=" add )*
  * add = mul ( "+" mul | "-" mul )*
  * mul = unary ( "*" unary | "/" unary )*
- * unary = ( "+" | "-" | "!" )? primary
+ * unary = ( "+" | "-" | "!" )? postfix
+ *       | ( "&" | "*" ) unary
+ *       | "(" type_name ")" unary
+ * postfix = primary ( "(" assign? ("," assign )* ")" | "[" expression "]" )*
  * primary  = num
- *          | ident ( "(" expr? ("," expr )* ")" )?
+ *          | ident
  *          | "(" expression ")"
  *
  *
@@ -38,9 +50,11 @@ pub mod declaration;
 pub mod equality;
 pub mod expression;
 pub mod function;
+pub mod global;
 pub mod inequality;
 pub mod mul;
 pub mod parse;
+pub mod postfix;
 pub mod primary;
 pub mod program;
 pub mod statement;
@@ -50,5 +64,12 @@ pub struct Parser {
     token_iter: TokenIter,
     pub local_vars: HashMap<String, LVar>,
     pub functions: HashMap<String, Function>,
+    pub global_vars: HashMap<String, GVar>,
+    // 関数内のstatic変数 (関数ごとに作り直す)
+    pub local_statics: HashMap<String, GVar>,
+    // 関数内のstatic変数の定義 (関数の後ろに出力する)
+    pub static_definitions: Vec<Node>,
+    pub static_count: usize,
     pub offset_last: usize,
+    pub return_typename: Typename,
 }
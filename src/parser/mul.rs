@@ -1,4 +1,4 @@
-use crate::node::{BinaryType, Node};
+use crate::node::{new_binary, BinaryType, Node};
 use crate::token::Token;
 
 use super::Parser;
@@ -10,11 +10,11 @@ impl Parser {
             match self.token_iter.peep().unwrap_or(Token::Eof) {
                 Token::Asterisk => {
                     self.token_iter.ignore(1);
-                    node = Node::Binary(Box::new((node, self.unary())), BinaryType::Mul)
+                    node = new_binary(BinaryType::Mul, node, self.unary())
                 }
                 Token::Slash => {
                     self.token_iter.ignore(1);
-                    node = Node::Binary(Box::new((node, self.unary())), BinaryType::Div)
+                    node = new_binary(BinaryType::Div, node, self.unary())
                 }
                 _ => {
                     return node;
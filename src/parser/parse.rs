@@ -1,5 +1,6 @@
 use crate::node::Node;
 use crate::tokenizer::TokenIter;
+use crate::typename::Typename;
 use std::collections::HashMap;
 
 use super::Parser;
@@ -8,7 +9,12 @@ pub fn parse(token_iter: TokenIter) -> Node {
         token_iter: token_iter,
         local_vars: HashMap::new(),
         functions: HashMap::new(),
+        global_vars: HashMap::new(),
+        local_statics: HashMap::new(),
+        static_definitions: Vec::new(),
+        static_count: 0,
         offset_last: 0,
+        return_typename: Typename::Void,
     };
 
     parser.program()
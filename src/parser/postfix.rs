@@ -0,0 +1,99 @@
+use crate::node::{
+    default_argument_promotion, new_add, new_assign_cast, typename_of, Node, UnaryType,
+};
+use crate::token::Token;
+use crate::typename::{sprint_typename, Typename};
+
+use super::Parser;
+
+impl Parser {
+    pub fn postfix(&mut self) -> Node {
+        let mut node = self.primary();
+
+        loop {
+            match self.token_iter.peep().unwrap_or(Token::Eof) {
+                Token::LeftParen => {
+                    // Function Call
+                    self.token_iter.ignore(1);
+                    node = self.function_call(node);
+                }
+                Token::LeftBracket => {
+                    // a[i] は *(a + i)
+                    self.token_iter.ignore(1);
+                    let index = self.expression();
+                    if let Token::RightBracket = self.token_iter.next().unwrap_or(Token::Eof) {
+                    } else {
+                        panic!("missing ']' after array subscript");
+                    }
+                    let address = new_add(node, index);
+                    if let Typename::Pointer(_) = typename_of(&address) {
+                    } else {
+                        panic!("subscripted value is neither array nor pointer");
+                    }
+                    node = Node::Unary(Box::new(address), UnaryType::Deref);
+                }
+                _ => {
+                    return node;
+                }
+            }
+        }
+    }
+
+    fn function_call(&mut self, callee: Node) -> Node {
+        // 関数名か関数ポインタ (またはその参照外し) を呼び出す
+        let (return_typename, arg_typenames, variadic) = match typename_of(&callee) {
+            Typename::Function(return_typename, arg_typenames, variadic) => {
+                (*return_typename, arg_typenames, variadic)
+            }
+            Typename::Pointer(pointee) => match *pointee {
+                Typename::Function(return_typename, arg_typenames, variadic) => {
+                    (*return_typename, arg_typenames, variadic)
+                }
+                typename => panic!(
+                    "called object of type '{}*' is not a function",
+                    sprint_typename(&typename)
+                ),
+            },
+            typename => panic!(
+                "called object of type '{}' is not a function",
+                sprint_typename(&typename)
+            ),
+        };
+
+        let mut arg_list: Vec<Node> = Vec::new();
+        if self.token_iter.peep().unwrap_or(Token::Eof).is_rightparen() {
+            // Call func()
+            self.token_iter.ignore(1);
+        } else {
+            arg_list.push(self.assign());
+            loop {
+                match self.token_iter.next().unwrap_or(Token::Eof) {
+                    Token::RightParen => break,
+                    Token::Comma => {
+                        arg_list.push(self.assign());
+                    }
+                    _ => {
+                        panic!("Invaid argument expression for function call");
+                    }
+                }
+            }
+        }
+
+        if arg_list.len() < arg_typenames.len() {
+            panic!("too few arguments to function");
+        }
+        if arg_list.len() > arg_typenames.len() && !variadic {
+            panic!("too many arguments to function");
+        }
+        // 引数は仮引数の型に変換し、可変長部分は既定の実引数拡張を施す
+        let arg_list = arg_list
+            .into_iter()
+            .enumerate()
+            .map(|(order, arg)| match arg_typenames.get(order) {
+                Some(typename) => new_assign_cast(arg, typename),
+                None => default_argument_promotion(arg),
+            })
+            .collect();
+        Node::FunctionCall(Box::new(callee), arg_list, return_typename, variadic)
+    }
+}
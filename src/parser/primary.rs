@@ -1,5 +1,6 @@
 use crate::node::Node;
 use crate::token::{sprint_token, Token};
+use crate::typename::Typename;
 
 use super::Parser;
 
@@ -21,53 +22,95 @@ impl Parser {
                 }
             }
             Token::Identity(name) => {
-                return if self.token_iter.peep().unwrap().is_leftparen() {
-                    // Function Call
-                    eprintln!("this is function call");
-                    self.token_iter.ignore(1);
-                    let mut arg_list: Vec<Node> = Vec::new();
-
-                    if self.functions.get(&name).is_none() {
-                        panic!("function named {} is not declared here", name);
-                    }
-
-                    match self.token_iter.clone().next().unwrap() {
-                        Token::RightParen => {
-                            // Call func()
-                            self.token_iter.ignore(1);
-                            return Node::FunctionCall(name, arg_list);
-                        }
-                        _ => {
-                            arg_list.push(self.expression());
-                        }
-                    }
-                    loop {
-                        match self.token_iter.next().unwrap() {
-                            Token::RightParen => {
-                                // Call func(args)
-                                return Node::FunctionCall(name, arg_list);
-                            }
-                            Token::Comma => {
-                                arg_list.push(self.expression());
-                            }
-                            _ => {
-                                panic!("Invaid argument expression for function call {}", name);
-                            }
-                        }
-                    }
-                } else {
+                if let Some(var) = self.local_vars.get(&name) {
                     // Variable
-                    let var = self
-                        .local_vars
-                        .get(&name)
-                        .expect("'{}' is not declared in this scope");
                     Node::LVar(var.offset, var.typename.clone())
-                };
+                } else if let Some(var) = self
+                    .local_statics
+                    .get(&name)
+                    .or_else(|| self.global_vars.get(&name))
+                {
+                    // Static variable
+                    Node::GVar(var.label.clone(), var.typename.clone())
+                } else if let Some(function) = self.functions.get(&name) {
+                    // Function designator
+                    Node::FunctionName(
+                        name,
+                        Typename::Function(
+                            Box::new(function.ret_typename.clone()),
+                            function.arg_typename.clone(),
+                            function.variadic,
+                        ),
+                    )
+                } else if self.token_iter.peep().unwrap_or(Token::Eof).is_leftparen() {
+                    let typename = self.declare_implicit_function(&name);
+                    Node::FunctionName(name, typename)
+                } else {
+                    panic!("'{}' is not declared in this scope", name);
+                }
+            }
+            Token::Num(n, typename) => Node::Num(n, typename),
+            Token::FloatNum(x, typename) => Node::FloatNum(x, typename),
+            Token::Str(bytes) => Node::Str(bytes),
+            Token::BuiltinVaStart => {
+                // va_start(ap, last)
+                self.expect_leftparen("va_start");
+                let ap = self.assign();
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_comma() {
+                    panic!("va_start requires two arguments");
+                }
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_identity() {
+                    panic!("the second argument of va_start must be a parameter name");
+                }
+                self.expect_rightparen("va_start");
+                Node::VaStart(Box::new(ap))
+            }
+            Token::BuiltinVaArg => {
+                // va_arg(ap, type)
+                self.expect_leftparen("va_arg");
+                let ap = self.assign();
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_comma() {
+                    panic!("va_arg requires two arguments");
+                }
+                let typename = self.type_name();
+                self.expect_rightparen("va_arg");
+                Node::VaArg(Box::new(ap), typename)
+            }
+            Token::BuiltinVaEnd => {
+                // va_end(ap) は何もしない
+                self.expect_leftparen("va_end");
+                self.assign();
+                self.expect_rightparen("va_end");
+                Node::Empty
+            }
+            Token::BuiltinVaCopy => {
+                // va_copy(dest, src)
+                self.expect_leftparen("va_copy");
+                let dest = self.assign();
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_comma() {
+                    panic!("va_copy requires two arguments");
+                }
+                let src = self.assign();
+                self.expect_rightparen("va_copy");
+                Node::VaCopy(Box::new((dest, src)))
             }
-            Token::Num(n) => Node::Num(n),
             _ => {
                 panic!("Invalid Input");
             }
         }
     }
 }
+
+impl Parser {
+    fn expect_leftparen(&mut self, name: &str) {
+        if !self.token_iter.next().unwrap_or(Token::Eof).is_leftparen() {
+            panic!("missing '(' after {}", name);
+        }
+    }
+
+    fn expect_rightparen(&mut self, name: &str) {
+        if !self.token_iter.next().unwrap_or(Token::Eof).is_rightparen() {
+            panic!("missing ')' in {}", name);
+        }
+    }
+}
@@ -1,11 +1,49 @@
 use crate::node::Node;
+use crate::token::Token;
+use crate::typename::{Inlining, Typename};
 
 use super::Parser;
 impl Parser {
     pub fn program(&mut self) -> Node {
         let mut code: Vec<Node> = Vec::new();
         while self.token_iter.peep().is_some() {
-            code.push(self.function());
+            let (storage_class, inlining) = self.storage_class_and_inlining();
+            let base = self.declaration_specifier();
+            let declarator = self.declarator(base.clone());
+            if let (Typename::Function(_, _, _), Token::LeftCurl) = (
+                &declarator.typename,
+                self.token_iter.peep().unwrap_or(Token::Eof),
+            ) {
+                code.push(self.function(storage_class, inlining, declarator));
+                // 関数内のstatic変数の定義
+                code.append(&mut self.static_definitions);
+                continue;
+            }
+            // 宣言子の並び
+            let mut declarator = declarator;
+            loop {
+                let name = declarator.name.expect("missing name in external declaration");
+                let inlining = std::cmp::max(inlining, self.trailing_attributes());
+                if let Typename::Function(_, _, _) = declarator.typename {
+                    self.declare_function(
+                        &name,
+                        &declarator.typename,
+                        storage_class,
+                        inlining,
+                        false,
+                    );
+                } else {
+                    if inlining != Inlining::Default {
+                        panic!("variable '{}' declared 'inline'", name);
+                    }
+                    code.push(self.global_variable(storage_class, declarator.typename, name));
+                }
+                match self.token_iter.next().unwrap_or(Token::Eof) {
+                    Token::Comma => declarator = self.declarator(base.clone()),
+                    Token::Semicolon => break,
+                    _ => panic!("missing ';' after external declaration"),
+                }
+            }
         }
         Node::Block(code)
     }
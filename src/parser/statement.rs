@@ -1,4 +1,4 @@
-use crate::node::Node;
+use crate::node::{new_assign_cast, Node};
 use crate::token::Token;
 
 use super::Parser;
@@ -12,16 +12,34 @@ impl Parser {
                 self.token_iter.ignore(1);
                 if let Token::Semicolon = self.token_iter.peep().unwrap_or(Token::Eof) {
                     self.token_iter.ignore(1);
-                    Node::Return(None)
+                    Node::Return(None, false)
                 } else {
-                    let return_expression = self.expression();
+                    let return_expression = new_assign_cast(self.expression(), &self.return_typename);
                     if let Token::Semicolon = self.token_iter.next().unwrap_or(Token::Eof) {
-                        Node::Return(Some(Box::new(return_expression)))
+                        Node::Return(Some(Box::new(return_expression)), false)
                     } else {
                         panic!("Missing Semicolon at the end of the statement")
                     }
                 }
             }
+            // 文の前の属性 (musttailのほかは無視する)
+            Token::Attribute => {
+                self.token_iter.ignore(1);
+                let mut musttail = false;
+                for name in self.attribute_names() {
+                    match &*name {
+                        "musttail" => musttail = true,
+                        _ => eprintln!("warning: '{}' attribute directive ignored", name),
+                    }
+                }
+                match self.statement() {
+                    Node::Return(Some(value), _) if musttail => Node::Return(Some(value), true),
+                    _ if musttail => {
+                        panic!("'musttail' attribute must be followed by a return of a call")
+                    }
+                    statement => statement,
+                }
+            }
             Token::If => {
                 self.token_iter.ignore(1);
                 // expect "("
@@ -105,39 +123,13 @@ impl Parser {
                 }
                 Node::While(Box::new((cond, self.statement())))
             }
-            Token::Signed
-            | Token::Unsigned
-            | Token::Short
-            | Token::Long
-            | Token::Void
-            | Token::Char
-            | Token::Int => {
-                // typename keywords
+            _ if self.is_declaration_start() => {
                 let declaration = self.declaration().expect("invalid declaration");
                 if !self.token_iter.next().unwrap_or(Token::Eof).is_semicolon() {
                     panic!("missing ';' after declaration statement");
                 }
                 declaration
             }
-            Token::Identity(_) => {
-                // Identity -> declaration or expression
-                match self.declaration() {
-                    None => {
-                        let node = self.expression();
-                        if let Token::Semicolon = self.token_iter.next().unwrap_or(Token::Eof) {
-                            node
-                        } else {
-                            panic!("missing ';' after an expression statement");
-                        }
-                    }
-                    Some(declaration) => {
-                        if !self.token_iter.next().unwrap_or(Token::Eof).is_semicolon() {
-                            panic!("missing ';' after declaration statement");
-                        }
-                        declaration
-                    }
-                }
-            }
             _ => {
                 let node = self.expression();
                 if let Token::Semicolon = self.token_iter.next().unwrap_or(Token::Eof) {
@@ -1,5 +1,10 @@
-use crate::node::{BinaryType, Node};
+use crate::node::{
+    lvalue_typename_of, new_binary, new_cast, typename_of, BinaryType, Node, UnaryType,
+};
 use crate::token::Token;
+use crate::typename::{
+    is_floating, is_typename_token, sizeof, sprint_typename, unqualified, SignedFlag, Typename,
+};
 
 use super::Parser;
 
@@ -8,13 +13,94 @@ impl Parser {
         return match self.token_iter.peep().unwrap_or(Token::Eof) {
             Token::Plus => {
                 self.token_iter.ignore(1);
-                self.primary()
+                self.postfix()
             }
             Token::Minus => {
                 self.token_iter.ignore(1);
-                Node::Binary(Box::new((Node::Num(0), self.primary())), BinaryType::Sub)
+                let node = self.postfix();
+                let typename = unqualified(&typename_of(&node)).clone();
+                // 浮動小数点数は -0.0 から引く (0 - 0.0 は +0.0 になり符号が反転しない)
+                let zero = if is_floating(&typename) {
+                    Node::FloatNum(-0.0, typename)
+                } else {
+                    Node::Num(0, Typename::Integer(SignedFlag::Signed, 4))
+                };
+                new_binary(BinaryType::Sub, zero, node)
             }
-            _ => self.primary(),
+            Token::Ampersand => {
+                self.token_iter.ignore(1);
+                let node = self.unary();
+                match node {
+                    Node::LVar(_, _) | Node::GVar(_, _) | Node::FunctionName(_, _) => {}
+                    Node::Unary(_, UnaryType::Deref) => {}
+                    _ => panic!("lvalue required as unary '&' operand"),
+                }
+                Node::Unary(Box::new(node), UnaryType::Address)
+            }
+            Token::Asterisk => {
+                self.token_iter.ignore(1);
+                let node = self.unary();
+                match typename_of(&node) {
+                    Typename::Pointer(_) | Typename::Function(_, _, _) => {}
+                    typename => panic!(
+                        "invalid type argument of unary '*' (have '{}')",
+                        sprint_typename(&typename)
+                    ),
+                }
+                Node::Unary(Box::new(node), UnaryType::Deref)
+            }
+            Token::LeftParen if self.is_cast() => {
+                // ( type_name ) unary
+                self.token_iter.ignore(1);
+                let typename = self.type_name();
+                if !self.token_iter.next().unwrap_or(Token::Eof).is_rightparen() {
+                    panic!("missing ')' in cast expression");
+                }
+                let node = self.unary();
+                match (typename_of(&node), unqualified(&typename)) {
+                    (_, Typename::Void) => {}
+                    (Typename::Void, _) => panic!("void value not ignored as it ought to be"),
+                    (_, Typename::Array(_, _) | Typename::Function(_, _, _)) => panic!(
+                        "cast specifies array or function type '{}'",
+                        sprint_typename(&typename)
+                    ),
+                    _ => {}
+                }
+                new_cast(node, unqualified(&typename))
+            }
+            Token::Sizeof => {
+                // sizeof ( type_name ) または sizeof unary (式は評価しない)
+                self.token_iter.ignore(1);
+                let typename = match self.token_iter.peep().unwrap_or(Token::Eof) {
+                    Token::LeftParen if self.is_cast() => {
+                        self.token_iter.ignore(1);
+                        let typename = self.type_name();
+                        if !self.token_iter.next().unwrap_or(Token::Eof).is_rightparen() {
+                            panic!("missing ')' in sizeof expression");
+                        }
+                        typename
+                    }
+                    _ => lvalue_typename_of(&self.unary()),
+                };
+                if let Typename::Void | Typename::Function(_, _, _) = unqualified(&typename) {
+                    panic!(
+                        "invalid application of 'sizeof' to type '{}'",
+                        sprint_typename(&typename)
+                    );
+                }
+                Node::Num(
+                    sizeof(&typename) as i64,
+                    Typename::Integer(SignedFlag::Unsigned, 8),
+                )
+            }
+            _ => self.postfix(),
         };
     }
+
+    // "(" の後ろが型名ならキャスト
+    fn is_cast(&mut self) -> bool {
+        let mut lookahead = self.token_iter.clone();
+        lookahead.ignore(1);
+        is_typename_token(&lookahead.peep().unwrap_or(Token::Eof))
+    }
 }
@@ -0,0 +1,287 @@
+use crate::ir::dce::{dce, remove_unused_functions};
+use crate::ir::fold::fold;
+use crate::ir::gvn::gvn;
+use crate::ir::indvars::indvars;
+use crate::ir::inline::inline;
+use crate::ir::licm::licm;
+use crate::ir::rotate::rotate;
+use crate::ir::tailcall::tailcall;
+use crate::ir::{sprint_function, sprint_module, ssa, Function, Module};
+
+// パスが書き換えるもの (変化があればtrueを返す)
+pub enum PassKind {
+    Function(fn(&mut Function) -> bool),
+    Module(fn(&mut Module) -> bool),
+    // コード生成で行う
+    Backend,
+}
+
+// 最適化パス
+pub struct Pass {
+    pub name: &'static str,
+    // このレベル以上で有効になる
+    pub level: usize,
+    pub kind: PassKind,
+}
+
+// 実行する順に並べる
+pub const PASSES: [Pass; 12] = [
+    // ヘッダの判定の値がメモリを通るうちに回転する
+    Pass {
+        name: "loop-rotate",
+        level: 2,
+        kind: PassKind::Function(rotate),
+    },
+    Pass {
+        name: "mem2reg",
+        level: 1,
+        kind: PassKind::Function(ssa::construct),
+    },
+    Pass {
+        name: "inline",
+        level: 1,
+        kind: PassKind::Module(inline),
+    },
+    // 展開しなかった呼び出しを末尾呼び出しにする
+    Pass {
+        name: "tailcall",
+        level: 1,
+        kind: PassKind::Function(tailcall),
+    },
+    Pass {
+        name: "fold",
+        level: 1,
+        kind: PassKind::Function(fold),
+    },
+    Pass {
+        name: "gvn",
+        level: 2,
+        kind: PassKind::Function(gvn),
+    },
+    Pass {
+        name: "licm",
+        level: 2,
+        kind: PassKind::Function(licm),
+    },
+    Pass {
+        name: "indvars",
+        level: 2,
+        kind: PassKind::Function(indvars),
+    },
+    Pass {
+        name: "dce",
+        level: 1,
+        kind: PassKind::Function(dce),
+    },
+    Pass {
+        name: "remove-unused-functions",
+        level: 1,
+        kind: PassKind::Module(remove_unused_functions),
+    },
+    Pass {
+        name: "regalloc",
+        level: 1,
+        kind: PassKind::Backend,
+    },
+    Pass {
+        name: "peephole",
+        level: 1,
+        kind: PassKind::Backend,
+    },
+];
+
+// -O2以上で中間表現のパスを繰り返す上限
+const MAX_ROUNDS: usize = 4;
+
+fn find_pass(name: &str) -> &'static Pass {
+    match PASSES.iter().find(|pass| pass.name == name) {
+        Some(pass) => pass,
+        None => panic!("unknown pass '{}'", name),
+    }
+}
+
+pub struct PassManager {
+    pub level: usize,
+    // -fno-<pass> で無効にしたパス
+    pub disabled: Vec<&'static str>,
+    // --print-after=<pass> で実行後に中間表現を出力するパス
+    pub print_after: Vec<&'static str>,
+}
+
+impl PassManager {
+    // -O0, -O1, -O2 (-O, -Ogは-O1, -Osは-O2), -fno-<pass>, --print-after=<pass> を読む
+    pub fn from_args(args: &[String]) -> PassManager {
+        let mut manager = PassManager {
+            level: 0,
+            disabled: Vec::new(),
+            print_after: Vec::new(),
+        };
+        for arg in args.iter() {
+            if let Some(level) = arg.strip_prefix("-O") {
+                manager.level = match level {
+                    // -Ogはデバッグしやすい範囲の最適化
+                    "" | "g" => 1,
+                    // 大きさを優先する最適化は区別しない
+                    "s" => 2,
+                    _ => match level.parse() {
+                        Ok(level) => level,
+                        Err(_) => panic!("invalid optimization level '{}'", arg),
+                    },
+                };
+            } else if let Some(name) = arg.strip_prefix("-fno-") {
+                match PASSES.iter().find(|pass| pass.name == name) {
+                    Some(pass) => manager.disabled.push(pass.name),
+                    // -fno-omit-frame-pointerはフレームの組み方の指定 (mainで読む)
+                    None if name == "omit-frame-pointer" => {}
+                    None => eprintln!("warning: ignoring unknown option '{}'", arg),
+                }
+            } else if let Some(name) = arg.strip_prefix("--print-after=") {
+                let pass = find_pass(name);
+                if let PassKind::Backend = pass.kind {
+                    panic!("pass '{}' does not work on the IR", name);
+                }
+                manager.print_after.push(pass.name);
+            }
+        }
+        manager
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        let pass = find_pass(name);
+        self.level >= pass.level && !self.disabled.contains(&pass.name)
+    }
+
+    // 有効な中間表現のパスを順に実行する
+    // -O2以上では変化がなくなるまで繰り返す
+    pub fn run(&self, module: &mut Module) {
+        let rounds = if self.level >= 2 { MAX_ROUNDS } else { 1 };
+        for _ in 0..rounds {
+            let mut changed = false;
+            for pass in PASSES.iter() {
+                if !self.is_enabled(pass.name) {
+                    continue;
+                }
+                let print = self.print_after.contains(&pass.name);
+                match pass.kind {
+                    PassKind::Function(run) => {
+                        for function in module.functions.iter_mut() {
+                            changed |= run(function);
+                            if print {
+                                eprint!(
+                                    "*** IR after {} ***\n{}",
+                                    pass.name,
+                                    sprint_function(function)
+                                );
+                            }
+                        }
+                    }
+                    PassKind::Module(run) => {
+                        changed |= run(module);
+                        if print {
+                            eprint!("*** IR after {} ***\n{}", pass.name, sprint_module(module));
+                        }
+                    }
+                    PassKind::Backend => {}
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::lower::lower;
+    use crate::ir::{ssa, BlockId, Inst};
+    use crate::parser::parse::parse;
+    use crate::pass::{PassManager, PASSES};
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn pass_manager_test() {
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+        let manager = PassManager::from_args(&args(&["main.c"]));
+        assert!(!manager.is_enabled("mem2reg"));
+        assert!(!manager.is_enabled("regalloc"));
+        let manager = PassManager::from_args(&args(&["-O2", "-fno-fold", "main.c"]));
+        assert!(manager.is_enabled("mem2reg"));
+        assert!(!manager.is_enabled("fold"));
+        assert!(manager.is_enabled("peephole"));
+        // 後に指定したレベルが優先される
+        let manager = PassManager::from_args(&args(&["-O2", "-O0"]));
+        assert!(!manager.is_enabled("peephole"));
+        let manager = PassManager::from_args(&args(&["-Og"]));
+        assert_eq!(manager.level, 1);
+        let manager = PassManager::from_args(&args(&["-Os"]));
+        assert_eq!(manager.level, 2);
+        // パスの名前でない-fno-*は無視する
+        let manager =
+            PassManager::from_args(&args(&["-O1", "-fno-builtin", "-fno-omit-frame-pointer"]));
+        assert!(manager.disabled.is_empty());
+        assert!(manager.is_enabled("fold"));
+    }
+
+    #[test]
+    fn disabled_pass_test() {
+        let program = parse(tokenize(
+            "int f() { int a; int b; int i; int t; int *p; a = 1; b = 2; for (i = 0; i < 5; i = i + 1) { t = a; a = b; b = t; } p = &a; return a * 10 + b; }"
+                .to_string(),
+        ));
+        // -O2で1つずつパスを外しても、中間表現をSSA形式から戻せる
+        for pass in PASSES.iter() {
+            let args = vec!["-O2".to_string(), format!("-fno-{}", pass.name)];
+            let mut module = lower(&program, false);
+            PassManager::from_args(&args).run(&mut module);
+            for function in module.functions.iter_mut() {
+                ssa::destruct(function);
+                let phis = function
+                    .blocks
+                    .iter()
+                    .flat_map(|block| block.insts.iter())
+                    .filter(|inst| matches!(inst, Inst::Phi(_, _, _)))
+                    .count();
+                assert_eq!(phis, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn volatile_test() {
+        let program = parse(tokenize(
+            "int f(volatile int *p, int n) { int s; int i; s = 0; for (i = 0; i < n; i = i + 1) { s = s + *p + *p; } *p = 1; *p = 2; return s; } int h() { volatile int x; x = 1; return x + x; }"
+                .to_string(),
+        ));
+        let mut module = lower(&program, false);
+        PassManager::from_args(&["-O2".to_string()]).run(&mut module);
+        for function in module.functions.iter() {
+            // volatileな読み書きの数と、読み出しのあるブロック
+            let mut loads: Vec<BlockId> = Vec::new();
+            let mut stores = 0;
+            for (id, block) in function.blocks.iter().enumerate() {
+                for inst in block.insts.iter() {
+                    match inst {
+                        Inst::Load(_, _, _, true) => loads.push(BlockId(id)),
+                        Inst::Store(_, _, _, true) => stores += 1,
+                        _ => {}
+                    }
+                }
+            }
+            // 同じアドレスの読み出しもまとめず、書き込みも消さない
+            assert_eq!(loads.len(), 2, "{}", function.name);
+            assert_eq!(stores, if function.name == "f" { 2 } else { 1 });
+            if function.name == "f" {
+                // ループの外に出さない
+                for block in loads.iter() {
+                    assert!(function
+                        .block(*block)
+                        .terminator
+                        .successors()
+                        .contains(block));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::register::Register;
+
+// volatileなメモリアクセスに付ける印 (読み出しを省かない)
+pub const VOLATILE_MARK: &str = "# volatile";
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AsmOperand {
+    Register(Register, usize), // (register, size)
+    Immediate(i64),
+    Memory(usize, String), // (size (PTRがなければ0), [ ] の中身)
+    Symbol(String),        // ラベルや関数名
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AsmLine {
+    Inst(AsmInst),
+    Label(String),
+    Directive(String), // そのまま出力する行
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AsmInst {
+    pub mnemonic: String,
+    pub operands: Vec<AsmOperand>,
+    pub volatile: bool,
+}
+
+impl AsmInst {
+    fn new(mnemonic: &str, operands: Vec<AsmOperand>) -> AsmInst {
+        AsmInst {
+            mnemonic: mnemonic.to_string(),
+            operands,
+            volatile: false,
+        }
+    }
+}
+
+fn parse_operand(text: &str) -> AsmOperand {
+    if let Some(start) = text.find('[') {
+        let size = match text.split_whitespace().next() {
+            Some("BYTE") => 1,
+            Some("WORD") => 2,
+            Some("DWORD") => 4,
+            Some("QWORD") => 8,
+            Some("XMMWORD") => 16,
+            _ => 0,
+        };
+        let end = text.rfind(']').unwrap();
+        return AsmOperand::Memory(size, text[start + 1..end].to_string());
+    }
+    if let Some((register, size)) = Register::from_name(text) {
+        return AsmOperand::Register(register, size);
+    }
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|n| n as i64),
+        None => digits.parse::<i64>().ok(),
+    };
+    match value {
+        Some(n) if negative => AsmOperand::Immediate(n.wrapping_neg()),
+        Some(n) => AsmOperand::Immediate(n),
+        None => AsmOperand::Symbol(text.to_string()),
+    }
+}
+
+pub fn parse_line(line: &str) -> AsmLine {
+    if !line.starts_with(' ') && line.ends_with(':') {
+        return AsmLine::Label(line[..line.len() - 1].to_string());
+    }
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('.') {
+        return AsmLine::Directive(line.to_string());
+    }
+    let (body, volatile) = match trimmed.strip_suffix(VOLATILE_MARK) {
+        Some(body) => (body.trim(), true),
+        None => (trimmed, false),
+    };
+    let (mnemonic, rest) = match body.find(' ') {
+        Some(index) => (&body[..index], body[index + 1..].trim()),
+        None => (body, ""),
+    };
+    let operands = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(", ").map(parse_operand).collect()
+    };
+    AsmLine::Inst(AsmInst {
+        mnemonic: mnemonic.to_string(),
+        operands,
+        volatile,
+    })
+}
+
+fn sprint_operand(operand: &AsmOperand) -> String {
+    match operand {
+        AsmOperand::Register(register, size) => register.get_name(*size),
+        AsmOperand::Immediate(n) => format!("{}", n),
+        AsmOperand::Memory(size, address) => match size {
+            1 => format!("BYTE PTR [{}]", address),
+            2 => format!("WORD PTR [{}]", address),
+            4 => format!("DWORD PTR [{}]", address),
+            8 => format!("QWORD PTR [{}]", address),
+            16 => format!("XMMWORD PTR [{}]", address),
+            _ => format!("[{}]", address),
+        },
+        AsmOperand::Symbol(name) => name.clone(),
+    }
+}
+
+pub fn sprint_line(line: &AsmLine) -> String {
+    match line {
+        AsmLine::Inst(inst) => {
+            let mut text = format!("    {}", inst.mnemonic);
+            if !inst.operands.is_empty() {
+                let operands: Vec<String> = inst.operands.iter().map(sprint_operand).collect();
+                text += &format!(" {}", operands.join(", "));
+            }
+            if inst.volatile {
+                text += &format!(" {}", VOLATILE_MARK);
+            }
+            text
+        }
+        AsmLine::Label(label) => format!("{}:", label),
+        AsmLine::Directive(text) => text.clone(),
+    }
+}
+
+// アセンブリを命令の列として読み、書き換えられなくなるまでパターンを適用する
+pub fn optimize(lines: Vec<String>) -> Vec<String> {
+    let mut lines: Vec<AsmLine> = lines.iter().map(|line| parse_line(line)).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let passes: [fn(&mut Vec<AsmLine>) -> bool; 7] = [
+            fold_push_pop,
+            remove_useless_inst,
+            forward_store_to_load,
+            fuse_compare_branch,
+            invert_branch_over_jump,
+            remove_jump_to_next,
+            remove_dead_labels,
+        ];
+        for pass in passes.iter() {
+            changed |= pass(&mut lines);
+        }
+    }
+    lines.iter().map(sprint_line).collect()
+}
+
+fn as_inst(line: &AsmLine) -> Option<&AsmInst> {
+    match line {
+        AsmLine::Inst(inst) => Some(inst),
+        _ => None,
+    }
+}
+
+// push x; pop y => mov y, x (x == y なら何もしない)
+fn fold_push_pop(lines: &mut Vec<AsmLine>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index + 1 < lines.len() {
+        if let (Some(push), Some(pop)) = (as_inst(&lines[index]), as_inst(&lines[index + 1])) {
+            if push.mnemonic == "push" && pop.mnemonic == "pop" {
+                let (src, dst) = (push.operands[0].clone(), pop.operands[0].clone());
+                if src == dst {
+                    lines.drain(index..index + 2);
+                } else {
+                    lines.splice(
+                        index..index + 2,
+                        vec![AsmLine::Inst(AsmInst::new("mov", vec![dst, src]))],
+                    );
+                }
+                changed = true;
+                continue;
+            }
+        }
+        index += 1;
+    }
+    changed
+}
+
+// mov r, r (64bit) と add r, 0 / sub r, 0 を消す
+// 32bitのmov r, rは上位を0にするので残す
+fn remove_useless_inst(lines: &mut Vec<AsmLine>) -> bool {
+    let before = lines.len();
+    let mut index = 0;
+    while index < lines.len() {
+        let useless = match as_inst(&lines[index]) {
+            Some(inst) => match (inst.mnemonic.as_str(), inst.operands.as_slice()) {
+                ("mov", [AsmOperand::Register(dst, 8), AsmOperand::Register(src, 8)])
+                | ("movaps", [AsmOperand::Register(dst, _), AsmOperand::Register(src, _)]) => {
+                    dst == src
+                }
+                ("add", [AsmOperand::Register(_, _), AsmOperand::Immediate(0)])
+                | ("sub", [AsmOperand::Register(_, _), AsmOperand::Immediate(0)]) => {
+                    // 直後でフラグを読むなら残す
+                    !lines
+                        .get(index + 1)
+                        .and_then(as_inst)
+                        .is_some_and(reads_flags)
+                }
+                _ => false,
+            },
+            None => false,
+        };
+        if useless {
+            lines.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+    lines.len() != before
+}
+
+fn reads_flags(inst: &AsmInst) -> bool {
+    let mnemonic = inst.mnemonic.as_str();
+    (mnemonic.starts_with('j') && mnemonic != "jmp")
+        || mnemonic.starts_with("set")
+        || mnemonic.starts_with("cmov")
+}
+
+// mov M, r; mov r2, M => mov M, r; mov r2, r
+fn forward_store_to_load(lines: &mut Vec<AsmLine>) -> bool {
+    let mut changed = false;
+    for index in 1..lines.len() {
+        let replacement = match (as_inst(&lines[index - 1]), as_inst(&lines[index])) {
+            (Some(store), Some(load))
+                if store.mnemonic == load.mnemonic && !store.volatile && !load.volatile =>
+            {
+                match (store.operands.as_slice(), load.operands.as_slice()) {
+                    (
+                        [AsmOperand::Memory(store_size, store_address), AsmOperand::Register(src, src_size)],
+                        [AsmOperand::Register(dst, dst_size), AsmOperand::Memory(load_size, load_address)],
+                    ) if store_address == load_address
+                        && store_size == load_size
+                        && src_size == dst_size =>
+                    {
+                        match (store.mnemonic.as_str(), dst, src) {
+                            (_, dst, src) if dst == src => Some(None),
+                            ("mov", Register::XMM(_), _) | ("mov", _, Register::XMM(_)) => None,
+                            ("mov", _, _) => Some(Some(AsmInst::new(
+                                "mov",
+                                vec![
+                                    AsmOperand::Register(*dst, *dst_size),
+                                    AsmOperand::Register(*src, *src_size),
+                                ],
+                            ))),
+                            ("movss", _, _) | ("movsd", _, _) => Some(Some(AsmInst::new(
+                                "movaps",
+                                vec![AsmOperand::Register(*dst, 8), AsmOperand::Register(*src, 8)],
+                            ))),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            lines[index] = match replacement {
+                Some(inst) => AsmLine::Inst(inst),
+                // 同じレジスタへの読み出しは消す (後で空行を取り除く)
+                None => AsmLine::Directive(String::new()),
+            };
+            changed = true;
+        }
+    }
+    lines.retain(|line| *line != AsmLine::Directive(String::new()));
+    changed
+}
+
+fn negate_condition(condition: &str) -> Option<&'static str> {
+    Some(match condition {
+        "e" => "ne",
+        "ne" => "e",
+        "l" => "ge",
+        "ge" => "l",
+        "le" => "g",
+        "g" => "le",
+        "b" => "ae",
+        "ae" => "b",
+        "be" => "a",
+        "a" => "be",
+        "p" => "np",
+        "np" => "p",
+        "s" => "ns",
+        "ns" => "s",
+        _ => return None,
+    })
+}
+
+// setcc r8; movzx r, r8; test r, r; jne L => setcc r8; movzx r, r8; jcc L
+// 結果のレジスタがその後読まれなければsetccとmovzxも消す
+fn fuse_compare_branch(lines: &mut Vec<AsmLine>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index + 3 < lines.len() {
+        let fused = match (
+            as_inst(&lines[index]),
+            as_inst(&lines[index + 1]),
+            as_inst(&lines[index + 2]),
+            as_inst(&lines[index + 3]),
+        ) {
+            (Some(setcc), Some(movzx), Some(test), Some(jump))
+                if setcc.mnemonic.starts_with("set")
+                    && movzx.mnemonic == "movzx"
+                    && test.mnemonic == "test"
+                    && (jump.mnemonic == "jne" || jump.mnemonic == "je") =>
+            {
+                match (
+                    setcc.operands.as_slice(),
+                    movzx.operands.as_slice(),
+                    test.operands.as_slice(),
+                ) {
+                    (
+                        [AsmOperand::Register(flag, 1)],
+                        [AsmOperand::Register(result, _), AsmOperand::Register(source, 1)],
+                        [AsmOperand::Register(lhs, _), AsmOperand::Register(rhs, _)],
+                    ) if flag == source && result == lhs && result == rhs => {
+                        let condition = &setcc.mnemonic[3..];
+                        let condition = if jump.mnemonic == "jne" {
+                            Some(condition)
+                        } else {
+                            negate_condition(condition)
+                        };
+                        condition.map(|condition| {
+                            (
+                                *flag,
+                                *result,
+                                AsmInst::new(&format!("j{}", condition), jump.operands.clone()),
+                            )
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match fused {
+            Some((flag, result, jump)) => {
+                lines.splice(index + 2..index + 4, vec![AsmLine::Inst(jump)]);
+                if is_dead_after(lines, index + 2, flag) && is_dead_after(lines, index + 2, result)
+                {
+                    lines.drain(index..index + 2);
+                }
+                changed = true;
+            }
+            None => index += 1,
+        }
+    }
+    changed
+}
+
+// jne L1; jmp L2; L1: => je L2; L1:
+fn invert_branch_over_jump(lines: &mut Vec<AsmLine>) -> bool {
+    let mut changed = false;
+    for index in 0..lines.len().saturating_sub(2) {
+        let inverted = match (&lines[index], &lines[index + 1], &lines[index + 2]) {
+            (AsmLine::Inst(branch), AsmLine::Inst(jump), AsmLine::Label(label))
+                if branch.mnemonic.starts_with('j')
+                    && branch.mnemonic != "jmp"
+                    && jump.mnemonic == "jmp"
+                    && branch.operands == vec![AsmOperand::Symbol(label.clone())] =>
+            {
+                negate_condition(&branch.mnemonic[1..]).map(|condition| {
+                    AsmInst::new(&format!("j{}", condition), jump.operands.clone())
+                })
+            }
+            _ => None,
+        };
+        if let Some(inverted) = inverted {
+            lines[index] = AsmLine::Inst(inverted);
+            lines[index + 1] = AsmLine::Directive(String::new());
+            changed = true;
+        }
+    }
+    lines.retain(|line| *line != AsmLine::Directive(String::new()));
+    changed
+}
+
+// jmp L; L: => L:
+fn remove_jump_to_next(lines: &mut Vec<AsmLine>) -> bool {
+    let before = lines.len();
+    let mut index = 0;
+    while index < lines.len() {
+        let target = match as_inst(&lines[index]) {
+            Some(jump) if jump.mnemonic == "jmp" => match jump.operands.as_slice() {
+                [AsmOperand::Symbol(target)] => Some(target.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let next_labels = lines[index + 1..]
+            .iter()
+            .take_while(|line| matches!(line, AsmLine::Label(_)));
+        let to_next = target.is_some_and(|target| {
+            next_labels
+                .clone()
+                .any(|line| *line == AsmLine::Label(target.clone()))
+        });
+        if to_next {
+            lines.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+    lines.len() != before
+}
+
+// どこからも参照されないローカルラベル (.L) を消す
+fn remove_dead_labels(lines: &mut Vec<AsmLine>) -> bool {
+    let mut referenced: HashSet<String> = HashSet::new();
+    for line in lines.iter() {
+        let text = match line {
+            AsmLine::Label(_) => continue,
+            line => sprint_line(line),
+        };
+        for word in text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')) {
+            referenced.insert(word.to_string());
+        }
+    }
+    let before = lines.len();
+    lines.retain(|line| match line {
+        AsmLine::Label(label) => !label.starts_with(".L") || referenced.contains(label),
+        _ => true,
+    });
+    lines.len() != before
+}
+
+// 命令が読むレジスタと、前の値を残さず書き換えるレジスタ
+fn effects(inst: &AsmInst) -> (Vec<Register>, Vec<Register>) {
+    let mut uses: Vec<Register> = Vec::new();
+    let mut defs: Vec<Register> = Vec::new();
+    for operand in inst.operands.iter() {
+        if let AsmOperand::Memory(_, address) = operand {
+            for word in address.split(|c: char| !c.is_ascii_alphanumeric()) {
+                if let Some((register, _)) = Register::from_name(word) {
+                    uses.push(register);
+                }
+            }
+        }
+    }
+    let registers: Vec<(Register, usize)> = inst
+        .operands
+        .iter()
+        .filter_map(|operand| match operand {
+            AsmOperand::Register(register, size) => Some((*register, *size)),
+            _ => None,
+        })
+        .collect();
+    let first_is_register = matches!(inst.operands.first(), Some(AsmOperand::Register(_, _)));
+    let mnemonic = inst.mnemonic.as_str();
+    match mnemonic {
+        "call" => {
+            uses.extend(registers.iter().map(|(register, _)| *register));
+            uses.extend(
+                [
+                    Register::RDI,
+                    Register::RSI,
+                    Register::RDX,
+                    Register::RCX,
+                    Register::Rn(8),
+                    Register::Rn(9),
+                    Register::RAX,
+                ]
+                .iter(),
+            );
+            defs.extend(
+                [
+                    Register::RAX,
+                    Register::RCX,
+                    Register::RDX,
+                    Register::RSI,
+                    Register::RDI,
+                    Register::Rn(8),
+                    Register::Rn(9),
+                    Register::Rn(10),
+                    Register::Rn(11),
+                ]
+                .iter(),
+            );
+        }
+        "cdq" | "cqo" => {
+            uses.push(Register::RAX);
+            defs.push(Register::RDX);
+        }
+        "idiv" | "div" => {
+            uses.extend(registers.iter().map(|(register, _)| *register));
+            uses.push(Register::RAX);
+            uses.push(Register::RDX);
+            defs.push(Register::RAX);
+            defs.push(Register::RDX);
+        }
+        "mov" | "movabs" | "movzx" | "movsx" | "movsxd" | "lea" | "movd" | "movq" | "pop"
+            if first_is_register =>
+        {
+            let (dst, size) = registers[0];
+            uses.extend(registers[1..].iter().map(|(register, _)| *register));
+            // 1, 2byteへのmovは残りのビットを残す
+            if size >= 4 || mnemonic.starts_with("movzx") || mnemonic.starts_with("movsx") {
+                defs.push(dst);
+            } else {
+                uses.push(dst);
+            }
+        }
+        _ => uses.extend(registers.iter().map(|(register, _)| *register)),
+    }
+    (uses, defs)
+}
+
+// index番目の命令の後でregisterの値が読まれることがないか
+fn is_dead_after(lines: &[AsmLine], index: usize, register: Register) -> bool {
+    let labels: HashMap<&str, usize> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| match line {
+            AsmLine::Label(label) => Some((label.as_str(), index)),
+            _ => None,
+        })
+        .collect();
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut worklist: Vec<usize> = vec![index + 1];
+    // 分岐命令自体の飛び先
+    if let Some(AsmOperand::Symbol(target)) =
+        as_inst(&lines[index]).and_then(|inst| inst.operands.first())
+    {
+        match labels.get(target.as_str()) {
+            Some(position) => worklist.push(*position),
+            None => return false,
+        }
+    }
+    while let Some(start) = worklist.pop() {
+        let mut position = start;
+        loop {
+            if !visited.insert(position) {
+                break;
+            }
+            let inst = match lines.get(position) {
+                Some(AsmLine::Label(_)) => {
+                    position += 1;
+                    continue;
+                }
+                Some(AsmLine::Inst(inst)) => inst,
+                // 関数の終わりを越えることはないはずなので、分からなければ生きているとみなす
+                _ => return false,
+            };
+            let mnemonic = inst.mnemonic.as_str();
+            if mnemonic == "ret" {
+                // 戻り値と呼び出し先保存のレジスタ
+                if let Register::RAX
+                | Register::RDX
+                | Register::RBX
+                | Register::RBP
+                | Register::RSP = register
+                {
+                    return false;
+                }
+                if let Register::Rn(12..=15) | Register::XMM(0..=1) = register {
+                    return false;
+                }
+                break;
+            }
+            if mnemonic == "ud2" {
+                break;
+            }
+            let (uses, defs) = effects(inst);
+            if uses.contains(&register) {
+                return false;
+            }
+            if defs.contains(&register) {
+                break;
+            }
+            if mnemonic.starts_with('j') {
+                let target = match inst.operands.first() {
+                    Some(AsmOperand::Symbol(target)) => target,
+                    _ => return false,
+                };
+                match labels.get(target.as_str()) {
+                    Some(position) => worklist.push(*position),
+                    None => return false,
+                }
+                if mnemonic == "jmp" {
+                    break;
+                }
+            }
+            position += 1;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use crate::peephole::optimize;
+
+    #[test]
+    fn peephole_test() {
+        let lines: Vec<String> = vec![
+            "f:",
+            "    push rax",
+            "    pop rdi",
+            "    mov DWORD PTR [rbp-0x8], edi",
+            "    mov esi, DWORD PTR [rbp-0x8]",
+            "    add rsi, 0",
+            "    mov rcx, rcx",
+            "    cmp esi, 3",
+            "    setl al",
+            "    movzx eax, al",
+            "    test eax, eax",
+            "    jne .Lf.1",
+            "    jmp .Lf.2",
+            ".Lf.1:",
+            "    mov eax, 1",
+            "    jmp .Lf.return",
+            ".Lf.2:",
+            "    mov eax, 2",
+            "    jmp .Lf.return",
+            ".Lf.return:",
+            "    ret",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(
+            optimize(lines),
+            vec![
+                "f:",
+                "    mov rdi, rax",
+                "    mov DWORD PTR [rbp-0x8], edi",
+                "    mov esi, edi",
+                "    cmp esi, 3",
+                "    jge .Lf.2",
+                "    mov eax, 1",
+                "    jmp .Lf.return",
+                ".Lf.2:",
+                "    mov eax, 2",
+                ".Lf.return:",
+                "    ret",
+            ]
+        );
+    }
+}
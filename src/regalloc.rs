@@ -0,0 +1,194 @@
+use crate::frame::CALLEE_SAVED;
+use crate::ir::liveness::{inst_uses, temps, Liveness};
+use crate::ir::{Function, Inst, Temp};
+use crate::register::Register;
+
+// 一時変数の置き場所
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Location {
+    Register(Register),
+    Stack(usize), // base-offset
+}
+
+// 関数呼び出しをまたがない一時変数に使う、呼び出し側保存のレジスタ
+// rax, rdx, rdi, r11 と xmm0〜xmm7 はコード生成の作業用と引数渡しに取っておく
+const CALLER_SAVED: [Register; 5] = [
+    Register::Rn(10),
+    Register::RSI,
+    Register::RCX,
+    Register::Rn(8),
+    Register::Rn(9),
+];
+const FLOAT_REGISTERS: [Register; 8] = [
+    Register::XMM(8),
+    Register::XMM(9),
+    Register::XMM(10),
+    Register::XMM(11),
+    Register::XMM(12),
+    Register::XMM(13),
+    Register::XMM(14),
+    Register::XMM(15),
+];
+
+// 一時変数が生きている範囲 (命令の番号で数える)
+// 命令iは2iで引数を読み、2i+1で結果を書く
+struct Interval {
+    temp: Temp,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+// 線形走査で一時変数をレジスタに割り当てる
+// 入りきらない一時変数はspill_offsetから下に8byteずつ置き、使ったバイト数も返す
+pub fn allocate(function: &Function, spill_offset: usize) -> (Vec<Option<Location>>, usize) {
+    let mut intervals = intervals(function);
+    intervals.sort_by_key(|interval| (interval.start, interval.temp));
+
+    let mut locations: Vec<Option<Location>> = vec![None; function.temp_types.len()];
+    let mut spill_size: usize = 0;
+    let mut spill = |locations: &mut Vec<Option<Location>>, temp: Temp| {
+        spill_size += 8;
+        locations[temp.0] = Some(Location::Stack(spill_offset + spill_size));
+    };
+    // (終わり, 一時変数, レジスタ)
+    let mut active: Vec<(usize, Temp, Register)> = Vec::new();
+    for interval in intervals.iter() {
+        active.retain(|(end, _, _)| *end >= interval.start);
+        let candidates: Vec<Register> = if function.temp_types[interval.temp.0].is_float() {
+            // 呼び出し先が保存するSSEレジスタはない
+            if interval.crosses_call {
+                Vec::new()
+            } else {
+                FLOAT_REGISTERS.to_vec()
+            }
+        } else if interval.crosses_call {
+            CALLEE_SAVED.to_vec()
+        } else {
+            CALLER_SAVED
+                .iter()
+                .chain(CALLEE_SAVED.iter())
+                .cloned()
+                .collect()
+        };
+        let free = candidates
+            .iter()
+            .find(|register| active.iter().all(|(_, _, used)| used != *register));
+        if let Some(register) = free {
+            locations[interval.temp.0] = Some(Location::Register(*register));
+            active.push((interval.end, interval.temp, *register));
+            continue;
+        }
+        // 候補のレジスタを使っている中で最も長く生きるものを追い出す
+        let victim = active
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, register))| candidates.contains(register))
+            .max_by_key(|(_, (end, _, _))| *end)
+            .map(|(index, _)| index);
+        match victim {
+            Some(index) if active[index].0 > interval.end => {
+                let (_, temp, register) = active.remove(index);
+                spill(&mut locations, temp);
+                locations[interval.temp.0] = Some(Location::Register(register));
+                active.push((interval.end, interval.temp, register));
+            }
+            _ => spill(&mut locations, interval.temp),
+        }
+    }
+    (locations, spill_size)
+}
+
+// レジスタを使わず全ての一時変数をスタックに置く (レジスタ割り当てを行わないとき)
+pub fn spill_all(function: &Function, spill_offset: usize) -> (Vec<Option<Location>>, usize) {
+    let locations = (0..function.temp_types.len())
+        .map(|temp| Some(Location::Stack(spill_offset + 8 * (temp + 1))))
+        .collect();
+    (locations, 8 * function.temp_types.len())
+}
+
+fn intervals(function: &Function) -> Vec<Interval> {
+    let liveness = Liveness::new(function);
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; function.temp_types.len()];
+    let mut extend = |temp: Temp, position: usize| {
+        let range = ranges[temp.0].get_or_insert((position, position));
+        range.0 = std::cmp::min(range.0, position);
+        range.1 = std::cmp::max(range.1, position);
+    };
+    // 仮引数は関数の入口 (位置0) で書かれる
+    for param in function.params.iter() {
+        extend(*param, 0);
+    }
+    let mut calls: Vec<usize> = Vec::new();
+    let mut index: usize = 1;
+    for (id, block) in function.blocks.iter().enumerate() {
+        let start = 2 * index;
+        for temp in liveness.live_in[id].iter() {
+            extend(*temp, start);
+        }
+        for inst in block.insts.iter() {
+            for temp in inst_uses(inst) {
+                extend(temp, 2 * index);
+            }
+            if let Some(dst) = inst.dst() {
+                extend(dst, 2 * index + 1);
+            }
+            if let Inst::Call(_, _, _, _) = inst {
+                calls.push(2 * index);
+            }
+            index += 1;
+        }
+        for temp in temps(block.terminator.operands()) {
+            extend(temp, 2 * index);
+        }
+        for temp in liveness.live_out[id].iter() {
+            extend(*temp, 2 * index + 1);
+        }
+        index += 1;
+    }
+    ranges
+        .iter()
+        .enumerate()
+        .filter_map(|(temp, range)| {
+            range.map(|(start, end)| Interval {
+                temp: Temp(temp),
+                start,
+                end,
+                // 呼び出しの前から後まで生きている
+                crosses_call: calls.iter().any(|call| start <= *call && *call + 1 < end),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ir::lower::lower;
+    use crate::ir::ssa;
+    use crate::parser::parse::parse;
+    use crate::regalloc::{allocate, Location};
+    use crate::register::Register;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn regalloc_test() {
+        let program = parse(tokenize(
+            "int g(int x); int f(int a, int b) { int c; c = a + b; return g(c) + a; }".to_string(),
+        ));
+        let mut function = lower(&program, false).functions.remove(0);
+        ssa::construct(&mut function);
+        ssa::destruct(&mut function);
+        let (locations, spill_size) = allocate(&function, 8);
+        assert_eq!(spill_size, 0);
+        // aはgの呼び出しをまたぐので呼び出し先保存のレジスタに置く
+        assert_eq!(
+            locations[function.params[0].0],
+            Some(Location::Register(Register::RBX))
+        );
+        // bは呼び出しの前に使い終わる
+        assert_eq!(
+            locations[function.params[1].0],
+            Some(Location::Register(Register::Rn(10)))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Register {
     RAX,
     RBX,
@@ -8,9 +9,41 @@ pub enum Register {
     RBP,
     RSP,
     Rn(usize),
+    XMM(usize),
 }
 
 impl Register {
+    // 名前からレジスタとアクセスする幅を求める (SSEレジスタの幅は8とする)
+    pub fn from_name(name: &str) -> Option<(Register, usize)> {
+        let registers = [
+            Register::RAX,
+            Register::RBX,
+            Register::RCX,
+            Register::RDX,
+            Register::RSI,
+            Register::RDI,
+            Register::RBP,
+            Register::RSP,
+        ]
+        .iter()
+        .cloned()
+        .chain((8..16).map(Register::Rn))
+        .chain((0..16).map(Register::XMM));
+        for register in registers {
+            for size in [8, 4, 2, 1].iter() {
+                if let Register::XMM(_) = register {
+                    if *size != 8 {
+                        continue;
+                    }
+                }
+                if register.get_name(*size) == name {
+                    return Some((register, *size));
+                }
+            }
+        }
+        None
+    }
+
     pub fn get_name(&self, size: usize) -> String {
         match self {
             Register::RAX => match size {
@@ -89,6 +122,45 @@ impl Register {
                     _ => panic!("invalid size to access register"),
                 }
             }
+            Register::XMM(n) => {
+                if 15 < *n {
+                    panic!("register xmm{} does not exist", n);
+                }
+                // SSEレジスタは幅によらず同じ名前
+                match size {
+                    4 | 8 | 16 => format!("xmm{}", n),
+                    _ => panic!("invalid size to access register"),
+                }
+            }
         }
     }
 }
+
+// アセンブラ (intel記法) がオペランドの中でレジスタや演算子として読む名前
+pub fn is_reserved_name(name: &str) -> bool {
+    const NAMES: [&str; 42] = [
+        "ah", "bh", "ch", "dh", "cs", "ds", "es", "fs", "gs", "ss", "rip", "eip", "st", "byte",
+        "word", "dword", "fword", "qword", "tbyte", "oword", "mmword", "xmmword", "ymmword",
+        "zmmword", "offset", "flat", "short", "near", "far", "not", "and", "or", "xor", "mod",
+        "shl", "shr", "eq", "ne", "lt", "le", "gt", "ge",
+    ];
+    // 番号付きのレジスタ (名前, 個数)
+    const NUMBERED: [(&str, usize); 10] = [
+        ("xmm", 32),
+        ("ymm", 32),
+        ("zmm", 32),
+        ("mm", 8),
+        ("k", 8),
+        ("cr", 16),
+        ("dr", 16),
+        ("db", 16),
+        ("bnd", 4),
+        ("tmm", 8),
+    ];
+    let name = name.to_ascii_lowercase();
+    Register::from_name(&name).is_some()
+        || NAMES.contains(&name.as_str())
+        || NUMBERED
+            .iter()
+            .any(|(prefix, count)| (0..*count).any(|n| name == format!("{}{}", prefix, n)))
+}
@@ -1,3 +1,5 @@
+use crate::typename::{sprint_typename, Typename};
+
 pub enum Token {
     // symbols
     Plus,
@@ -14,24 +16,74 @@ pub enum Token {
     LeftCurl,
     RightCurl,
     Comma,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    Percent,
+    Question,
+    Colon,
     // literal
-    Num(i32),
+    Num(i64, Typename),      // value, literal type
+    FloatNum(f64, Typename), // value, literal type
+    Str(Vec<u8>),            // string literal (without '\0')
     // identity
     Identity(String),
     // reserved keyword
-    Signed,
-    Unsigned,
-    Short,
-    Long,
-    Void,
-    Boolean,
+    Auto,
+    Break,
+    Case,
     Char,
-    Int,
-    Return,
-    If,
+    Const,
+    Continue,
+    Default,
+    Do,
+    Double,
     Else,
+    Enum,
+    Extern,
+    Float,
     For,
+    Goto,
+    If,
+    Inline,
+    Int,
+    Long,
+    Register,
+    Restrict,
+    Return,
+    Short,
+    Signed,
+    Sizeof,
+    Static,
+    Struct,
+    Switch,
+    Typedef,
+    Union,
+    Unsigned,
+    Void,
+    Volatile,
     While,
+    Alignas,
+    Alignof,
+    Atomic,
+    Boolean,
+    Complex,
+    Generic,
+    Imaginary,
+    Noreturn,
+    StaticAssert,
+    ThreadLocal,
+    // builtin
+    BuiltinVaList,
+    BuiltinVaStart,
+    BuiltinVaArg,
+    BuiltinVaEnd,
+    BuiltinVaCopy,
+    Attribute,
     // EOF
     Eof,
 }
@@ -39,7 +91,9 @@ pub enum Token {
 pub fn sprint_token(token: &Token) -> String {
     use Token::*;
     match token {
-        Num(n) => format!("Num: {}, ", n),
+        Num(n, typename) => format!("Num: {} ({}), ", n, sprint_typename(typename)),
+        Str(bytes) => format!("Str: {:?}, ", String::from_utf8_lossy(bytes)),
+        FloatNum(x, typename) => format!("FloatNum: {} ({}), ", x, sprint_typename(typename)),
         Plus => "Mark +, ".to_string(),
         Minus => "Mark -, ".to_string(),
         Asterisk => "Mark *, ".to_string(),
@@ -54,28 +108,75 @@ pub fn sprint_token(token: &Token) -> String {
         LeftCurl => "Mark {, ".to_string(),
         RightCurl => "Mark }, ".to_string(),
         Comma => "Mark \",\", ".to_string(),
+        LeftBracket => "Mark [, ".to_string(),
+        RightBracket => "Mark ], ".to_string(),
+        Dot => "Mark ., ".to_string(),
+        Ampersand => "Mark &, ".to_string(),
+        Pipe => "Mark |, ".to_string(),
+        Caret => "Mark ^, ".to_string(),
+        Tilde => "Mark ~, ".to_string(),
+        Percent => "Mark %, ".to_string(),
+        Question => "Mark ?, ".to_string(),
+        Colon => "Mark :, ".to_string(),
         Identity(name) => format!("Identity [{}], ", name.clone()),
-        Signed => "Signed, ".to_string(),
-        Unsigned => "Unsigned, ".to_string(),
-        Short => "Short, ".to_string(),
-        Long => "Long, ".to_string(),
-        Void => "Void, ".to_string(),
-        Boolean => "Boolean, ".to_string(),
+        Auto => "Auto, ".to_string(),
+        Break => "Break, ".to_string(),
+        Case => "Case, ".to_string(),
         Char => "Char, ".to_string(),
-        Int => "Int, ".to_string(),
-        Return => "Return, ".to_string(),
-        If => "If, ".to_string(),
+        Const => "Const, ".to_string(),
+        Continue => "Continue, ".to_string(),
+        Default => "Default, ".to_string(),
+        Do => "Do, ".to_string(),
+        Double => "Double, ".to_string(),
         Else => "Else, ".to_string(),
+        Enum => "Enum, ".to_string(),
+        Extern => "Extern, ".to_string(),
+        Float => "Float, ".to_string(),
         For => "For, ".to_string(),
+        Goto => "Goto, ".to_string(),
+        If => "If, ".to_string(),
+        Inline => "Inline, ".to_string(),
+        Int => "Int, ".to_string(),
+        Long => "Long, ".to_string(),
+        Register => "Register, ".to_string(),
+        Restrict => "Restrict, ".to_string(),
+        Return => "Return, ".to_string(),
+        Short => "Short, ".to_string(),
+        Signed => "Signed, ".to_string(),
+        Sizeof => "Sizeof, ".to_string(),
+        Static => "Static, ".to_string(),
+        Struct => "Struct, ".to_string(),
+        Switch => "Switch, ".to_string(),
+        Typedef => "Typedef, ".to_string(),
+        Union => "Union, ".to_string(),
+        Unsigned => "Unsigned, ".to_string(),
+        Void => "Void, ".to_string(),
+        Volatile => "Volatile, ".to_string(),
         While => "While, ".to_string(),
+        Alignas => "Alignas, ".to_string(),
+        Alignof => "Alignof, ".to_string(),
+        Atomic => "Atomic, ".to_string(),
+        Boolean => "Boolean, ".to_string(),
+        Complex => "Complex, ".to_string(),
+        Generic => "Generic, ".to_string(),
+        Imaginary => "Imaginary, ".to_string(),
+        Noreturn => "Noreturn, ".to_string(),
+        StaticAssert => "StaticAssert, ".to_string(),
+        ThreadLocal => "ThreadLocal, ".to_string(),
+        BuiltinVaList => "BuiltinVaList, ".to_string(),
+        BuiltinVaStart => "BuiltinVaStart, ".to_string(),
+        BuiltinVaArg => "BuiltinVaArg, ".to_string(),
+        BuiltinVaEnd => "BuiltinVaEnd, ".to_string(),
+        BuiltinVaCopy => "BuiltinVaCopy, ".to_string(),
+        Attribute => "Attribute, ".to_string(),
         Eof => "EOF".to_string(),
     }
 }
 
 impl Token {
-    pub fn expect_num(&self) -> i32 {
+    pub fn expect_num(&self) -> i64 {
         match self {
-            Token::Num(n) => *n,
+            Token::Num(n, _) => *n,
             _ => panic!("Invalid Code"),
         }
     }
@@ -1,16 +1,46 @@
 use crate::token::{sprint_token, Token};
+use crate::typename::{SignedFlag, Typename};
 
 #[derive(Clone)]
 pub struct TokenIter {
     s: String,
+    headers: Headers,
+}
+
+// includeされた標準ヘッダ
+// そのヘッダのマクロをトークナイザで展開する
+#[derive(Copy, Clone, Default)]
+struct Headers {
+    stdbool: bool, // bool, true, false
+    stdarg: bool,  // va_list, va_start, va_arg, va_end, va_copy
 }
 
 pub fn tokenize(s: String) -> TokenIter {
-    TokenIter { s: s.clone() }
+    // プリプロセッサはまだないので、ディレクティブ行は読み飛ばす
+    // ただし<stdbool.h>, <stdarg.h>だけはマクロをトークナイザで展開する
+    let mut headers = Headers::default();
+    let body = s
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with('#') {
+                if line.contains("<stdbool.h>") {
+                    headers.stdbool = true;
+                }
+                if line.contains("<stdarg.h>") {
+                    headers.stdarg = true;
+                }
+                ""
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n");
+    TokenIter { s: body, headers }
 }
 
-fn tokenize_str(input: &String) -> (Option<Token>, String) {
-    let mut s = input.clone();
+fn tokenize_str(input: &str, headers: Headers) -> (Option<Token>, String) {
+    let mut s = input.to_string();
 
     // 空白文字を飛ばす
     s = s.trim_start().to_string();
@@ -35,36 +65,122 @@ fn tokenize_str(input: &String) -> (Option<Token>, String) {
         b'{' => tokenize_byte(s, Token::LeftCurl),
         b'}' => tokenize_byte(s, Token::RightCurl),
         b',' => tokenize_byte(s, Token::Comma),
-        b'0'..=b'9' => {
+        b'[' => tokenize_byte(s, Token::LeftBracket),
+        b']' => tokenize_byte(s, Token::RightBracket),
+        b'.' if s.as_bytes().get(1).is_some_and(u8::is_ascii_digit) => {
+            // .5 のような浮動小数点数リテラル
             let (digit_s, remain_s) = split_digit(s);
-            s = remain_s;
+            let (value, typename) = parse_floating_literal(&digit_s);
+            (Some(Token::FloatNum(value, typename)), remain_s)
+        }
+        b'.' => tokenize_byte(s, Token::Dot),
+        b'&' => tokenize_byte(s, Token::Ampersand),
+        b'|' => tokenize_byte(s, Token::Pipe),
+        b'^' => tokenize_byte(s, Token::Caret),
+        b'~' => tokenize_byte(s, Token::Tilde),
+        b'%' => tokenize_byte(s, Token::Percent),
+        b'?' => tokenize_byte(s, Token::Question),
+        b':' => tokenize_byte(s, Token::Colon),
+        b'"' => {
+            let (bytes, remain_s) = split_quoted(s, '"');
+            (Some(Token::Str(bytes)), remain_s)
+        }
+        b'\'' => {
+            // 文字定数の型はint
+            let (bytes, remain_s) = split_quoted(s, '\'');
+            if bytes.len() != 1 {
+                panic!("character constant must contain exactly one character");
+            }
+            let value = bytes[0] as i8 as i64;
             (
-                Some(Token::Num(i32::from_str_radix(&digit_s, 10).unwrap())),
-                s,
+                Some(Token::Num(value, Typename::Integer(SignedFlag::Signed, 4))),
+                remain_s,
             )
         }
-        b'a'..=b'z' | b'_' => {
+        b'0'..=b'9' => {
+            let (digit_s, remain_s) = split_digit(s);
+            if is_floating_literal(&digit_s) {
+                let (value, typename) = parse_floating_literal(&digit_s);
+                (Some(Token::FloatNum(value, typename)), remain_s)
+            } else {
+                let (value, typename) = parse_integer_literal(&digit_s);
+                (Some(Token::Num(value, typename)), remain_s)
+            }
+        }
+        b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
             let (ident_s, remain_s) = split_identity(s);
-            let token = match &*ident_s {
-                "return" => Token::Return,
-                "signed" => Token::Signed,
-                "unsigned" => Token::Unsigned,
-                "short" => Token::Short,
-                "long" => Token::Long,
-                "void" => Token::Void,
-                "_Bool" => Token::Void,
-                "char" => Token::Char,
-                "int" => Token::Int,
-                "if" => Token::If,
-                "else" => Token::Else,
-                "for" => Token::For,
-                "while" => Token::While,
-                _ => Token::Identity(ident_s),
+            let token = match (&*ident_s, headers.stdbool, headers.stdarg) {
+                ("bool", true, _) => Token::Boolean,
+                ("true", true, _) => Token::Num(1, Typename::Integer(SignedFlag::Signed, 4)),
+                ("false", true, _) => Token::Num(0, Typename::Integer(SignedFlag::Signed, 4)),
+                ("va_list", _, true) | ("__builtin_va_list", _, _) => Token::BuiltinVaList,
+                ("va_start", _, true) | ("__builtin_va_start", _, _) => Token::BuiltinVaStart,
+                ("va_arg", _, true) | ("__builtin_va_arg", _, _) => Token::BuiltinVaArg,
+                ("va_end", _, true) | ("__builtin_va_end", _, _) => Token::BuiltinVaEnd,
+                ("va_copy", _, true) | ("__builtin_va_copy", _, _) => Token::BuiltinVaCopy,
+                ("__attribute__", _, _) => Token::Attribute,
+                ("__inline", _, _) | ("__inline__", _, _) => Token::Inline,
+                _ => keyword_token(&ident_s).unwrap_or(Token::Identity(ident_s)),
             };
             (Some(token), remain_s)
         }
-        _ => (None, s),
+        _ => {
+            let c = s.chars().next().unwrap();
+            panic!("unexpected character '{}' in source", c);
+        }
+    };
+}
+
+// C11 6.4.1の予約語
+fn keyword_token(ident: &str) -> Option<Token> {
+    let token = match ident {
+        "auto" => Token::Auto,
+        "break" => Token::Break,
+        "case" => Token::Case,
+        "char" => Token::Char,
+        "const" => Token::Const,
+        "continue" => Token::Continue,
+        "default" => Token::Default,
+        "do" => Token::Do,
+        "double" => Token::Double,
+        "else" => Token::Else,
+        "enum" => Token::Enum,
+        "extern" => Token::Extern,
+        "float" => Token::Float,
+        "for" => Token::For,
+        "goto" => Token::Goto,
+        "if" => Token::If,
+        "inline" => Token::Inline,
+        "int" => Token::Int,
+        "long" => Token::Long,
+        "register" => Token::Register,
+        "restrict" => Token::Restrict,
+        "return" => Token::Return,
+        "short" => Token::Short,
+        "signed" => Token::Signed,
+        "sizeof" => Token::Sizeof,
+        "static" => Token::Static,
+        "struct" => Token::Struct,
+        "switch" => Token::Switch,
+        "typedef" => Token::Typedef,
+        "union" => Token::Union,
+        "unsigned" => Token::Unsigned,
+        "void" => Token::Void,
+        "volatile" => Token::Volatile,
+        "while" => Token::While,
+        "_Alignas" => Token::Alignas,
+        "_Alignof" => Token::Alignof,
+        "_Atomic" => Token::Atomic,
+        "_Bool" => Token::Boolean,
+        "_Complex" => Token::Complex,
+        "_Generic" => Token::Generic,
+        "_Imaginary" => Token::Imaginary,
+        "_Noreturn" => Token::Noreturn,
+        "_Static_assert" => Token::StaticAssert,
+        "_Thread_local" => Token::ThreadLocal,
+        _ => return None,
     };
+    Some(token)
 }
 
 pub fn tokenize_byte(input: String, token: Token) -> (Option<Token>, String) {
@@ -75,7 +191,7 @@ impl Iterator for TokenIter {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (token, remain_s) = tokenize_str(&self.s);
+        let (token, remain_s) = tokenize_str(&self.s, self.headers);
         self.s = remain_s;
         return token;
     }
@@ -86,14 +202,14 @@ impl TokenIter {
     // Peekableは拘束しないといけないので面倒
     pub fn peep(&self) -> Option<Token> {
         let s_cp = self.s.clone();
-        tokenize_str(&s_cp).0
+        tokenize_str(&s_cp, self.headers).0
     }
 
     // skipで型が変わるのが面倒なので実装
     // TODO: advance_by()がstableになったら置き換える
     pub fn ignore(&mut self, n: usize) {
         for _i in 0..n {
-            let (tokenize_result, remain_s) = tokenize_str(&self.s);
+            let (tokenize_result, remain_s) = tokenize_str(&self.s, self.headers);
             if let None = tokenize_result {
                 return;
             }
@@ -102,15 +218,212 @@ impl TokenIter {
     }
 }
 
+// 数値リテラルは接頭辞(0x, 0b)や接尾辞(u, l, ll, f)、小数点や指数部を含めて切り出す
 pub fn split_digit(s: String) -> (String, String) {
-    let first_non_num_idx = s.find(|c| !char::is_numeric(c)).unwrap_or(s.len());
-    let (former, latter) = s.split_at(first_non_num_idx);
+    let bytes = s.as_bytes();
+    // 16進数では'e'は数字なので、指数部はpで始まる
+    let exponent_chars: &[u8] = if s.starts_with("0x") || s.starts_with("0X") {
+        b"pP"
+    } else {
+        b"eE"
+    };
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let c = bytes[idx];
+        let is_exponent_sign =
+            (c == b'+' || c == b'-') && idx > 0 && exponent_chars.contains(&bytes[idx - 1]);
+        if c.is_ascii_alphanumeric() || c == b'.' || is_exponent_sign {
+            idx += 1;
+        } else {
+            break;
+        }
+    }
+    let (former, latter) = s.split_at(idx);
     (former.to_string(), latter.to_string())
 }
 
+// 整数リテラルを値と型に変換する
+// 型はC11 6.4.4.1の規則に従い、値が収まる最初の型を選ぶ
+pub fn parse_integer_literal(literal: &str) -> (i64, Typename) {
+    let lower = literal.to_ascii_lowercase();
+    let octal = lower
+        .strip_prefix('0')
+        .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+    let (radix, body) = if let Some(hex) = lower.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        (2, binary)
+    } else if let Some(octal) = octal {
+        // 0の直後が接尾辞なら (0Lなど) 10進の0
+        (8, octal)
+    } else {
+        (10, &lower[..])
+    };
+
+    // 接尾辞を分離する
+    let suffix_idx = body
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(body.len());
+    let (digits, suffix) = body.split_at(suffix_idx);
+    if digits.is_empty() {
+        panic!("invalid integer literal '{}'", literal);
+    }
+    let (unsigned, long_count) = match suffix {
+        "" => (false, 0),
+        "u" => (true, 0),
+        "l" => (false, 1),
+        "ul" | "lu" => (true, 1),
+        "ll" => (false, 2),
+        "ull" | "llu" => (true, 2),
+        _ => panic!("invalid suffix '{}' on integer literal '{}'", suffix, literal),
+    };
+    // 'lL'のような大文字小文字の混ざったllは認めない
+    if long_count == 2 && !literal.contains("ll") && !literal.contains("LL") {
+        panic!("invalid suffix on integer literal '{}'", literal);
+    }
+
+    let value = u64::from_str_radix(digits, radix)
+        .unwrap_or_else(|_| panic!("integer literal '{}' is too large", literal));
+
+    // 候補の型 (符号, サイズ) を順に試す
+    let candidates: Vec<(SignedFlag, usize)> = {
+        use SignedFlag::*;
+        let decimal = radix == 10;
+        match (unsigned, long_count, decimal) {
+            (false, 0, true) => vec![(Signed, 4), (Signed, 8)],
+            (false, 0, false) => vec![(Signed, 4), (Unsigned, 4), (Signed, 8), (Unsigned, 8)],
+            (true, 0, _) => vec![(Unsigned, 4), (Unsigned, 8)],
+            (false, _, true) => vec![(Signed, 8)],
+            (false, _, false) => vec![(Signed, 8), (Unsigned, 8)],
+            (true, _, _) => vec![(Unsigned, 8)],
+        }
+    };
+    for (flag, size) in candidates {
+        let max = match flag {
+            SignedFlag::Signed => (1u64 << (size * 8 - 1)) - 1,
+            SignedFlag::Unsigned => u64::MAX >> (64 - size * 8),
+        };
+        if value <= max {
+            return (value as i64, Typename::Integer(flag, size));
+        }
+    }
+    panic!(
+        "integer literal '{}' is too large for any integer type",
+        literal
+    );
+}
+
+// 引用符で囲まれた文字列を切り出し、エスケープシーケンスを解釈する
+fn split_quoted(s: String, quote: char) -> (Vec<u8>, String) {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c == quote {
+            return (bytes, s.split_at(idx + 1).1.to_string());
+        }
+        if c == '\n' {
+            break;
+        }
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let (_, escaped) = chars.next().expect("unterminated escape sequence");
+        let byte = match escaped {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            'e' => 0x1b,
+            '\\' | '\'' | '"' | '?' => escaped as u8,
+            'x' => {
+                let mut value: u32 = 0;
+                while let Some(digit) = chars.peek().and_then(|(_, c)| c.to_digit(16)) {
+                    value = value * 16 + digit;
+                    chars.next();
+                }
+                value as u8
+            }
+            '0'..='7' => {
+                // 8進数は3桁まで
+                let mut value = escaped.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|(_, c)| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                value as u8
+            }
+            _ => panic!("unknown escape sequence '\\{}'", escaped),
+        };
+        bytes.push(byte);
+    }
+    panic!("missing terminating {} character", quote);
+}
+
+fn is_floating_literal(literal: &str) -> bool {
+    let lower = literal.to_ascii_lowercase();
+    if lower.starts_with("0x") {
+        lower.contains('.') || lower.contains('p')
+    } else {
+        lower.contains('.') || lower.contains('e')
+    }
+}
+
+// 浮動小数点数リテラルを値と型に変換する
+// 接尾辞なしはdouble, fはfloat, lはlong double(doubleとして扱う)
+pub fn parse_floating_literal(literal: &str) -> (f64, Typename) {
+    let lower = literal.to_ascii_lowercase();
+    let (body, typename) = if let Some(body) = lower.strip_suffix('f') {
+        (body, Typename::Floating(4))
+    } else if let Some(body) = lower.strip_suffix('l') {
+        (body, Typename::Floating(8))
+    } else {
+        (&lower[..], Typename::Floating(8))
+    };
+    let value = if let Some(hex) = body.strip_prefix("0x") {
+        parse_hex_floating(hex)
+    } else {
+        body.parse::<f64>().ok()
+    };
+    match value {
+        Some(value) => (value, typename),
+        None => panic!("invalid floating literal '{}'", literal),
+    }
+}
+
+// 0x1.8p3 のような16進浮動小数点数 ("0x"は除いたもの)
+fn parse_hex_floating(hex: &str) -> Option<f64> {
+    let (mantissa, exponent) = hex.split_once('p')?;
+    let exponent = exponent.parse::<i32>().ok()?;
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let mut value = 0.0;
+    for c in int_part.chars() {
+        value = value * 16.0 + c.to_digit(16)? as f64;
+    }
+    let mut scale = 1.0 / 16.0;
+    for c in frac_part.chars() {
+        value += c.to_digit(16)? as f64 * scale;
+        scale /= 16.0;
+    }
+    Some(value * 2f64.powi(exponent))
+}
+
 pub fn split_identity(s: String) -> (String, String) {
+    // identifier = [A-Za-z_][A-Za-z0-9_]*
     let first_non_ident_idx = s
-        .find(|c: char| !char::is_alphabetic(c) && !char::is_numeric(c) && c != '_')
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
         .unwrap_or(s.len());
     let (former, latter) = s.split_at(first_non_ident_idx);
     (former.to_string(), latter.to_string())
@@ -126,7 +439,10 @@ pub fn sprint_token_iter(token_iter: TokenIter) -> String {
 
 #[cfg(test)]
 mod test {
-    use crate::tokenizer::{sprint_token_iter, tokenize, Token};
+    use crate::tokenizer::{
+        parse_floating_literal, parse_integer_literal, sprint_token_iter, tokenize, Token,
+    };
+    use crate::typename::{SignedFlag, Typename};
     #[test]
     fn tokenize_test() {
         let prog = "1 + 2 + 3 + 4".to_string();
@@ -142,4 +458,85 @@ mod test {
             panic!("It's not a left paren");
         }
     }
+
+    fn assert_literal(literal: &str, expected: i64, signed: bool, size: usize) {
+        let (value, typename) = parse_integer_literal(literal);
+        assert_eq!(value, expected, "value of {}", literal);
+        match typename {
+            Typename::Integer(flag, actual_size) => {
+                assert_eq!(
+                    matches!(flag, SignedFlag::Signed),
+                    signed,
+                    "signedness of {}",
+                    literal
+                );
+                assert_eq!(actual_size, size, "size of {}", literal);
+            }
+            _ => panic!("integer literal {} is not an integer type", literal),
+        }
+    }
+
+    #[test]
+    fn integer_literal_test() {
+        assert_literal("0", 0, true, 4);
+        assert_literal("42", 42, true, 4);
+        assert_literal("0x1F", 31, true, 4);
+        assert_literal("017", 15, true, 4);
+        assert_literal("0b101", 5, true, 4);
+        assert_literal("10u", 10, false, 4);
+        assert_literal("10l", 10, true, 8);
+        assert_literal("10ULL", 10, false, 8);
+        assert_literal("2147483648", 2147483648, true, 8);
+        assert_literal("0x80000000", 0x80000000, false, 4);
+        assert_literal("0x100000000", 0x100000000, true, 8);
+        assert_literal("0xFFFFFFFFFFFFFFFF", -1, false, 8);
+        assert_literal("0L", 0, true, 8);
+        assert_literal("0u", 0, false, 4);
+        assert_literal("0UL", 0, false, 8);
+        assert_literal("0x0", 0, true, 4);
+    }
+
+    #[test]
+    fn floating_literal_test() {
+        assert!(matches!(parse_floating_literal("1.5"), (x, Typename::Floating(8)) if x == 1.5));
+        assert!(matches!(parse_floating_literal(".25f"), (x, Typename::Floating(4)) if x == 0.25));
+        assert!(matches!(parse_floating_literal("1e-3"), (x, Typename::Floating(8)) if x == 1e-3));
+        assert!(matches!(parse_floating_literal("0x1.8p1"), (x, Typename::Floating(8)) if x == 3.0));
+
+        let mut token_iter = tokenize("1.5e+2+.5".to_string());
+        assert!(matches!(token_iter.next(), Some(Token::FloatNum(x, _)) if x == 150.0));
+        assert!(matches!(token_iter.next(), Some(Token::Plus)));
+        assert!(matches!(token_iter.next(), Some(Token::FloatNum(x, _)) if x == 0.5));
+    }
+
+    #[test]
+    fn string_literal_test() {
+        let mut token_iter = tokenize(r#""a\tb\x41\101\"" 'c' '\n'"#.to_string());
+        assert!(matches!(token_iter.next(), Some(Token::Str(bytes)) if bytes == b"a\tbAA\""));
+        assert!(matches!(token_iter.next(), Some(Token::Num(99, _))));
+        assert!(matches!(token_iter.next(), Some(Token::Num(10, _))));
+        assert!(token_iter.next().is_none());
+    }
+
+    #[test]
+    fn identifier_and_keyword_test() {
+        let mut token_iter = tokenize("MAX_SIZE _Bool static Node2".to_string());
+        assert!(matches!(token_iter.next(), Some(Token::Identity(name)) if name == "MAX_SIZE"));
+        assert!(matches!(token_iter.next(), Some(Token::Boolean)));
+        assert!(matches!(token_iter.next(), Some(Token::Static)));
+        assert!(matches!(token_iter.next(), Some(Token::Identity(name)) if name == "Node2"));
+        assert!(token_iter.next().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_character_test() {
+        tokenize("a @ b".to_string()).for_each(drop);
+    }
+
+    #[test]
+    #[should_panic]
+    fn integer_literal_overflow_test() {
+        parse_integer_literal("18446744073709551616");
+    }
 }
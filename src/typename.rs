@@ -3,6 +3,16 @@ use crate::token::Token;
 #[derive(Copy, Clone)]
 pub enum StorageClass {
     Static,
+    Extern,
+}
+
+// 関数のインライン展開の指定 (後ろほど強く、重ねたときは強い方を取る)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Inlining {
+    Default,
+    Inline, // inline
+    Always, // __attribute__((always_inline))
+    Never,  // __attribute__((noinline))
 }
 
 #[derive(Copy, Clone)]
@@ -28,6 +38,8 @@ pub enum SizeModifier {
 pub enum PrimitiveType {
     Char,
     Int,
+    Float,
+    Double,
 }
 
 #[derive(Clone)]
@@ -35,13 +47,55 @@ pub enum Typename {
     Void,
     Boolean,
     Integer(SignedFlag, usize),
+    Floating(usize),
+    Pointer(Box<Typename>),
+    Array(Box<Typename>, usize),                  // element_type, length
+    Function(Box<Typename>, Vec<Typename>, bool), // return_type, arg_type[], variadic
+    VaList,
     UserDefined(String),
+    Qualified(Box<Typename>, Mutability, bool), // typename, mutability, volatile
+}
+
+// 型修飾子を付ける (どちらもなければそのまま)
+pub fn qualify(typename: Typename, mutability: Mutability, volatile: bool) -> Typename {
+    match (mutability, volatile) {
+        (Mutability::Mutable, false) => typename,
+        _ => match typename {
+            // 修飾子は重ねずにまとめる
+            Typename::Qualified(inner, inner_mutability, inner_volatile) => {
+                let mutability = match (mutability, inner_mutability) {
+                    (Mutability::Mutable, Mutability::Mutable) => Mutability::Mutable,
+                    _ => Mutability::Const,
+                };
+                Typename::Qualified(inner, mutability, volatile || inner_volatile)
+            }
+            _ => Typename::Qualified(Box::new(typename), mutability, volatile),
+        },
+    }
+}
+
+// 最上位の型修飾子を外した型
+pub fn unqualified(typename: &Typename) -> &Typename {
+    match typename {
+        Typename::Qualified(inner, _, _) => inner,
+        _ => typename,
+    }
+}
+
+pub fn is_const(typename: &Typename) -> bool {
+    matches!(typename, Typename::Qualified(_, Mutability::Const, _))
+}
+
+// volatileな領域へのアクセスは最適化で消したりまとめたりしてはいけない
+pub fn is_volatile(typename: &Typename) -> bool {
+    matches!(typename, Typename::Qualified(_, _, true))
 }
 
 pub fn is_typename_token(token: &Token) -> bool {
     use Token::*;
     match token {
-        Identity(_) | Signed | Unsigned | Short | Long | Void | Char | Int | Boolean => true,
+        Signed | Unsigned | Short | Long | Void | Char | Int | Float | Double | Boolean | Const
+        | Volatile | BuiltinVaList => true,
         _ => false,
     }
 }
@@ -52,18 +106,51 @@ pub fn sizeof(typename: &Typename) -> usize {
         Void => 0,
         Boolean => 1,
         Integer(_, size) => *size,
+        Floating(size) => *size,
+        Pointer(_) => 8,
+        Array(element, length) => sizeof(element) * length,
+        // gp_offset, fp_offset, overflow_arg_area, reg_save_area
+        VaList => 24,
+        Qualified(typename, _, _) => sizeof(typename),
         _ => 0,
     }
 }
 
+pub fn alignof(typename: &Typename) -> usize {
+    match typename {
+        Typename::Array(element, _) => alignof(element),
+        Typename::VaList => 8,
+        Typename::Qualified(typename, _, _) => alignof(typename),
+        _ => std::cmp::max(sizeof(typename), 1),
+    }
+}
+
 pub fn parse_userdefined_type(_token_list: Vec<Token>) -> Typename {
     Typename::UserDefined("Hoge".to_string())
 }
 
 pub fn parse_typename(token_list: Vec<Token>) -> Typename {
+    let mutability = if token_list.iter().any(|token| matches!(token, Token::Const)) {
+        Mutability::Const
+    } else {
+        Mutability::Mutable
+    };
+    let volatile = token_list
+        .iter()
+        .any(|token| matches!(token, Token::Volatile));
+    qualify(parse_type_specifier(token_list), mutability, volatile)
+}
+
+// 型修飾子を除いた型指定子の並びから型を作る
+fn parse_type_specifier(token_list: Vec<Token>) -> Typename {
     let mut signed_flag: Option<SignedFlag> = None;
     let mut size_modifier: Option<SizeModifier> = None;
     let mut primitive_type: Option<PrimitiveType> = None;
+    // 修飾子を除いた型指定子の数
+    let specifier_count = token_list
+        .iter()
+        .filter(|token| !matches!(token, Token::Const | Token::Volatile))
+        .count();
     for token in token_list.iter() {
         match token {
             Token::Signed => {
@@ -104,6 +191,31 @@ pub fn parse_typename(token_list: Vec<Token>) -> Typename {
                     panic!("two or more data types in a declaration");
                 }
             }
+            Token::Float | Token::Double => {
+                let floating = if matches!(token, Token::Float) {
+                    PrimitiveType::Float
+                } else {
+                    PrimitiveType::Double
+                };
+                if primitive_type.replace(floating).is_some() {
+                    panic!("two or more data types in a declaration");
+                }
+            }
+            Token::Const | Token::Volatile => {
+                // 型修飾子はparse_typenameで付ける
+            }
+            Token::BuiltinVaList => {
+                if specifier_count > 1 {
+                    panic!("va_list type cannot be modified with another keyword");
+                }
+                return Typename::VaList;
+            }
+            Token::Boolean => {
+                if specifier_count > 1 {
+                    panic!("_Bool type cannot be modified with another keyword");
+                }
+                return Typename::Boolean;
+            }
             Token::Void => {
                 if signed_flag.is_some() {
                     panic!("void type cannot be modified with 'signed' or 'unsigned'");
@@ -111,7 +223,7 @@ pub fn parse_typename(token_list: Vec<Token>) -> Typename {
                 if size_modifier.is_some() {
                     panic!("void type cannot be modified with 'short' or 'long'");
                 }
-                if token_list.len() > 1 {
+                if specifier_count > 1 {
                     panic!("void type cannot be modified with another keyword");
                 }
                 return Typename::Void;
@@ -119,11 +231,25 @@ pub fn parse_typename(token_list: Vec<Token>) -> Typename {
             _ => {}
         };
     }
-    if signed_flag.is_some() && primitive_type.is_none() {
+    if (signed_flag.is_some() || size_modifier.is_some()) && primitive_type.is_none() {
         primitive_type.replace(PrimitiveType::Int);
     }
     match primitive_type {
         None => parse_userdefined_type(token_list),
+        Some(PrimitiveType::Float) | Some(PrimitiveType::Double) => {
+            if signed_flag.is_some() {
+                panic!("floating type cannot be modified with 'signed' or 'unsigned'");
+            }
+            match (primitive_type.unwrap(), size_modifier) {
+                (PrimitiveType::Float, None) => Typename::Floating(4),
+                (PrimitiveType::Double, None) => Typename::Floating(8),
+                // x87の80bit浮動小数点数は扱わない (doubleとして扱うと大きさも呼び出し規約も合わない)
+                (PrimitiveType::Double, Some(SizeModifier::Long)) => {
+                    panic!("'long double' is not supported")
+                }
+                _ => panic!("invalid combination of 'short'/'long' and floating type"),
+            }
+        }
         Some(int_type) => Typename::Integer(
             match signed_flag {
                 None => SignedFlag::Signed,
@@ -134,7 +260,8 @@ pub fn parse_typename(token_list: Vec<Token>) -> Typename {
                     None => 4,
                     Some(size) => match size {
                         SizeModifier::Short => 2,
-                        SizeModifier::Long => 4,
+                        // LP64: long is 64 bits wide
+                        SizeModifier::Long => 8,
                         SizeModifier::LongLong => 8,
                     },
                 },
@@ -144,6 +271,7 @@ pub fn parse_typename(token_list: Vec<Token>) -> Typename {
                     }
                     1
                 }
+                _ => unreachable!(),
             },
         ),
     }
@@ -162,14 +290,123 @@ pub fn sprint_typename(typename: &Typename) -> String {
             },
             size
         ),
+        Typename::Floating(size) => match size {
+            4 => "float".to_string(),
+            _ => "double".to_string(),
+        },
+        Typename::Pointer(typename) => format!("{}*", sprint_typename(typename)),
+        Typename::Array(element, length) => format!("{}[{}]", sprint_typename(element), length),
+        Typename::Function(return_type, arg_types, variadic) => format!(
+            "{}({}{})",
+            sprint_typename(return_type),
+            arg_types
+                .iter()
+                .map(sprint_typename)
+                .collect::<Vec<String>>()
+                .join(", "),
+            match (arg_types.is_empty(), variadic) {
+                (true, true) => "",
+                (true, false) => "void",
+                (false, true) => ", ...",
+                (false, false) => "",
+            }
+        ),
+        Typename::VaList => "va_list".to_string(),
         Typename::UserDefined(name) => name.to_string(),
+        Typename::Qualified(typename, mutability, volatile) => {
+            let qualifiers = format!(
+                "{}{}",
+                if let Mutability::Const = mutability {
+                    "const "
+                } else {
+                    ""
+                },
+                if *volatile { "volatile " } else { "" },
+            );
+            match **typename {
+                // ポインタ自体の修飾子は '*' の後ろに書く
+                Typename::Pointer(_) => {
+                    format!("{} {}", sprint_typename(typename), qualifiers.trim_end())
+                }
+                _ => format!("{}{}", qualifiers, sprint_typename(typename)),
+            }
+        }
     }
 }
 
+// 仮引数の並びのない関数の型 '()' は引数の数も型も決まっていない
+pub fn is_unprototyped(typename: &Typename) -> bool {
+    matches!(typename, Typename::Function(_, arg_types, true) if arg_types.is_empty())
+}
+
+// 既定の実引数拡張で型が変わらないか
+fn is_promoted(typename: &Typename) -> bool {
+    match typename {
+        Typename::Boolean | Typename::Floating(4) => false,
+        Typename::Integer(_, size) => *size >= 4,
+        _ => true,
+    }
+}
+
+// 適合する型か (C11 6.2.7)
+pub fn is_compatible(lhs: &Typename, rhs: &Typename) -> bool {
+    use Typename::*;
+    match (lhs, rhs) {
+        (Void, Void) | (Boolean, Boolean) | (VaList, VaList) => true,
+        (Integer(lflag, lsize), Integer(rflag, rsize)) => {
+            lsize == rsize
+                && matches!(lflag, SignedFlag::Signed) == matches!(rflag, SignedFlag::Signed)
+        }
+        (Floating(lsize), Floating(rsize)) => lsize == rsize,
+        (Pointer(lhs), Pointer(rhs)) => is_compatible(lhs, rhs),
+        // 長さを省略した配列はどの長さとも適合する
+        (Array(lhs, llength), Array(rhs, rlength)) => {
+            is_compatible(lhs, rhs) && (llength == rlength || *llength == 0 || *rlength == 0)
+        }
+        (Function(lret, largs, lvariadic), Function(rret, rargs, rvariadic)) => {
+            if !is_compatible(lret, rret) {
+                return false;
+            }
+            match (is_unprototyped(lhs), is_unprototyped(rhs)) {
+                (true, true) => true,
+                // 片方にしか仮引数の並びがなければ、そちらは可変長でなく
+                // 既定の実引数拡張で変わらない型だけを取る
+                (true, false) => !rvariadic && rargs.iter().all(is_promoted),
+                (false, true) => !lvariadic && largs.iter().all(is_promoted),
+                (false, false) => {
+                    lvariadic == rvariadic
+                        && largs.len() == rargs.len()
+                        && largs
+                            .iter()
+                            .zip(rargs.iter())
+                            .all(|(l, r)| is_compatible(l, r))
+                }
+            }
+        }
+        (Qualified(lhs, lmutability, lvolatile), Qualified(rhs, rmutability, rvolatile)) => {
+            matches!(lmutability, Mutability::Const) == matches!(rmutability, Mutability::Const)
+                && lvolatile == rvolatile
+                && is_compatible(lhs, rhs)
+        }
+        (UserDefined(lname), UserDefined(rname)) => lname == rname,
+        _ => false,
+    }
+}
+
+pub fn is_floating(typename: &Typename) -> bool {
+    matches!(unqualified(typename), Typename::Floating(_))
+}
+
 #[cfg(test)]
 mod test {
+    use crate::node::Node;
+    use crate::parser::parse::parse;
     use crate::token::Token;
-    use crate::typename::{parse_typename, sprint_typename};
+    use crate::tokenizer::tokenize;
+    use crate::typename::{
+        is_compatible, is_const, is_volatile, parse_typename, sizeof, sprint_typename, unqualified,
+        SignedFlag, Typename,
+    };
 
     #[test]
     fn parse_test() {
@@ -180,4 +417,106 @@ mod test {
         let typename = parse_typename(test_type);
         panic!("typename: {}", sprint_typename(&typename));
     }
+
+    #[test]
+    #[should_panic(expected = "'long double' is not supported")]
+    fn parse_long_double_test() {
+        parse_typename(vec![Token::Long, Token::Double]);
+    }
+
+    #[test]
+    fn parse_boolean_test() {
+        let typename = parse_typename(vec![Token::Boolean]);
+        assert!(matches!(typename, Typename::Boolean));
+        assert_eq!(sizeof(&typename), 1);
+    }
+
+    #[test]
+    fn parse_qualifier_test() {
+        let typename = parse_typename(vec![Token::Const, Token::Volatile, Token::Int]);
+        assert!(is_const(&typename));
+        assert!(is_volatile(&typename));
+        assert_eq!(sizeof(&typename), 4);
+        assert!(matches!(unqualified(&typename), Typename::Integer(_, 4)));
+        assert!(!is_const(&parse_typename(vec![Token::Int])));
+    }
+
+    #[test]
+    fn compatible_function_test() {
+        let int = Typename::Integer(SignedFlag::Signed, 4);
+        let function = |args: Vec<Typename>, variadic: bool| {
+            Typename::Function(Box::new(int.clone()), args, variadic)
+        };
+        // int f(int) と int f(int)
+        assert!(is_compatible(
+            &function(vec![int.clone()], false),
+            &function(vec![int.clone()], false)
+        ));
+        // int f() と int f(int)
+        assert!(is_compatible(
+            &function(vec![], true),
+            &function(vec![int.clone()], false)
+        ));
+        // int f() と int f(float)
+        assert!(!is_compatible(
+            &function(vec![], true),
+            &function(vec![Typename::Floating(4)], false)
+        ));
+        // int f(void) と int f(int)
+        assert!(!is_compatible(
+            &function(vec![], false),
+            &function(vec![int.clone()], false)
+        ));
+    }
+
+    #[test]
+    fn declarator_test() {
+        let program = parse(tokenize(
+            "int (*f)(int); int *a[3]; int (*(*g)(void))(int); long s = sizeof(int (*)[3]) + sizeof(int *[3]); long c = (long)((char (*)[5])0 + 1); long d = (long)((int (**)(int))8 + 1);"
+                .to_string(),
+        ));
+        let globals: Vec<(String, String, Option<String>)> = match program {
+            Node::Block(nodes) => nodes
+                .iter()
+                .filter_map(|node| match node {
+                    Node::GlobalVar(label, typename, _, initializer) => Some((
+                        label.clone(),
+                        sprint_typename(typename),
+                        initializer.as_ref().map(|value| match &**value {
+                            Node::Num(n, _) => n.to_string(),
+                            _ => panic!("not a number"),
+                        }),
+                    )),
+                    _ => None,
+                })
+                .collect(),
+            _ => panic!("not a block"),
+        };
+        let globals: Vec<(&str, &str, Option<&str>)> = globals
+            .iter()
+            .map(|(label, typename, value)| (label.as_str(), typename.as_str(), value.as_deref()))
+            .collect();
+        assert_eq!(
+            globals,
+            vec![
+                // int (*f)(int)
+                (
+                    "f",
+                    "Signed Integer (size: 4)(Signed Integer (size: 4))*",
+                    None
+                ),
+                ("a", "Signed Integer (size: 4)*[3]", None),
+                // 「intを引数にとりintを返す関数へのポインタ」を返す関数へのポインタ
+                (
+                    "g",
+                    "Signed Integer (size: 4)(Signed Integer (size: 4))*(void)*",
+                    None
+                ),
+                // sizeofとキャストの中の抽象宣言子
+                ("s", "Signed Integer (size: 8)", Some("32")),
+                ("c", "Signed Integer (size: 8)", Some("5")),
+                ("d", "Signed Integer (size: 8)", Some("16")),
+            ]
+        );
+    }
 }
@@ -0,0 +1,372 @@
+// コンパイルしたプログラムをgccでアセンブル・リンクして実行する
+use std::path::PathBuf;
+use std::process::Command;
+
+// Cのプログラムをコンパイルし、gccでアセンブル・リンクした実行ファイルを返す
+fn build(name: &str, source: &str, args: &[&str]) -> PathBuf {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg(source)
+        .args(args)
+        .output()
+        .expect("failed to run the compiler");
+    assert!(
+        output.status.success(),
+        "compile error: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let dir = std::env::temp_dir();
+    let stem = format!("rcc-{}-{}-{}", name, args.join(""), std::process::id());
+    let assembly: PathBuf = dir.join(format!("{}.S", stem));
+    let executable: PathBuf = dir.join(stem);
+    std::fs::write(&assembly, &output.stdout).unwrap();
+    let assembled = Command::new("gcc")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&assembly)
+        .output()
+        .expect("failed to run gcc");
+    std::fs::remove_file(&assembly).ok();
+    assert!(
+        assembled.status.success(),
+        "failed to assemble {}: {}",
+        name,
+        String::from_utf8_lossy(&assembled.stderr)
+    );
+    executable
+}
+
+// Cのプログラムをコンパイルして実行し、標準出力を返す
+fn run(name: &str, source: &str, args: &[&str]) -> String {
+    let executable = build(name, source, args);
+    let output = Command::new(&executable)
+        .output()
+        .expect("failed to run the program");
+    std::fs::remove_file(&executable).ok();
+    assert!(
+        output.status.success(),
+        "{} exited with {}",
+        name,
+        output.status
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// 最適化のレベルを変えても同じ出力になる
+fn run_all(name: &str, source: &str, expected: &str) {
+    for args in [
+        &["-O0"][..],
+        &["-O1"],
+        &["-O2"],
+        &["-O2", "-fomit-frame-pointer"],
+    ]
+    .iter()
+    {
+        assert_eq!(run(name, source, args), expected, "{} {:?}", name, args);
+    }
+}
+
+#[test]
+fn negative_zero_test() {
+    run_all(
+        "negative_zero",
+        "int printf(char *fmt, ...);
+double zero() { return 0.0; }
+int main() {
+    double x;
+    float y;
+    x = zero();
+    y = 0.0f;
+    printf(\"%f %f %f %f %f\\n\", -0.0, -x, 1 / -0.0, 1 / -x, 1 / -y);
+    return 0;
+}",
+        "-0.000000 -0.000000 -inf -inf -inf\n",
+    );
+}
+
+#[test]
+fn address_constant_test() {
+    run_all(
+        "address_constant",
+        "int printf(char *fmt, ...);
+int g;
+int *p = &g;
+int f(int x) { return x * 2; }
+int (*fp)(int) = f;
+int (*fq)(int) = &f;
+int main() {
+    *p = 21;
+    printf(\"%d %d %d\\n\", g, fp(g), (*fq)(1));
+    return 0;
+}",
+        "21 42 2\n",
+    );
+}
+
+// 7個目以降の引数はスタックで渡す
+#[test]
+fn many_args_test() {
+    run_all(
+        "many_args",
+        "int printf(char *fmt, ...);
+long sum8(long a, long b, long c, long d, long e, long f, long g, long h) {
+    return a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g * 7 + h * 8;
+}
+double mix(int a, double x, int b, double y, int c, int d, int e, int f, int g, int h, double z) {
+    return a + x * b + y * c + d + e + f + g * 10 + h * 100 + z;
+}
+int main() {
+    printf(\"%ld %f\\n\", sum8(1, 2, 3, 4, 5, 6, 7, 8), mix(1, 0.5, 2, 0.25, 4, 5, 6, 7, 8, 9, 1000.0));
+    printf(\"%d %d %d %d %d %d %d %d %d %f %f\\n\", 1, 2, 3, 4, 5, 6, 7, 8, 9, 1.5, 2.5);
+    return 0;
+}",
+        "204 2001.000000\n1 2 3 4 5 6 7 8 9 1.500000 2.500000\n",
+    );
+}
+
+// 式の途中の深さからの呼び出しでもrspを16バイトに揃える
+#[test]
+fn odd_depth_call_test() {
+    run_all(
+        "odd_depth_call",
+        "int printf(char *fmt, ...);
+double half(double x) { printf(\"%f\\n\", x); return x / 2; }
+int three(int a, int b, int c) { return a * 100 + b * 10 + c; }
+int main() {
+    double x;
+    x = 1 + (2 + (3 + half(4.0)));
+    printf(\"%f %d\\n\", x + half(x), three(1, three(0, 0, 2) + 1, (3 + three(0, 0, 4)) * 1));
+    return 0;
+}",
+        "4.000000\n8.000000\n12.000000 137\n",
+    );
+}
+
+// 関数ポインタの配列と引数を通した呼び出し
+#[test]
+fn function_pointer_test() {
+    run_all(
+        "function_pointer",
+        "int printf(char *fmt, ...);
+int twice(int x) { return x * 2; }
+int square(int x) { return x * x; }
+int apply(int (*f)(int), int x) { return f(x) + (*f)(1); }
+int main() {
+    int (*table[2])(int);
+    int (*fp)(int);
+    int i;
+    table[0] = twice;
+    table[1] = &square;
+    for (i = 0; i < 2; i = i + 1) {
+        fp = table[i];
+        printf(\"%d %d \", fp(5), apply(table[i], i + 3));
+    }
+    printf(\"\\n\");
+    return 0;
+}",
+        "10 8 25 17 \n",
+    );
+}
+
+// 仮引数は本体で読み書きでき、除算でrdxが壊れても3番目の引数は残る
+#[test]
+fn parameters_test() {
+    run_all(
+        "parameters",
+        "int printf(char *fmt, ...);
+int params(int a, int b, int c, int d) {
+    a = a / b;
+    c = c + (a - a / d * d);
+    return a * 1000 + b * 100 + c * 10 + d;
+}
+int main() {
+    printf(\"%d\\n\", params(17, 4, 5, 3));
+    return 0;
+}",
+        "4463\n",
+    );
+}
+
+// アセンブラがレジスタや演算子と読む名前の関数や変数
+#[test]
+fn reserved_names_test() {
+    run_all(
+        "reserved_names",
+        "int printf(char *fmt, ...);
+int gs = 5;
+static int mod = 7;
+int r8(int x) { return x + 1; }
+static int ah(int x) { return x * 2; }
+int (*table[2])(int);
+int (*fp)(int) = r8;
+static int (*sp)(int) = ah;
+int *pgs = &gs;
+int eq() { static int or = 0; or = or + 1; return or; }
+int main() {
+    table[0] = r8;
+    table[1] = ah;
+    eq();
+    printf(\"%d %d %d %d %d %d %d %d\\n\", gs, mod, r8(gs), ah(mod), table[1](3), fp(1) + sp(2), *pgs, eq());
+    return 0;
+}",
+        "5 7 6 14 6 6 5 2\n",
+    );
+}
+
+// va_argはレジスタ退避領域を使い切るとスタックの引数を読む
+#[test]
+fn variadic_test() {
+    run_all(
+        "variadic",
+        "#include <stdarg.h>
+int printf(char *fmt, ...);
+long sum(int n, ...) {
+    va_list ap;
+    long s;
+    int i;
+    va_start(ap, n);
+    s = 0;
+    for (i = 0; i < n; i = i + 1) s = s * 10 + va_arg(ap, long);
+    va_end(ap);
+    return s;
+}
+double mixed(int n, ...) {
+    va_list ap;
+    va_list aq;
+    double s;
+    int i;
+    va_start(ap, n);
+    va_copy(aq, ap);
+    s = 0;
+    for (i = 0; i < n; i = i + 1) {
+        s = s * 10 + va_arg(ap, int);
+        s = s + va_arg(ap, double);
+    }
+    s = s + va_arg(aq, int) * 1000000000.0;
+    va_end(aq);
+    va_end(ap);
+    return s;
+}
+int main() {
+    printf(\"%ld %ld\\n\", sum(3, 1L, 2L, 3L), sum(9, 1L, 2L, 3L, 4L, 5L, 6L, 7L, 8L, 9L));
+    printf(\"%f\\n\", mixed(2, 1, 0.5, 2, 0.25));
+    printf(\"%f\\n\", mixed(9, 1, 0.5, 2, 0.25, 3, 0.125, 4, 0.0625, 5, 1.0, 6, 2.0, 7, 4.0, 8, 8.0, 9, 16.0));
+    return 0;
+}",
+        "123 123456789\n1000000017.250000\n1176100535.000000\n",
+    );
+}
+
+// staticな関数と変数は外部から見えず、関数内のstatic変数は呼び出しをまたいで値を保つ
+#[test]
+fn static_binding_test() {
+    let source = "int printf(char *fmt, ...);
+static int hidden(int x) { return x + 1; }
+int shown(int x) { return hidden(x) * 2; }
+static int hidden_var = 3;
+int shown_var = 4;
+int counter() { static int count = 10; count = count + 1; return count; }
+int main() {
+    counter();
+    counter();
+    printf(\"%d %d %d\\n\", shown(hidden_var), shown_var, counter());
+    return 0;
+}";
+    for args in [&["-O0"][..], &["-O2"]].iter() {
+        assert_eq!(run("static_binding", source, args), "8 4 13\n");
+        let executable = build("static_binding", source, args);
+        let symbols = Command::new("nm")
+            .arg(&executable)
+            .output()
+            .expect("failed to run nm");
+        std::fs::remove_file(&executable).ok();
+        // 名前 => 種類 (大文字なら外部から見える)
+        let symbols: Vec<(String, char)> = String::from_utf8(symbols.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace().rev();
+                let name = fields.next()?.to_string();
+                let kind = fields.next()?.chars().next()?;
+                Some((name, kind))
+            })
+            .collect();
+        let kind = |name: &str| {
+            symbols
+                .iter()
+                .find(|(symbol, _)| symbol == name)
+                .map(|(_, kind)| *kind)
+        };
+        assert_eq!(kind("shown"), Some('T'), "{:?}", args);
+        assert_eq!(kind("shown_var"), Some('D'), "{:?}", args);
+        // 展開されて消えていてもよいが、外部から見えてはいけない
+        for name in ["hidden", "hidden_var"].iter() {
+            assert!(
+                kind(name).is_none_or(|kind| kind.is_ascii_lowercase()),
+                "{} is global with {:?}",
+                name,
+                args
+            );
+        }
+        // 関数内のstatic変数は count.<番号> という名前になる
+        assert!(
+            symbols
+                .iter()
+                .filter(|(name, _)| name.starts_with("count."))
+                .all(|(_, kind)| kind.is_ascii_lowercase()),
+            "{:?}",
+            args
+        );
+    }
+}
+
+// _Boolに入れた0以外の値は1になる
+#[test]
+fn bool_conversion_test() {
+    run_all(
+        "bool_conversion",
+        "int printf(char *fmt, ...);
+_Bool global = 256;
+_Bool convert(long x) { return x; }
+int ret() { _Bool b = 256; return b; }
+int main() {
+    _Bool b = 256;
+    _Bool c;
+    _Bool *p;
+    int n;
+    p = &c;
+    *p = 512;
+    n = -1;
+    printf(\"%d %d %d %d %d %d %d %d\\n\", ret(), b, c, global, convert(1099511627776L), convert(0), (_Bool)0.5, (_Bool)n + 1);
+    return 0;
+}",
+        "1 1 1 1 1 0 1 2\n",
+    );
+}
+
+// --debug-stack-check: callの直前でrspが16バイト境界になければud2で止まる
+#[test]
+fn stack_check_test() {
+    let source = "int printf(char *fmt, ...);
+int add7(int a, int b, int c, int d, int e, int f, int g) { return a + b + c + d + e + f + g; }
+int twice(int x) { return x * 2; }
+int main() {
+    int x;
+    x = 1 + (2 + twice(3 + add7(1, 2, 3, 4, 5, 6, twice(7))));
+    printf(\"%d %f\\n\", x, 1.5 + twice(x));
+    return 0;
+}";
+    for args in [
+        &["-O0", "--debug-stack-check"][..],
+        &["-O2", "--debug-stack-check"],
+        &["-O2", "-fomit-frame-pointer", "--debug-stack-check"],
+    ]
+    .iter()
+    {
+        assert_eq!(
+            run("stack_check", source, args),
+            "79 159.500000\n",
+            "{:?}",
+            args
+        );
+    }
+}